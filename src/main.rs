@@ -74,7 +74,7 @@ fn main() {
         eprintln!("Warning: failed to load window icon; launching without icon.");
     }
 
-    let mut cfg = Config::new()
+    let cfg = Config::new()
         .with_window(
             WindowBuilder::new()
                 .with_title("Blaze Terminal")
@@ -88,11 +88,15 @@ fn main() {
         .with_disable_context_menu(true);
 
     #[cfg(target_os = "windows")]
-    {
-        cfg = cfg.with_data_directory(windows_data_dir());
-    }
+    let cfg = cfg.with_data_directory(windows_data_dir());
+
+    // Read before launching so every window starts with the same settings.
+    let settings = terminal::config::Settings::load();
 
-    dioxus::LaunchBuilder::desktop().with_cfg(cfg).launch(App);
+    dioxus::LaunchBuilder::desktop()
+        .with_cfg(cfg)
+        .with_context(settings)
+        .launch(App);
 }
 
 #[cfg(all(test, feature = "desktop", not(target_arch = "wasm32")))]
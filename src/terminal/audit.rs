@@ -0,0 +1,287 @@
+//! Tamper-evident audit log of every command entered in Blaze.
+//!
+//! Entries are appended as JSON lines to `<user home>/.blaze_audit.jsonl`.
+//! Each entry holds the SHA-256 of the one before it (`prev`) and its own
+//! hash over its other fields, so editing, inserting or removing an entry
+//! breaks the chain. The sequence number and hash of the last entry are also
+//! kept in `.blaze_audit.head`, which is what reveals entries cut off the end
+//! of the log. `audit verify` checks both.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::terminal::utils::home_dir;
+
+/// `prev` of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What Blaze decided to do with a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Handled by Blaze itself (a builtin command or native builtin).
+    Builtin,
+    /// Run as the given binary.
+    Allowed { binary: String },
+    /// Refused; `rule` names the policy rule or other reason.
+    Denied { rule: &'static str },
+}
+
+/// One command, as written to the log.
+#[derive(Clone, Debug)]
+pub struct AuditEntry<'a> {
+    pub command: &'a str,
+    pub cwd: &'a str,
+    pub decision: &'a Decision,
+    pub exit_code: i32,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    head_path: PathBuf,
+}
+
+impl Default for AuditLog {
+    /// The log next to `.blaze_history` in the user's home directory.
+    fn default() -> Self {
+        Self::at(home_dir().join(".blaze_audit.jsonl"))
+    }
+}
+
+impl AuditLog {
+    /// A log at `path`, with its head file alongside.
+    pub fn at(path: PathBuf) -> Self {
+        let head_path = path.with_extension("head");
+        Self { path, head_path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `entry`, chained to the last entry in the log.
+    pub fn append(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        self.append_at(entry, SystemTime::now())
+    }
+
+    fn append_at(&self, entry: &AuditEntry, time: SystemTime) -> std::io::Result<()> {
+        let (seq, prev) = self.last_link()?;
+        let (decision, binary, rule) = match entry.decision {
+            Decision::Builtin => ("builtin", None, None),
+            Decision::Allowed { binary } => ("allowed", Some(binary.as_str()), None),
+            Decision::Denied { rule } => ("denied", None, Some(*rule)),
+        };
+        let mut record = json!({
+            "seq": seq + 1,
+            "time": format_utc(time),
+            "command": entry.command,
+            "cwd": entry.cwd,
+            "decision": decision,
+            "binary": binary,
+            "rule": rule,
+            "exit_code": entry.exit_code,
+            "duration_ms": entry.duration.as_millis() as u64,
+            "prev": prev,
+        });
+        let hash = entry_hash(record.as_object().expect("audit record is an object"));
+        record["hash"] = Value::String(hash.clone());
+
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", record)?;
+        file.sync_data()?;
+        self.write_head(seq + 1, &hash)
+    }
+
+    /// Sequence number and hash of the last entry, from the head file or, if
+    /// that is missing, from the log itself.
+    fn last_link(&self) -> std::io::Result<(u64, String)> {
+        if let Some(head) = self.read_head() {
+            return Ok(head);
+        }
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let last = text.lines().rev().find(|l| !l.trim().is_empty());
+        let link = last
+            .and_then(|line| serde_json::from_str::<Value>(line).ok())
+            .and_then(|v| Some((v["seq"].as_u64()?, v["hash"].as_str()?.to_string())));
+        Ok(link.unwrap_or((0, GENESIS.to_string())))
+    }
+
+    fn read_head(&self) -> Option<(u64, String)> {
+        let text = std::fs::read_to_string(&self.head_path).ok()?;
+        let (seq, hash) = text.trim().split_once(' ')?;
+        Some((seq.parse().ok()?, hash.to_string()))
+    }
+
+    /// Replace the head file in one step, so it is never half-written.
+    fn write_head(&self, seq: u64, hash: &str) -> std::io::Result<()> {
+        let tmp = self.head_path.with_extension("head.tmp");
+        std::fs::write(&tmp, format!("{} {}\n", seq, hash))?;
+        std::fs::rename(&tmp, &self.head_path)
+    }
+
+    /// Check every entry's hash and link, and that nothing was cut off the
+    /// end. Returns the number of entries.
+    pub fn verify(&self) -> Result<u64, String> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("cannot read {}: {}", self.path.display(), e)),
+        };
+
+        let mut seq = 0u64;
+        let mut prev = GENESIS.to_string();
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let Ok(Value::Object(mut record)) = serde_json::from_str::<Value>(line) else {
+                return Err(format!("line {}: not a valid entry", line_no));
+            };
+            let Some(Value::String(hash)) = record.remove("hash") else {
+                return Err(format!("line {}: entry has no hash", line_no));
+            };
+            if record.get("seq").and_then(Value::as_u64) != Some(seq + 1) {
+                return Err(format!("line {}: expected entry {} (entries removed or reordered)", line_no, seq + 1));
+            }
+            if record.get("prev").and_then(Value::as_str) != Some(prev.as_str()) {
+                return Err(format!("line {}: does not follow the previous entry", line_no));
+            }
+            if entry_hash(&record) != hash {
+                return Err(format!("line {}: entry was modified (hash mismatch)", line_no));
+            }
+            seq += 1;
+            prev = hash;
+        }
+
+        match self.read_head() {
+            Some((head_seq, head_hash)) if head_seq != seq || head_hash != prev => Err(format!(
+                "log ends at entry {} but the head file records entry {} (log truncated or replaced)",
+                seq, head_seq
+            )),
+            None if seq > 0 => Err(format!("head file {} is missing", self.head_path.display())),
+            _ => Ok(seq),
+        }
+    }
+}
+
+/// SHA-256 over the entry's fields, serialized with sorted keys.
+fn entry_hash(record: &Map<String, Value>) -> String {
+    use std::fmt::Write;
+
+    let canonical = Value::Object(record.clone()).to_string();
+    let mut hex = String::with_capacity(64);
+    for byte in Sha256::digest(canonical.as_bytes()) {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+/// `time` as an RFC 3339 UTC timestamp with milliseconds.
+pub(crate) fn format_utc(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_utc, AuditEntry, AuditLog, Decision};
+    use crate::terminal::testing::TempDir;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// A log in a directory of its own, removed along with it.
+    fn temp_log(name: &str) -> (TempDir, AuditLog) {
+        let dir = TempDir::new(&format!("audit_{}", name));
+        let log = AuditLog::at(dir.join("audit.jsonl"));
+        (dir, log)
+    }
+
+    fn append(log: &AuditLog, command: &str, decision: Decision) {
+        let entry = AuditEntry {
+            command,
+            cwd: "/work",
+            decision: &decision,
+            exit_code: 0,
+            duration: Duration::from_millis(5),
+        };
+        log.append(&entry).expect("append audit entry");
+    }
+
+    fn fill(log: &AuditLog) {
+        append(log, "ls", Decision::Builtin);
+        append(log, "curl https://x", Decision::Allowed { binary: "/usr/bin/curl".into() });
+        append(log, "python3", Decision::Denied { rule: "allowlist" });
+    }
+
+    #[test]
+    fn records_decisions_and_verifies_the_chain() {
+        let (_dir, log) = temp_log("ok");
+        assert_eq!(log.verify(), Ok(0));
+        fill(&log);
+        assert_eq!(log.verify(), Ok(3));
+
+        let text = std::fs::read_to_string(&log.path).expect("read log");
+        let second: serde_json::Value = serde_json::from_str(text.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(second["binary"], "/usr/bin/curl");
+        assert_eq!(second["decision"], "allowed");
+        let third: serde_json::Value = serde_json::from_str(text.lines().nth(2).unwrap()).unwrap();
+        assert_eq!(third["rule"], "allowlist");
+        assert_eq!(third["prev"], second["hash"]);
+    }
+
+    #[test]
+    fn detects_edits_removals_and_truncation() {
+        let (_dir, log) = temp_log("tamper");
+        fill(&log);
+        let text = std::fs::read_to_string(&log.path).expect("read log");
+        let lines: Vec<&str> = text.lines().collect();
+
+        std::fs::write(&log.path, text.replace("python3", "python2")).unwrap();
+        assert!(log.verify().unwrap_err().contains("line 3: entry was modified"));
+
+        std::fs::write(&log.path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(log.verify().unwrap_err().contains("line 2: expected entry 2"));
+
+        std::fs::write(&log.path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert!(log.verify().unwrap_err().contains("log truncated"));
+
+        std::fs::remove_file(&log.path).unwrap();
+        assert!(log.verify().unwrap_err().contains("log truncated"));
+    }
+
+    #[test]
+    fn formats_timestamps_as_utc() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_utc(time), "2024-02-29T12:34:56.789Z");
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
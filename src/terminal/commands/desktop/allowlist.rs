@@ -0,0 +1,262 @@
+//! User-editable additions to the profile's command set.
+//!
+//! The commands file lives at `<user home>/.blaze_commands` and holds one
+//! entry per line; blank lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! allow git                  # run `git` from PATH
+//! allow rg /usr/bin/rg       # only ever run this binary
+//! allow curl sha256=1f0e...  # and only if it has this SHA-256 digest
+//! deny wget                  # refuse even if the profile allows it
+//! ```
+//!
+//! A `deny` always wins. The filesystem-mutating commands stay under the
+//! control of the profile and cannot be allowed here. The `readonly` profile
+//! only runs its own programs, so it ignores every `allow` entry, pins
+//! included; `deny` entries still apply. The file is re-read whenever it
+//! changes, so edits apply to the next command.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::{Executables, Pin, Profile};
+use crate::terminal::utils::home_dir;
+
+/// Returns the path to the commands file.
+pub fn commands_file_path() -> PathBuf {
+    home_dir().join(".blaze_commands")
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandList {
+    path: PathBuf,
+    /// Modification time of the file when it was read; `None` if it is missing.
+    modified: Option<SystemTime>,
+    allowed: BTreeMap<String, Pin>,
+    /// Denied commands and the line that denies them.
+    denied: BTreeMap<String, usize>,
+    /// Problems found in the file, one per bad line.
+    pub warnings: Vec<String>,
+}
+
+impl CommandList {
+    /// Read the commands file at `path`; a missing file is an empty list.
+    pub fn load(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        let mut list = Self::parse(&path.display().to_string(), &text);
+        list.path = path;
+        list.modified = modified;
+        list
+    }
+
+    /// Whether the file changed (or appeared, or vanished) since it was read.
+    pub fn is_stale(&self) -> bool {
+        modified_time(&self.path) != self.modified
+    }
+
+    /// Re-read the file in place.
+    pub fn reload(&mut self) {
+        *self = Self::load(std::mem::take(&mut self.path));
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn parse(name: &str, text: &str) -> Self {
+        let mut list = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_no = i + 1;
+            if let Err(e) = list.add_entry(line_no, line) {
+                list.warnings.push(format!("{}:{}: {}", name, line_no, e));
+            }
+        }
+        list
+    }
+
+    fn add_entry(&mut self, line_no: usize, line: &str) -> Result<(), String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (directive, program, rest) = match fields.as_slice() {
+            [directive, program, rest @ ..] => (*directive, program.to_lowercase(), rest),
+            _ => {
+                return Err("expected 'allow <program> [<path>] [sha256=<digest>]' or 'deny <program>'".to_string())
+            }
+        };
+        match (directive, rest) {
+            ("allow", _) if matches!(program.as_str(), "mkdir" | "rm" | "del" | "mv") => {
+                Err(format!("{} is controlled by the profile and cannot be allowed here", program))
+            }
+            ("allow", rest) if rest.len() <= 2 => {
+                let pin = parse_pin(&program, rest)?;
+                self.allowed.insert(program, pin);
+                Ok(())
+            }
+            ("deny", []) => {
+                self.denied.insert(program, line_no);
+                Ok(())
+            }
+            ("allow" | "deny", _) => Err(format!("unexpected text after '{} {}'", directive, program)),
+            _ => Err(format!("unknown directive '{}' (expected allow or deny)", directive)),
+        }
+    }
+
+    /// The line that denies `program`, if any.
+    pub fn denied_at(&self, program: &str) -> Option<usize> {
+        self.denied.get(program).copied()
+    }
+
+    /// Whether the file allows `program` on top of the profile.
+    pub fn allows(&self, program: &str) -> bool {
+        self.allowed.contains_key(program) && !self.denied.contains_key(program)
+    }
+
+    /// Whether the file allows `program` on top of `profile`. It allows
+    /// nothing in the `readonly` profile.
+    pub fn allows_in(&self, profile: Profile, program: &str) -> bool {
+        !profile.is_read_only() && self.allows(program)
+    }
+
+    /// Where `program` may be run from, as set by its `allow` entry.
+    pub fn pin(&self, program: &str) -> Pin {
+        self.allowed.get(program).cloned().unwrap_or_default()
+    }
+
+    /// Every program `profile` may run once this file is applied, in the order
+    /// `help` lists them.
+    pub fn commands(&self, profile: Profile) -> Vec<String> {
+        let mut commands: Vec<String> = profile.external_commands().into_iter().map(str::to_string).collect();
+        for program in self.allowed.keys() {
+            if !commands.contains(program) && self.allows_in(profile, program) {
+                commands.push(program.clone());
+            }
+        }
+        commands.retain(|program| !self.denied.contains_key(program));
+        commands
+    }
+
+    /// Resolve every program `profile` may run to the binary it would spawn.
+    /// Pins are `allow` entries too, so `readonly` ignores them.
+    pub fn resolve_executables(&self, profile: Profile) -> Executables {
+        let commands = self.commands(profile);
+        let pin = |program: &str| if profile.is_read_only() { Pin::default() } else { self.pin(program) };
+        Executables::resolve(commands.iter().map(|program| (program.as_str(), pin(program))))
+    }
+
+    /// The `help` line listing what may run.
+    pub fn help_line(&self, profile: Profile) -> String {
+        format!(
+            "Allowed system commands ({} profile): {}.",
+            profile,
+            self.commands(profile).join(", ")
+        )
+    }
+}
+
+/// The optional path and `sha256=` digest after `allow <program>`.
+fn parse_pin(program: &str, fields: &[&str]) -> Result<Pin, String> {
+    let mut pin = Pin::default();
+    for field in fields {
+        if let Some(digest) = field.strip_prefix("sha256=") {
+            if pin.sha256.is_some() || digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("invalid SHA-256 digest for {}", program));
+            }
+            pin.sha256 = Some(digest.to_ascii_lowercase());
+        } else {
+            let path = PathBuf::from(field);
+            if pin.path.is_some() || !path.is_absolute() {
+                return Err(format!("pinned path for {} must be a single absolute path", program));
+            }
+            pin.path = Some(path);
+        }
+    }
+    Ok(pin)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommandList;
+    use crate::terminal::commands::desktop::{Pin, Profile};
+    use crate::terminal::testing::TempDir;
+    use std::path::Path;
+
+    #[test]
+    fn parses_allow_pin_and_deny_entries() {
+        let list = CommandList::parse(
+            "cmds",
+            "# team tools\nallow git\nallow RG /usr/bin/rg  # pinned\ndeny wget\n\nallow cargo\ndeny cargo\n\
+             allow curl sha256=AB00000000000000000000000000000000000000000000000000000000000000\n",
+        );
+        assert!(list.warnings.is_empty(), "{:?}", list.warnings);
+        assert!(list.allows("git"));
+        assert_eq!(list.pin("rg").path.as_deref(), Some(Path::new("/usr/bin/rg")));
+        assert_eq!(list.pin("curl").sha256, Some(format!("ab{}", "0".repeat(62))));
+        assert_eq!(list.pin("git"), Pin::default());
+        assert_eq!(list.denied_at("wget"), Some(4));
+        assert!(!list.allows("cargo"), "deny wins over allow");
+
+        let commands = list.commands(Profile::Dev);
+        assert!(commands.contains(&"git".to_string()));
+        assert!(commands.contains(&"rg".to_string()));
+        assert!(!commands.contains(&"wget".to_string()));
+        assert!(!commands.contains(&"cargo".to_string()));
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let list = CommandList::parse(
+            "cmds",
+            "permit git\nallow rg bin/rg\nallow rm\ndeny\ndeny x y\nallow git sha256=abc\n",
+        );
+        assert_eq!(list.warnings.len(), 6, "{:?}", list.warnings);
+        assert!(list.warnings[0].starts_with("cmds:1: unknown directive 'permit'"));
+        assert!(list.warnings[1].contains("single absolute path"));
+        assert!(list.warnings[2].contains("controlled by the profile"));
+        assert!(list.warnings[5].contains("invalid SHA-256"));
+        assert!(!list.allows("rg") && !list.allows("rm") && !list.allows("git"));
+    }
+
+    #[test]
+    fn readonly_ignores_allow_entries_but_not_deny_entries() {
+        let binary = std::env::current_exe().expect("test binary");
+        let list = CommandList::parse("cmds", &format!("allow git\nallow cat {}\ndeny wget\n", binary.display()));
+        assert!(list.allows_in(Profile::Dev, "git") && !list.allows_in(Profile::ReadOnly, "git"));
+        let commands = list.commands(Profile::ReadOnly);
+        assert!(!commands.contains(&"git".to_string()) && !commands.contains(&"wget".to_string()));
+        assert_eq!(commands.iter().filter(|c| *c == "cat").count(), 1);
+
+        // A pin would swap the binary behind one of the profile's own programs.
+        let executables = list.resolve_executables(Profile::ReadOnly);
+        let pinned = binary.display().to_string();
+        assert_ne!(executables.describe("cat").ok(), Some(pinned.clone()));
+        assert_eq!(list.resolve_executables(Profile::Dev).describe("cat").ok(), Some(pinned));
+    }
+
+    #[test]
+    fn reloads_when_the_file_changes() {
+        let dir = TempDir::new("commands");
+        let path = dir.join("commands");
+
+        let mut list = CommandList::load(path.clone());
+        assert!(!list.is_stale());
+        assert!(!list.allows("git"));
+
+        std::fs::write(&path, "allow git\n").expect("write commands file");
+        assert!(list.is_stale());
+        list.reload();
+        assert!(list.allows("git"));
+        assert!(!list.is_stale());
+
+        std::fs::remove_file(&path).expect("remove commands file");
+        assert!(list.is_stale());
+    }
+}
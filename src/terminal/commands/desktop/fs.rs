@@ -1,223 +1,713 @@
-#[cfg(target_os = "windows")]
+use super::trash::{Operation, Trash};
+use super::Profile;
 use crate::terminal::state::{LineType, TerminalLine};
-#[cfg(target_os = "windows")]
 use crate::terminal::utils::resolve_in_dir;
 
-#[cfg(target_os = "windows")]
-pub fn handle_windows_fs_command(
+/// Run a filesystem builtin. `input` is what a pipeline feeds it: `cat`
+/// without a file prints it and `grep` without a file searches it.
+pub fn handle_fs_command(
+    profile: Profile,
+    trash: &Trash,
     cwd: &str,
     program: &str,
     argv: &[String],
+    input: Option<&str>,
 ) -> Option<Vec<TerminalLine>> {
     match program {
         "dir" | "ls" => {
-            let target = argv.get(1).map(|s| s.as_str()).unwrap_or(".");
-            let path = resolve_in_dir(cwd, target);
-            Some(list_dir_lines(&path))
+            let args = match parse_flags(argv, LS_FLAGS) {
+                Ok(args) => args,
+                Err(option) => return Some(vec![unsupported_option_line(program, &option)]),
+            };
+            // `dir` shows every entry; `ls` hides dot files unless asked.
+            let listing = Listing {
+                all: program == "dir" || args.has('a') || args.has('A'),
+                long: args.has('l'),
+            };
+            if args.operands.is_empty() {
+                return Some(list_dir_lines(&resolve_in_dir(cwd, "."), listing));
+            }
+            // Files are listed by name, the way `ls *.rs` shows them.
+            let mut out = Vec::new();
+            for target in args.operands {
+                let path = resolve_in_dir(cwd, target);
+                if path.is_dir() {
+                    out.extend(list_dir_lines(&path, listing));
+                } else if let Ok(meta) = std::fs::symlink_metadata(&path) {
+                    out.push(TerminalLine {
+                        content: listing.entry(target, &meta),
+                        line_type: LineType::Output,
+                        spans: Vec::new(),
+                    });
+                } else {
+                    out.push(TerminalLine {
+                        content: format!("dir: {}: not found", target),
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    });
+                }
+            }
+            Some(out)
         }
         "mkdir" => {
-            if argv.len() < 2 {
+            if !profile.allows_mutations() {
+                return Some(vec![mutation_disabled_line(profile, "mkdir")]);
+            }
+            let args = match parse_flags(argv, MKDIR_FLAGS) {
+                Ok(args) => args,
+                Err(option) => return Some(vec![unsupported_option_line(program, &option)]),
+            };
+            if args.operands.is_empty() {
                 return Some(vec![TerminalLine {
-                    content: "Usage: mkdir <dir>".into(),
+                    content: "Usage: mkdir [-p] <dir>...".into(),
                     line_type: LineType::Error,
+                    spans: Vec::new(),
                 }]);
             }
-            #[cfg(all(not(feature = "safe-mode"), feature = "unsafe-fs"))]
-            {
-                let path = resolve_in_dir(cwd, &argv[1]);
-                Some(match std::fs::create_dir_all(&path) {
-                    Ok(()) => vec![TerminalLine {
-                        content: "Directory created".into(),
-                        line_type: LineType::Output,
-                    }],
-                    Err(e) => vec![TerminalLine {
-                        content: format!("mkdir: {}", e),
+            // `-p` creates missing parents and accepts directories that exist.
+            let mut out = Vec::new();
+            let mut created = 0;
+            for &dir in &args.operands {
+                let path = resolve_in_dir(cwd, dir);
+                let result = if args.has('p') { std::fs::create_dir_all(&path) } else { std::fs::create_dir(&path) };
+                match result {
+                    Ok(()) => created += 1,
+                    Err(e) => out.push(TerminalLine {
+                        content: format!("mkdir: {}: {}", dir, e),
                         line_type: LineType::Error,
-                    }],
-                })
-            }
-            #[cfg(not(all(not(feature = "safe-mode"), feature = "unsafe-fs")))]
-            {
-                Some(vec![TerminalLine {
-                    content: "mkdir is disabled in this build (enable feature 'unsafe-fs').".into(),
-                    line_type: LineType::Error,
-                }])
+                        spans: Vec::new(),
+                    }),
+                }
             }
+            let done = match created {
+                0 => return Some(out),
+                1 => "Directory created".to_string(),
+                n => format!("{} directories created", n),
+            };
+            out.insert(
+                0,
+                TerminalLine {
+                    content: done,
+                    line_type: LineType::Output,
+                    spans: Vec::new(),
+                },
+            );
+            Some(out)
         }
         "rm" | "del" => {
-            if argv.len() < 2 {
+            if !profile.allows_mutations() {
+                return Some(vec![mutation_disabled_line(profile, "rm/del")]);
+            }
+            let rm = match parse_rm_args(argv) {
+                Ok(rm) => rm,
+                Err(option) => return Some(vec![unsupported_option_line(program, &option)]),
+            };
+            if rm.targets.is_empty() && !rm.force {
                 return Some(vec![TerminalLine {
-                    content: "Usage: rm [-r] <path>".into(),
+                    content: "Usage: rm [-rf] <path>...".into(),
                     line_type: LineType::Error,
+                    spans: Vec::new(),
                 }]);
             }
-            #[cfg(all(not(feature = "safe-mode"), feature = "unsafe-fs"))]
-            {
-                let mut recursive = false;
-                let mut idx = 1;
-                if argv.get(1).map(|s| s.as_str()) == Some("-r")
-                    || argv.get(1).map(|s| s.as_str()) == Some("-R")
-                {
-                    recursive = true;
-                    idx = 2;
-                }
-                let Some(target) = argv.get(idx) else {
-                    return Some(vec![TerminalLine {
-                        content: "Usage: rm [-r] <path>".into(),
-                        line_type: LineType::Error,
-                    }]);
-                };
+            let mut out = Vec::new();
+            let mut ids = Vec::new();
+            for target in rm.targets {
                 let path = resolve_in_dir(cwd, target);
-                let result = match std::fs::metadata(&path) {
-                    Ok(m) if m.is_dir() => {
-                        if recursive {
-                            std::fs::remove_dir_all(&path)
-                        } else {
-                            Err(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                "Is a directory (use rm -r)",
-                            ))
-                        }
-                    }
-                    Ok(_) => std::fs::remove_file(&path),
+                let result = match std::fs::symlink_metadata(&path) {
+                    Ok(m) if m.is_dir() && !rm.recursive => Err(std::io::Error::other("Is a directory (use rm -r)")),
+                    Ok(_) => trash.put(&path),
+                    Err(e) if rm.force && e.kind() == std::io::ErrorKind::NotFound => continue,
                     Err(e) => Err(e),
                 };
-                Some(match result {
-                    Ok(()) => vec![TerminalLine {
-                        content: "Deleted".into(),
-                        line_type: LineType::Output,
-                    }],
-                    Err(e) => vec![TerminalLine {
-                        content: format!("rm: {}", e),
+                match result {
+                    Ok(id) => ids.push(id),
+                    Err(e) => out.push(TerminalLine {
+                        content: format!("rm: {}: {}", target, e),
                         line_type: LineType::Error,
-                    }],
-                })
+                        spans: Vec::new(),
+                    }),
+                }
             }
-            #[cfg(not(all(not(feature = "safe-mode"), feature = "unsafe-fs")))]
-            {
-                Some(vec![TerminalLine {
-                    content: "rm/del is disabled in this build (enable feature 'unsafe-fs').".into(),
-                    line_type: LineType::Error,
-                }])
+            if !ids.is_empty() {
+                out.insert(
+                    0,
+                    TerminalLine {
+                        content: "Moved to the trash ('undo' restores)".into(),
+                        line_type: LineType::Output,
+                        spans: Vec::new(),
+                    },
+                );
+                if let Err(e) = trash.record(&Operation::Remove { ids }) {
+                    out.push(undo_not_recorded_line(e));
+                }
             }
+            Some(out)
         }
         "mv" => {
-            if argv.len() < 3 {
+            if !profile.allows_mutations() {
+                return Some(vec![mutation_disabled_line(profile, "mv")]);
+            }
+            let args = match parse_flags(argv, "") {
+                Ok(args) => args,
+                Err(option) => return Some(vec![unsupported_option_line(program, &option)]),
+            };
+            let [from, to] = args.operands[..] else {
                 return Some(vec![TerminalLine {
                     content: "Usage: mv <from> <to>".into(),
                     line_type: LineType::Error,
+                    spans: Vec::new(),
                 }]);
-            }
-            #[cfg(all(not(feature = "safe-mode"), feature = "unsafe-fs"))]
-            {
-                let from = resolve_in_dir(cwd, &argv[1]);
-                let to = resolve_in_dir(cwd, &argv[2]);
-                Some(match std::fs::rename(&from, &to) {
-                    Ok(()) => vec![TerminalLine {
+            };
+            let from = resolve_in_dir(cwd, from);
+            let to = resolve_in_dir(cwd, to);
+            Some(match move_keeping_replaced(trash, &from, &to) {
+                Ok(warning) => {
+                    let mut out = vec![TerminalLine {
                         content: "Moved".into(),
                         line_type: LineType::Output,
-                    }],
-                    Err(e) => vec![TerminalLine {
-                        content: format!("mv: {}", e),
+                        spans: Vec::new(),
+                    }];
+                    out.extend(warning);
+                    out
+                }
+                Err(e) => vec![TerminalLine {
+                    content: format!("mv: {}", e),
+                    line_type: LineType::Error,
+                    spans: Vec::new(),
+                }],
+            })
+        }
+        "cat" | "type" => {
+            let args = match parse_flags(argv, CAT_FLAGS) {
+                Ok(args) => args,
+                Err(option) => return Some(vec![unsupported_option_line(program, &option)]),
+            };
+            let mut out = match input.filter(|_| args.operands.is_empty()) {
+                Some(input) => text_lines(input),
+                None if args.operands.is_empty() => {
+                    return Some(vec![TerminalLine {
+                        content: "Usage: cat [-n] <file>...".into(),
                         line_type: LineType::Error,
-                    }],
-                })
+                        spans: Vec::new(),
+                    }]);
+                }
+                None => args.operands.iter().flat_map(|file| read_file_lines(&resolve_in_dir(cwd, file))).collect(),
+            };
+            if args.has('n') {
+                number_lines(&mut out);
             }
-            #[cfg(not(all(not(feature = "safe-mode"), feature = "unsafe-fs")))]
-            {
+            Some(out)
+        }
+        "grep" => {
+            let args = match parse_flags(argv, GREP_FLAGS) {
+                Ok(args) => args,
+                Err(option) => return Some(vec![unsupported_option_line(program, &option)]),
+            };
+            let ignore_case = args.has('i');
+            let recursive = args.has('r') || args.has('R');
+            let usage = || {
                 Some(vec![TerminalLine {
-                    content: "mv is disabled in this build (enable feature 'unsafe-fs').".into(),
+                    content: "Usage: grep [-inr] <pattern> <file>...".into(),
                     line_type: LineType::Error,
+                    spans: Vec::new(),
                 }])
+            };
+            let Some((&pat, files)) = args.operands.split_first() else {
+                return usage();
+            };
+            if let Some(input) = input.filter(|_| files.is_empty() && !recursive) {
+                let matching = input.lines().filter(|line| line_matches(line, pat, ignore_case));
+                return Some(matching.flat_map(text_lines).collect());
+            }
+            // Without a path, a recursive search starts in the working
+            // directory and names files relative to it.
+            let files: &[&str] = match files {
+                [] if recursive => &[""],
+                [] => return usage(),
+                files => files,
+            };
+            let mut targets = Vec::new();
+            let mut notices = Vec::new();
+            for &file in files {
+                let path = resolve_in_dir(cwd, if file.is_empty() { "." } else { file });
+                if recursive && path.is_dir() {
+                    if let Err(limit) = files_under(&path, file, &mut targets) {
+                        notices.push(TerminalLine {
+                            content: format!("grep: searched only the first {} files", limit),
+                            line_type: LineType::System,
+                            spans: Vec::new(),
+                        });
+                        break;
+                    }
+                } else {
+                    targets.push((file.to_string(), path));
+                }
+            }
+            if let ([(_, path)], false) = (targets.as_slice(), recursive) {
+                return Some(grep_file_lines(pat, path, ignore_case));
+            }
+            // With several files each match says which file it is from, and
+            // "(no matches)" is only said once, for all of them.
+            let mut out = Vec::new();
+            for (file, path) in &targets {
+                for mut line in grep_file_lines(pat, path, ignore_case) {
+                    if line.line_type == LineType::Output {
+                        if line.content == "(no matches)" {
+                            continue;
+                        }
+                        line.content = format!("{}:{}", file, line.content);
+                    }
+                    out.push(line);
+                }
             }
+            out.extend(notices);
+            if !out.iter().any(|line| line.line_type == LineType::Output) {
+                out.push(TerminalLine {
+                    content: "(no matches)".into(),
+                    line_type: LineType::Output,
+                    spans: Vec::new(),
+                });
+            }
+            Some(out)
         }
-        "cat" | "type" => {
-            if argv.len() < 2 {
-                return Some(vec![TerminalLine {
-                    content: "Usage: cat <file>".into(),
-                    line_type: LineType::Error,
-                }]);
+        _ => None,
+    }
+}
+
+/// Whether a builtin reads its input rather than files: `cat` without a file
+/// and `grep` with only a pattern.
+pub(super) fn reads_input(program: &str, argv: &[String]) -> bool {
+    match (program, parse_flags(argv, if program == "grep" { GREP_FLAGS } else { CAT_FLAGS })) {
+        ("cat" | "type", Ok(args)) => args.operands.is_empty(),
+        ("grep", Ok(args)) => args.operands.len() == 1 && !args.has('r') && !args.has('R'),
+        _ => false,
+    }
+}
+
+/// The flags each builtin understands. Line numbers are always shown by
+/// `grep`, and sizes by `ls -l` are always human-readable, so `-n` and `-h`
+/// change nothing.
+const LS_FLAGS: &str = "aAlh1";
+const CAT_FLAGS: &str = "n";
+const GREP_FLAGS: &str = "inrR";
+const MKDIR_FLAGS: &str = "p";
+const RM_FLAGS: &str = "rRf";
+
+/// A builtin's command line split into single-letter flags and operands.
+struct Args<'a> {
+    flags: Vec<char>,
+    operands: Vec<&'a str>,
+}
+
+impl Args<'_> {
+    fn has(&self, flag: char) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+/// Split `argv` into flags and operands. Flags may be combined (`-la`) and
+/// come anywhere before `--`; a lone `-` is an operand. Any flag missing from
+/// `known`, and any long option, is returned as the error, so that a builtin
+/// never takes an option it does not understand for a path.
+fn parse_flags<'a>(argv: &'a [String], known: &str) -> Result<Args<'a>, String> {
+    let mut args = Args { flags: Vec::new(), operands: Vec::new() };
+    let mut iter = argv.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            args.operands.extend(iter.map(String::as_str));
+            break;
+        }
+        match arg.strip_prefix('-').filter(|flags| !flags.is_empty()) {
+            Some(flags) if flags.starts_with('-') => return Err(arg.clone()),
+            Some(flags) => {
+                for flag in flags.chars() {
+                    if !known.contains(flag) {
+                        return Err(format!("-{}", flag));
+                    }
+                    args.flags.push(flag);
+                }
             }
-            let path = resolve_in_dir(cwd, &argv[1]);
-            Some(read_file_lines(&path))
+            None => args.operands.push(arg),
         }
-        "grep" => {
-            if argv.len() < 3 {
-                return Some(vec![TerminalLine {
-                    content: "Usage: grep <pattern> <file>".into(),
-                    line_type: LineType::Error,
-                }]);
+    }
+    Ok(args)
+}
+
+fn unsupported_option_line(program: &str, option: &str) -> TerminalLine {
+    // The mutating commands have no system binary to fall back to.
+    let content = match program {
+        "mkdir" | "rm" | "del" | "mv" => format!("{}: unknown option {}", program, option),
+        _ => format!("{0}: {1} is not supported by the builtin; 'command {0} ...' runs the system {0}.", program, option),
+    };
+    TerminalLine {
+        content,
+        line_type: LineType::Error,
+        spans: Vec::new(),
+    }
+}
+
+/// Add the files under `dir` to `found`, sorted and named after `shown`.
+/// Symlinks are not followed. Fails with the limit once `found` holds that
+/// many files.
+fn files_under(
+    dir: &std::path::Path,
+    shown: &str,
+    found: &mut Vec<(String, std::path::PathBuf)>,
+) -> Result<(), usize> {
+    const MAX_FILES: usize = 10_000;
+    let mut pending = vec![(dir.to_path_buf(), shown.to_string())];
+    while let Some((dir, shown)) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+        entries.sort_by_key(|e| e.file_name());
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let shown = if shown.is_empty() { name } else { format!("{}/{}", shown.trim_end_matches('/'), name) };
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => subdirs.push((entry.path(), shown)),
+                Ok(kind) if kind.is_file() => {
+                    if found.len() == MAX_FILES {
+                        return Err(MAX_FILES);
+                    }
+                    found.push((shown, entry.path()));
+                }
+                _ => {}
             }
-            let pat = &argv[1];
-            let path = resolve_in_dir(cwd, &argv[2]);
-            Some(grep_file_lines(pat, &path))
         }
+        // Pushed in reverse so they are searched in order.
+        pending.extend(subdirs.into_iter().rev());
+    }
+    Ok(())
+}
+
+/// Rename `from` to `to` so that `undo` can revert it, moving a file it
+/// replaces to the trash first. Returns a warning line if the move could not
+/// be recorded for `undo`.
+fn move_keeping_replaced(
+    trash: &Trash,
+    from: &std::path::Path,
+    to: &std::path::Path,
+) -> std::io::Result<Option<TerminalLine>> {
+    std::fs::symlink_metadata(from)?;
+    let replaced = match std::fs::symlink_metadata(to) {
+        Ok(m) if !m.is_dir() => Some(trash.put(to)?),
         _ => None,
+    };
+    if let Err(e) = std::fs::rename(from, to) {
+        if let Some(id) = replaced {
+            let _ = trash.restore(id);
+        }
+        return Err(e);
+    }
+    let op = Operation::Move {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        replaced,
+    };
+    Ok(trash.record(&op).err().map(undo_not_recorded_line))
+}
+
+fn undo_not_recorded_line(e: std::io::Error) -> TerminalLine {
+    TerminalLine {
+        content: format!("warning: 'undo' will not revert this: {}", e),
+        line_type: LineType::System,
+        spans: Vec::new(),
+    }
+}
+
+/// What an `rm`/`del` command line asks for.
+struct RmArgs<'a> {
+    /// `-r`/`-R`: directories may be removed.
+    recursive: bool,
+    /// `-f`: paths that do not exist are skipped without an error.
+    force: bool,
+    targets: Vec<&'a str>,
+}
+
+/// Parse `rm`'s flags, which may be combined (`-rf`). Fails with the first
+/// unknown option.
+fn parse_rm_args(argv: &[String]) -> Result<RmArgs<'_>, String> {
+    let args = parse_flags(argv, RM_FLAGS)?;
+    Ok(RmArgs {
+        recursive: args.has('r') || args.has('R'),
+        force: args.has('f'),
+        targets: args.operands,
+    })
+}
+
+/// What a destructive `rm`/`del` or `mv` is about to affect, as the lines
+/// of a prompt the user must answer with `y`. `None` if the command may run
+/// without asking: it is not destructive, or the builtin will refuse it.
+///
+/// Recursive deletes of a directory, deletes of more than `confirm_over`
+/// files and `mv` onto an existing path all ask first.
+pub fn confirmation_prompt(
+    profile: Profile,
+    cwd: &str,
+    program: &str,
+    argv: &[String],
+    confirm_over: usize,
+) -> Option<Vec<TerminalLine>> {
+    if !profile.allows_mutations() {
+        return None;
+    }
+    let (heading, targets) = match program {
+        "rm" | "del" => {
+            let rm = parse_rm_args(argv).ok()?;
+            let mut affected = Vec::new();
+            for target in rm.targets {
+                let path = resolve_in_dir(cwd, target);
+                let Ok(meta) = std::fs::symlink_metadata(&path) else {
+                    continue;
+                };
+                if meta.is_dir() && !rm.recursive {
+                    continue;
+                }
+                affected.push((target, tally(&path)));
+            }
+            let files: u64 = affected.iter().map(|(_, t)| t.files).sum();
+            let has_dir = affected.iter().any(|(_, t)| t.dirs > 0);
+            if !has_dir && files <= confirm_over as u64 {
+                return None;
+            }
+            (format!("{} will move to the trash:", program), affected)
+        }
+        "mv" => {
+            let args = parse_flags(argv, "").ok()?;
+            let [from, to] = args.operands[..] else {
+                return None;
+            };
+            let path = resolve_in_dir(cwd, to);
+            std::fs::symlink_metadata(&path).ok().filter(|m| !m.is_dir())?;
+            let heading = format!("mv will replace {} with {} (the old one goes to the trash):", to, from);
+            (heading, vec![(to, tally(&path))])
+        }
+        _ => return None,
+    };
+
+    let mut total = Tally::default();
+    let mut prompt = vec![heading];
+    for (target, tally) in &targets {
+        prompt.push(format!("  {}  ({})", target, tally));
+        total.add(tally);
+    }
+    if targets.len() > 1 {
+        prompt.push(format!("Total: {}", total));
+    }
+    prompt.push("Type y to proceed; anything else cancels.".to_string());
+    Some(
+        prompt
+            .into_iter()
+            .map(|content| TerminalLine {
+                content,
+                line_type: LineType::System,
+                spans: Vec::new(),
+            })
+            .collect(),
+    )
+}
+
+/// Files, directories and bytes under a path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Tally {
+    files: u64,
+    dirs: u64,
+    bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, other: &Tally) {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.bytes += other.bytes;
+    }
+}
+
+impl std::fmt::Display for Tally {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.dirs {
+            0 => {}
+            1 => f.write_str("1 directory, ")?,
+            n => write!(f, "{} directories, ", n)?,
+        }
+        match self.files {
+            1 => f.write_str("1 file, ")?,
+            n => write!(f, "{} files, ", n)?,
+        }
+        f.write_str(&format_size(self.bytes))
+    }
+}
+
+/// Count what deleting `path` removes. Symlinks count as files and are not
+/// followed; unreadable directories count as empty.
+fn tally(path: &std::path::Path) -> Tally {
+    let mut tally = Tally::default();
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let Ok(meta) = std::fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if meta.is_dir() {
+            tally.dirs += 1;
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                pending.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+            }
+        } else {
+            tally.files += 1;
+            tally.bytes += meta.len();
+        }
+    }
+    tally
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn mutation_disabled_line(profile: Profile, program: &str) -> TerminalLine {
+    TerminalLine {
+        content: format!("{} is disabled in the '{}' profile (start Blaze with --profile full).", program, profile),
+        line_type: LineType::Error,
+        spans: Vec::new(),
+    }
+}
+
+fn text_lines(text: &str) -> Vec<TerminalLine> {
+    text.lines()
+        .map(|l| TerminalLine {
+            content: l.to_string(),
+            line_type: LineType::Output,
+            spans: Vec::new(),
+        })
+        .collect()
+}
+
+/// How `ls`/`dir` shows entries.
+#[derive(Clone, Copy, Debug, Default)]
+struct Listing {
+    /// Include names that start with a dot.
+    all: bool,
+    /// Show the kind and size of each entry before its name.
+    long: bool,
+}
+
+impl Listing {
+    fn entry(&self, name: &str, meta: &std::fs::Metadata) -> String {
+        if !self.long {
+            return name.to_string();
+        }
+        let (kind, size) = if meta.is_dir() {
+            ('d', "-".to_string())
+        } else if meta.file_type().is_symlink() {
+            ('l', "-".to_string())
+        } else {
+            ('-', format_size(meta.len()))
+        };
+        format!("{} {:>10}  {}", kind, size, name)
     }
 }
 
-#[cfg(target_os = "windows")]
-fn list_dir_lines(path: &std::path::Path) -> Vec<TerminalLine> {
+fn list_dir_lines(path: &std::path::Path, listing: Listing) -> Vec<TerminalLine> {
     let mut out = Vec::new();
     match std::fs::read_dir(path) {
         Ok(entries) => {
             out.push(TerminalLine {
                 content: format!(" Directory of {}", path.display()),
                 line_type: LineType::Output,
+                spans: Vec::new(),
             });
             out.push(TerminalLine {
                 content: String::new(),
                 line_type: LineType::Output,
+                spans: Vec::new(),
             });
 
-            let mut names: Vec<String> = entries
+            let mut names: Vec<(String, std::path::PathBuf)> = entries
                 .filter_map(|e| e.ok())
-                .filter_map(|e| e.file_name().into_string().ok())
+                .filter_map(|e| Some((e.file_name().into_string().ok()?, e.path())))
+                .filter(|(name, _)| listing.all || !name.starts_with('.'))
                 .collect();
             names.sort();
 
-            for name in names {
+            for (name, path) in names {
+                let Ok(meta) = std::fs::symlink_metadata(&path) else {
+                    continue;
+                };
                 out.push(TerminalLine {
-                    content: name,
+                    content: listing.entry(&name, &meta),
                     line_type: LineType::Output,
+                    spans: Vec::new(),
                 });
             }
         }
         Err(e) => out.push(TerminalLine {
             content: format!("dir: {}", e),
             line_type: LineType::Error,
+            spans: Vec::new(),
         }),
     }
     out
 }
 
-#[cfg(target_os = "windows")]
 fn read_file_lines(path: &std::path::Path) -> Vec<TerminalLine> {
     const MAX_BYTES: usize = 512 * 1024;
     match std::fs::read(path) {
         Ok(bytes) => {
-            let bytes = if bytes.len() > MAX_BYTES {
+            let truncated = bytes.len() > MAX_BYTES;
+            let bytes = if truncated {
                 &bytes[..MAX_BYTES]
             } else {
                 &bytes
             };
-            let text = String::from_utf8_lossy(bytes);
-            text.lines()
-                .map(|l| TerminalLine {
-                    content: l.to_string(),
-                    line_type: LineType::Output,
-                })
-                .collect()
+            let mut out = text_lines(&String::from_utf8_lossy(bytes));
+            if truncated {
+                out.push(TerminalLine {
+                    content: "cat: file too large, showing only first 512 KiB".to_string(),
+                    line_type: LineType::System,
+                    spans: Vec::new(),
+                });
+            }
+            out
         }
         Err(e) => vec![TerminalLine {
             content: format!("cat: {}", e),
             line_type: LineType::Error,
+            spans: Vec::new(),
         }],
     }
 }
 
-#[cfg(target_os = "windows")]
-fn grep_file_lines(pattern: &str, path: &std::path::Path) -> Vec<TerminalLine> {
+/// Whether `line` contains `pattern`, ignoring case with `ignore_case`.
+fn line_matches(line: &str, pattern: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        line.to_lowercase().contains(&pattern.to_lowercase())
+    } else {
+        line.contains(pattern)
+    }
+}
+
+/// Prefix each output line with its number, the way `cat -n` does.
+fn number_lines(lines: &mut [TerminalLine]) {
+    let output = lines.iter_mut().filter(|l| l.line_type == LineType::Output);
+    for (idx, line) in output.enumerate() {
+        line.content = format!("{:>6}\t{}", idx + 1, line.content);
+    }
+}
+
+fn grep_file_lines(pattern: &str, path: &std::path::Path, ignore_case: bool) -> Vec<TerminalLine> {
     const MAX_BYTES: usize = 1024 * 1024;
     let bytes = match std::fs::read(path) {
         Ok(b) => b,
@@ -225,6 +715,7 @@ fn grep_file_lines(pattern: &str, path: &std::path::Path) -> Vec<TerminalLine> {
             return vec![TerminalLine {
                 content: format!("grep: {}", e),
                 line_type: LineType::Error,
+                spans: Vec::new(),
             }]
         }
     };
@@ -237,12 +728,27 @@ fn grep_file_lines(pattern: &str, path: &std::path::Path) -> Vec<TerminalLine> {
     };
     let content = String::from_utf8_lossy(bytes);
 
+    // Lines of a binary file are not worth showing, only that it matches.
+    if bytes.iter().take(8192).any(|&b| b == 0) {
+        let content = if content.lines().any(|line| line_matches(line, pattern, ignore_case)) {
+            "binary file matches"
+        } else {
+            "(no matches)"
+        };
+        return vec![TerminalLine {
+            content: content.to_string(),
+            line_type: LineType::Output,
+            spans: Vec::new(),
+        }];
+    }
+
     let mut out = Vec::new();
     for (idx, line) in content.lines().enumerate() {
-        if line.contains(pattern) {
+        if line_matches(line, pattern, ignore_case) {
             out.push(TerminalLine {
                 content: format!("{}:{}", idx + 1, line),
                 line_type: LineType::Output,
+                spans: Vec::new(),
             });
         }
     }
@@ -251,6 +757,7 @@ fn grep_file_lines(pattern: &str, path: &std::path::Path) -> Vec<TerminalLine> {
         out.push(TerminalLine {
             content: "grep: file too large, searched only first 1 MiB".to_string(),
             line_type: LineType::System,
+            spans: Vec::new(),
         });
     }
 
@@ -258,16 +765,22 @@ fn grep_file_lines(pattern: &str, path: &std::path::Path) -> Vec<TerminalLine> {
         out.push(TerminalLine {
             content: "(no matches)".to_string(),
             line_type: LineType::Output,
+            spans: Vec::new(),
         });
     }
 
     out
 }
 
-#[cfg(all(test, target_os = "windows"))]
+#[cfg(test)]
 mod tests {
-    use super::grep_file_lines;
+    use super::{
+        confirmation_prompt, grep_file_lines, handle_fs_command, list_dir_lines, read_file_lines, reads_input, Listing,
+        Trash,
+    };
+    use crate::terminal::commands::desktop::Profile;
     use crate::terminal::state::LineType;
+    use crate::terminal::testing::TempDir;
 
     fn temp_test_file_path(name: &str) -> std::path::PathBuf {
         let mut path = std::env::temp_dir();
@@ -284,7 +797,7 @@ mod tests {
         let path = temp_test_file_path("grep_match");
         std::fs::write(&path, "alpha\nbeta\nalpha again\n").expect("write test file");
 
-        let lines = grep_file_lines("alpha", &path);
+        let lines = grep_file_lines("alpha", &path, false);
 
         assert!(lines.iter().any(|l| l.content == "1:alpha" && l.line_type == LineType::Output));
         assert!(lines.iter().any(|l| l.content == "3:alpha again" && l.line_type == LineType::Output));
@@ -297,7 +810,7 @@ mod tests {
         let path = temp_test_file_path("grep_no_match");
         std::fs::write(&path, "alpha\nbeta\n").expect("write test file");
 
-        let lines = grep_file_lines("zzz", &path);
+        let lines = grep_file_lines("zzz", &path, false);
 
         assert!(lines.iter().any(|l| l.content == "(no matches)" && l.line_type == LineType::Output));
 
@@ -310,7 +823,7 @@ mod tests {
         let content = "A".repeat((1024 * 1024) + 64);
         std::fs::write(&path, content).expect("write test file");
 
-        let lines = grep_file_lines("nomatch", &path);
+        let lines = grep_file_lines("nomatch", &path, false);
 
         assert!(
             lines.iter().any(|l| l.content.contains("searched only first 1 MiB") && l.line_type == LineType::System),
@@ -319,4 +832,175 @@ mod tests {
 
         let _ = std::fs::remove_file(path);
     }
+
+    #[test]
+    fn cat_truncates_large_files_and_reports_notice() {
+        let dir = TempDir::new("fs_cat_truncate");
+        let path = dir.join("big.txt");
+        std::fs::write(&path, "B".repeat((512 * 1024) + 64)).expect("write test file");
+
+        let lines = read_file_lines(&path);
+
+        assert!(
+            lines.iter().any(|l| l.content.contains("showing only first 512 KiB") && l.line_type == LineType::System),
+            "expected truncation notice in output"
+        );
+    }
+
+    #[test]
+    fn ls_lists_entries_sorted_and_reports_errors() {
+        let dir = TempDir::new("fs_ls_dir");
+        std::fs::write(dir.join("b.txt"), "").expect("write test file");
+        std::fs::write(dir.join("a.txt"), "").expect("write test file");
+
+        let lines = list_dir_lines(&dir, Listing::default());
+        let names: Vec<&str> = lines.iter().skip(2).map(|l| l.content.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt"]);
+
+        let lines = list_dir_lines(&dir.join("missing"), Listing::default());
+        assert!(lines.iter().any(|l| l.content.starts_with("dir: ") && l.line_type == LineType::Error));
+    }
+
+    #[test]
+    fn ls_cat_and_grep_take_several_paths() {
+        let dir = TempDir::new("fs_many_paths");
+        std::fs::create_dir_all(dir.join("sub")).expect("create test dir");
+        std::fs::write(dir.join("a.rs"), "fn a() {}\n// TODO a\n").expect("write test file");
+        std::fs::write(dir.join("b.rs"), "fn b() {}\n").expect("write test file");
+        let cwd = dir.display().to_string();
+        let trash = Trash::at(dir.join(".trash"));
+        let run = |line: &str| {
+            let argv: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            handle_fs_command(Profile::Full, &trash, &cwd, &argv[0], &argv, None).expect("builtin")
+        };
+        let contents = |line: &str| run(line).into_iter().map(|l| l.content).collect::<Vec<_>>();
+
+        assert_eq!(contents("ls a.rs b.rs"), vec!["a.rs", "b.rs"]);
+        assert!(contents("ls a.rs sub").iter().any(|l| l.starts_with(" Directory of")));
+        assert_eq!(contents("cat a.rs b.rs"), vec!["fn a() {}", "// TODO a", "fn b() {}"]);
+        assert_eq!(contents("grep TODO a.rs b.rs"), vec!["a.rs:2:// TODO a"]);
+        assert_eq!(contents("grep zzz a.rs b.rs"), vec!["(no matches)"]);
+        assert_eq!(contents("grep TODO a.rs"), vec!["2:// TODO a"]);
+    }
+
+    #[test]
+    fn ls_cat_and_grep_understand_common_flags() {
+        let dir = TempDir::new("fs_flags");
+        std::fs::create_dir_all(dir.join("sub/deep")).expect("create test dir");
+        std::fs::write(dir.join("a.rs"), "fn a() {}\n// todo a\n").expect("write test file");
+        std::fs::write(dir.join(".hidden"), "").expect("write test file");
+        std::fs::write(dir.join("sub/deep/c.rs"), "// TODO c\n").expect("write test file");
+        let cwd = dir.display().to_string();
+        let trash = Trash::at(dir.join(".trash"));
+        let contents = |line: &str| {
+            let argv: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            let lines = handle_fs_command(Profile::Full, &trash, &cwd, &argv[0], &argv, None).expect("builtin");
+            lines.into_iter().map(|l| l.content).collect::<Vec<_>>()
+        };
+
+        assert_eq!(contents("ls")[2..], ["a.rs", "sub"]);
+        assert_eq!(contents("ls -a")[2..], [".hidden", "a.rs", "sub"]);
+        assert_eq!(contents("ls -A1 sub")[2..], ["deep"]);
+        assert_eq!(contents("ls -la")[2..], ["-    0 bytes  .hidden", "-   20 bytes  a.rs", "d          -  sub"]);
+        assert_eq!(contents("cat -n a.rs"), ["     1\tfn a() {}", "     2\t// todo a"]);
+        assert_eq!(contents("grep -i TODO a.rs"), ["2:// todo a"]);
+        assert_eq!(contents("grep -rn TODO"), ["sub/deep/c.rs:1:// TODO c"]);
+        assert_eq!(contents("grep -ri todo ."), ["./a.rs:2:// todo a", "./sub/deep/c.rs:1:// TODO c"]);
+        assert_eq!(contents("grep -R TODO sub a.rs"), ["sub/deep/c.rs:1:// TODO c"]);
+        assert_eq!(contents("grep -- -a a.rs"), ["(no matches)"]);
+
+        // In a pipeline, flags do not count as files to read.
+        let argv = |line: &str| line.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        assert!(reads_input("cat", &argv("cat -n")) && reads_input("grep", &argv("grep -i x")));
+        assert!(!reads_input("grep", &argv("grep -r x")) && !reads_input("cat", &argv("cat a.rs")));
+    }
+
+    #[test]
+    fn unsupported_flags_are_refused_not_taken_for_paths() {
+        let dir = TempDir::new("fs_bad_flags");
+        let cwd = dir.display().to_string();
+        let trash = Trash::at(dir.join(".trash"));
+        let run = |line: &str| {
+            let argv: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            handle_fs_command(Profile::Full, &trash, &cwd, &argv[0], &argv, None).expect("builtin")
+        };
+
+        for (line, option) in [("ls -lt", "-t"), ("cat -A x", "-A"), ("grep -rw x .", "-w"), ("grep --color x .", "--color")] {
+            let lines = run(line);
+            assert_eq!(lines.len(), 1, "{line}");
+            assert_eq!(lines[0].line_type, LineType::Error);
+            let program = line.split(' ').next().unwrap_or_default();
+            assert_eq!(
+                lines[0].content,
+                format!("{0}: {1} is not supported by the builtin; 'command {0} ...' runs the system {0}.", program, option)
+            );
+        }
+    }
+
+    #[test]
+    fn mkdir_rm_and_mv_parse_flags_and_take_several_operands() {
+        let dir = TempDir::new("fs_mutating_flags");
+        let cwd = dir.display().to_string();
+        let trash = Trash::at(dir.join(".trash"));
+        let contents = |line: &str| {
+            let argv: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            let lines = handle_fs_command(Profile::Full, &trash, &cwd, &argv[0], &argv, None).expect("builtin");
+            lines.into_iter().map(|l| l.content).collect::<Vec<_>>()
+        };
+
+        assert_eq!(contents("mkdir -p a/b c"), ["2 directories created"]);
+        assert!(dir.join("a/b").is_dir() && dir.join("c").is_dir() && !dir.join("-p").exists());
+        assert_eq!(contents("mkdir -p a/b"), ["Directory created"]);
+        let lines = contents("mkdir d/e");
+        assert!(lines.len() == 1 && lines[0].starts_with("mkdir: d/e: "), "{lines:?}");
+        assert_eq!(contents("mkdir -- -p"), ["Directory created"]);
+        assert!(dir.join("-p").is_dir());
+
+        std::fs::write(dir.join("x.txt"), "").expect("write test file");
+        assert_eq!(contents("rm -rf a missing x.txt"), ["Moved to the trash ('undo' restores)"]);
+        assert!(!dir.join("a").exists() && !dir.join("x.txt").exists());
+        assert_eq!(contents("rm -f missing"), Vec::<String>::new());
+        let lines = contents("rm -R c missing");
+        assert_eq!(lines[0], "Moved to the trash ('undo' restores)");
+        assert!(lines[1].starts_with("rm: missing: "), "{lines:?}");
+        assert_eq!(contents("rm -i -p"), ["rm: unknown option -i"]);
+        assert_eq!(contents("mkdir -m 700 d"), ["mkdir: unknown option -m"]);
+        assert_eq!(contents("mv -f -p q"), ["mv: unknown option -f"]);
+        assert!(dir.join("-p").is_dir());
+        assert_eq!(contents("mv -- -p q"), ["Moved"]);
+        assert!(dir.join("q").is_dir());
+    }
+
+    #[test]
+    fn destructive_operations_ask_first_with_counts_and_size() {
+        let dir = TempDir::new("fs_confirm_dir");
+        std::fs::create_dir_all(dir.join("sub")).expect("create test dir");
+        std::fs::write(dir.join("a.txt"), "x".repeat(2048)).expect("write test file");
+        std::fs::write(dir.join("sub/b.txt"), "y").expect("write test file");
+        let cwd = dir.display().to_string();
+        let argv = |line: &str| line.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        let prompt = |line: &str| {
+            let argv = argv(line);
+            confirmation_prompt(Profile::Full, &cwd, &argv[0], &argv, 1)
+                .map(|lines| lines.into_iter().map(|l| l.content).collect::<Vec<_>>())
+        };
+
+        let lines = prompt("rm -r sub").expect("recursive delete asks");
+        assert_eq!(lines[1], "  sub  (1 directory, 1 file, 1 bytes)");
+        assert_eq!(prompt("rm a.txt"), None);
+        assert_eq!(prompt("rm sub"), None, "rm refuses the directory itself");
+        assert!(prompt("rm -rf sub").is_some(), "combined flags are recursive too");
+        assert_eq!(prompt("rm -x sub"), None, "rm refuses unknown options");
+        let lines = prompt("rm a.txt sub/b.txt").expect("more than one file asks");
+        assert_eq!(lines.last().map(String::as_str), Some("Type y to proceed; anything else cancels."));
+        assert!(lines.contains(&"Total: 2 files, 2.0 KiB".to_string()), "{lines:?}");
+        let lines = prompt("mv sub/b.txt a.txt").expect("overwriting mv asks");
+        assert!(lines[0].starts_with("mv will replace a.txt with sub/b.txt"), "{lines:?}");
+        assert_eq!(prompt("mv a.txt c.txt"), None);
+        assert_eq!(
+            confirmation_prompt(Profile::Dev, &cwd, "rm", &argv("rm -r sub"), 1),
+            None,
+            "the profile refuses it anyway"
+        );
+    }
 }
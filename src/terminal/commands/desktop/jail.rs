@@ -0,0 +1,101 @@
+//! The optional workspace jail.
+//!
+//! With a `workspace` root configured, `cd`, tab completion and the
+//! filesystem builtins only reach paths inside it. Paths are checked after
+//! resolving symlinks, so a link inside the workspace that points out of it
+//! is refused like the path it points to.
+
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Jail {
+    /// Canonical path of the workspace root.
+    root: PathBuf,
+}
+
+impl Jail {
+    /// Confine to `root`, which must be an existing directory.
+    pub fn new(root: &Path) -> Result<Self, String> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("workspace {}: {}", root.display(), e))?;
+        if !root.is_dir() {
+            return Err(format!("workspace {} is not a directory", root.display()));
+        }
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether `path` (absolute) resolves to somewhere inside the workspace.
+    pub fn contains(&self, path: &Path) -> bool {
+        normalize(path).starts_with(&self.root)
+    }
+
+    /// `path` resolved, or the error to show when it leaves the workspace.
+    /// `shown` is the path as the user typed it.
+    pub fn check(&self, path: &Path, shown: &str) -> Result<PathBuf, String> {
+        let resolved = normalize(path);
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(format!("{}: outside the workspace ({})", shown, self.root.display()))
+        }
+    }
+}
+
+/// `path` with symlinks resolved. Where it does not exist, its deepest
+/// existing ancestor is resolved and the rest folded lexically.
+pub(super) fn normalize(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    let mut out = loop {
+        if let Ok(real) = existing.canonicalize() {
+            break real;
+        }
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(last)) => {
+                rest.push(last);
+                existing = parent;
+            }
+            _ => break PathBuf::new(),
+        }
+    };
+    for component in rest.into_iter().rev() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::Jail;
+    use crate::terminal::testing::TempDir;
+
+    #[test]
+    fn refuses_paths_and_symlinks_that_leave_the_root() {
+        let base = TempDir::new("jail");
+        let root = base.join("root");
+        std::fs::create_dir_all(root.join("sub")).expect("create test dir");
+        std::fs::create_dir_all(base.join("outside")).expect("create test dir");
+        std::os::unix::fs::symlink(base.join("outside"), root.join("escape")).expect("symlink");
+        std::os::unix::fs::symlink(root.join("sub"), root.join("inner")).expect("symlink");
+
+        let jail = Jail::new(&root).expect("jail");
+        assert!(jail.contains(&root.join("sub/../sub/new.txt")));
+        assert!(jail.contains(&root.join("inner")));
+        assert!(!jail.contains(&root.join("..")));
+        assert!(!jail.contains(&root.join("escape/file")));
+        let err = jail.check(&root.join("escape"), "escape").unwrap_err();
+        assert!(err.starts_with("escape: outside the workspace"), "{err}");
+        assert!(Jail::new(&root.join("missing")).is_err());
+    }
+}
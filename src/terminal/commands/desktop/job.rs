@@ -0,0 +1,227 @@
+//! The foreground job: the external command currently running in the terminal.
+
+#[cfg(not(target_os = "windows"))]
+use super::pty::PtyControl;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    /// Suspended with Ctrl+Z; resumed by the `fg` builtin.
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    Stopped,
+}
+
+#[derive(Clone)]
+pub struct ForegroundJob {
+    /// The command line as typed, shown next to the running indicator.
+    pub command: String,
+    pub state: JobState,
+    /// Process group of the job. The child is started as a session leader, so
+    /// this is also its pid. `None` until the process has been spawned.
+    #[cfg(not(target_os = "windows"))]
+    pub pgid: Option<u32>,
+    #[cfg(not(target_os = "windows"))]
+    pub pty: Option<PtyControl>,
+    /// Whether the job's terminal echoes input; off while a program reads a
+    /// password.
+    #[cfg(not(target_os = "windows"))]
+    pub echo: bool,
+    /// Set once the job shows a full-screen program or the user types into
+    /// or suspends it; interactive jobs are exempt from the timeout.
+    #[cfg(not(target_os = "windows"))]
+    pub interactive: bool,
+}
+
+impl ForegroundJob {
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            state: JobState::Running,
+            #[cfg(not(target_os = "windows"))]
+            pgid: None,
+            #[cfg(not(target_os = "windows"))]
+            pty: None,
+            #[cfg(not(target_os = "windows"))]
+            echo: true,
+            #[cfg(not(target_os = "windows"))]
+            interactive: false,
+        }
+    }
+
+    /// Type `bytes` into the job's terminal.
+    #[cfg(not(target_os = "windows"))]
+    pub fn write_input(&self, bytes: &[u8]) -> std::io::Result<()> {
+        match &self.pty {
+            Some(pty) => pty.write(bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Deliver a job-control signal to every process in the job's group.
+    ///
+    /// Suspending may block briefly (see [`ForegroundJob::suspend`]), so call
+    /// this off the UI thread.
+    #[cfg(not(target_os = "windows"))]
+    pub fn send(&self, signal: JobSignal) -> std::io::Result<()> {
+        match signal {
+            JobSignal::Suspend => self.suspend(),
+            JobSignal::Interrupt | JobSignal::Quit => {
+                self.killpg(signal.raw())?;
+                // A stopped job only acts on the signal once it runs again.
+                if self.state == JobState::Stopped {
+                    self.killpg(libc::SIGCONT)?;
+                }
+                Ok(())
+            }
+            JobSignal::Continue => self.killpg(libc::SIGCONT),
+        }
+    }
+
+    /// Kill the whole job, e.g. after it exceeded an execution limit.
+    #[cfg(not(target_os = "windows"))]
+    pub fn kill(&self) -> std::io::Result<()> {
+        self.killpg(libc::SIGKILL)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn killpg(&self, signal: libc::c_int) -> std::io::Result<()> {
+        let Some(pgid) = self.pgid else {
+            return Ok(());
+        };
+        // SAFETY: killpg has no memory-safety preconditions.
+        if unsafe { libc::killpg(pgid as libc::pid_t, signal) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Suspend the job as Ctrl+Z would.
+    ///
+    /// The job runs in its own session, so its process group has no parent in
+    /// that session (it is "orphaned") and the kernel discards SIGTSTP for
+    /// programs that do not handle it. Programs that do (e.g. `vim`) get a
+    /// moment to restore the terminal and stop themselves; anything still
+    /// running afterwards is stopped with SIGSTOP.
+    #[cfg(not(target_os = "windows"))]
+    fn suspend(&self) -> std::io::Result<()> {
+        const GRACE: std::time::Duration = std::time::Duration::from_millis(100);
+
+        self.killpg(libc::SIGTSTP)?;
+        std::thread::sleep(GRACE);
+        if !self.is_stopped() {
+            self.killpg(libc::SIGSTOP)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn is_stopped(&self) -> bool {
+        self.pgid.is_some_and(is_stopped)
+    }
+}
+
+/// Whether the child process `pid` is stopped.
+#[cfg(not(target_os = "windows"))]
+pub(super) fn is_stopped(pid: u32) -> bool {
+    // SAFETY: `info` is a zeroed, writable siginfo_t. WNOWAIT leaves the
+    // child's state in place for the task that eventually reaps it.
+    unsafe {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let rc = libc::waitid(
+            libc::P_PID,
+            pid as libc::id_t,
+            &mut info,
+            libc::WSTOPPED | libc::WNOHANG | libc::WNOWAIT,
+        );
+        rc == 0 && info.si_signo != 0
+    }
+}
+
+/// Job-control signals the terminal can send to the foreground job.
+#[cfg(not(target_os = "windows"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobSignal {
+    /// Ctrl+C (SIGINT).
+    Interrupt,
+    /// Ctrl+\ (SIGQUIT).
+    Quit,
+    /// Ctrl+Z (SIGTSTP).
+    Suspend,
+    /// `fg` (SIGCONT).
+    Continue,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl JobSignal {
+    /// The signal a terminal sends for Ctrl+`key`.
+    pub fn for_ctrl_key(key: &str) -> Option<Self> {
+        match key {
+            "c" | "C" => Some(Self::Interrupt),
+            "\\" => Some(Self::Quit),
+            "z" | "Z" => Some(Self::Suspend),
+            _ => None,
+        }
+    }
+
+    fn raw(self) -> libc::c_int {
+        match self {
+            Self::Interrupt => libc::SIGINT,
+            Self::Quit => libc::SIGQUIT,
+            Self::Suspend => libc::SIGTSTP,
+            Self::Continue => libc::SIGCONT,
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::{ForegroundJob, JobSignal, JobState};
+    use crate::terminal::commands::desktop::pty::{spawn_in_pty, PtyChild};
+    use crate::terminal::commands::desktop::limits::ExecLimits;
+    use crate::terminal::commands::desktop::sandbox::Sandbox;
+    use crate::terminal::state::TermSize;
+    use std::os::unix::process::ExitStatusExt;
+
+    /// Closing the master hangs up the terminal, so the caller keeps the
+    /// returned `PtyChild` alive for the duration of the test.
+    fn spawn_sleeper() -> (ForegroundJob, PtyChild) {
+        let args = vec!["5".to_string()];
+        let pty = spawn_in_pty("sleep", &args, ".", TermSize::default(), &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let mut job = ForegroundJob::new("sleep 5".into());
+        job.pgid = Some(pty.child.id());
+        (job, pty)
+    }
+
+    #[test]
+    fn maps_control_keys_to_signals() {
+        assert_eq!(JobSignal::for_ctrl_key("c"), Some(JobSignal::Interrupt));
+        assert_eq!(JobSignal::for_ctrl_key("\\"), Some(JobSignal::Quit));
+        assert_eq!(JobSignal::for_ctrl_key("z"), Some(JobSignal::Suspend));
+        assert_eq!(JobSignal::for_ctrl_key("x"), None);
+    }
+
+    #[test]
+    fn interrupt_reaches_the_process_group() {
+        let (job, mut pty) = spawn_sleeper();
+        job.send(JobSignal::Interrupt).expect("send SIGINT");
+        let status = pty.child.wait().expect("wait");
+        assert_eq!(status.signal(), Some(libc::SIGINT));
+    }
+
+    #[test]
+    fn suspend_stops_programs_without_a_handler_and_interrupt_ends_them() {
+        let (mut job, mut pty) = spawn_sleeper();
+        job.send(JobSignal::Suspend).expect("suspend");
+        // SIGSTOP is delivered asynchronously.
+        let stopped = (0..50).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            job.is_stopped()
+        });
+        assert!(stopped, "job did not stop");
+
+        job.state = JobState::Stopped;
+        job.send(JobSignal::Interrupt).expect("interrupt");
+        let status = pty.child.wait().expect("wait");
+        assert_eq!(status.signal(), Some(libc::SIGINT));
+    }
+}
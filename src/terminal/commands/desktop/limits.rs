@@ -0,0 +1,414 @@
+//! Execution limits applied to every external command, on every platform.
+//!
+//! A command that runs longer than the timeout, or writes more than the byte or
+//! line cap, is stopped (on Windows, where output is collected once the command
+//! exits, the output is truncated) and a notice explains which limit it hit.
+//! On Unix, commands also run under resource limits (CPU time, address space,
+//! file size, process count) set with `setrlimit` before they start; the
+//! kernel enforces those, and a command it kills for one gets the same kind of
+//! notice. The limits can be inspected and changed at runtime with the
+//! `limits` builtin.
+
+use std::time::Duration;
+
+#[cfg(not(target_os = "windows"))]
+use crate::terminal::state::ExitOutcome;
+use crate::terminal::state::{LineType, TerminalLine};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecLimits {
+    /// Wall-clock limit per command; `None` disables it. Interactive jobs
+    /// (full-screen programs, or ones the user has typed into) are exempt,
+    /// since they are expected to wait for the user and can be stopped with
+    /// Ctrl+C.
+    pub timeout: Option<Duration>,
+    /// Bytes of output a command may produce; `None` disables the cap.
+    /// Full-screen programs are exempt, as their redraws do not accumulate.
+    pub max_output_bytes: Option<usize>,
+    /// Lines of output a command may add to the scrollback; `None` disables
+    /// the cap.
+    pub max_output_lines: Option<usize>,
+    /// CPU time per process (`RLIMIT_CPU`). Ignored on Windows.
+    pub cpu: Option<Duration>,
+    /// Address space per process in bytes (`RLIMIT_AS`). Ignored on Windows.
+    pub memory: Option<usize>,
+    /// Largest file a process may write, in bytes (`RLIMIT_FSIZE`). Ignored
+    /// on Windows.
+    pub file_size: Option<usize>,
+    /// Processes the user may have at once (`RLIMIT_NPROC`); forks beyond it
+    /// fail. The kernel counts all of the user's processes and threads, not
+    /// just the command's. Ignored on Windows.
+    pub processes: Option<u64>,
+}
+
+impl Default for ExecLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(15)),
+            max_output_bytes: Some(1024 * 1024),
+            max_output_lines: Some(10_000),
+            cpu: Some(Duration::from_secs(300)),
+            memory: Some(8 * 1024 * 1024 * 1024),
+            file_size: Some(1024 * 1024 * 1024),
+            processes: Some(4096),
+        }
+    }
+}
+
+/// The limit a command ran into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Timeout(Duration),
+    Bytes(usize),
+    Lines(usize),
+    Cpu(Duration),
+    /// A crash by `signal`, which running out of address space may explain
+    /// but ordinary bugs can cause too.
+    Memory { limit: usize, signal: i32 },
+    FileSize(usize),
+}
+
+impl LimitExceeded {
+    /// The error line shown below the command's output.
+    pub fn notice(self, program: &str) -> TerminalLine {
+        let content = match self {
+            Self::Timeout(limit) => format!("{}: timed out after {}", program, format_secs(limit)),
+            Self::Bytes(limit) => format!(
+                "{}: output truncated at {} (limit reached)",
+                program,
+                format_bytes(limit)
+            ),
+            Self::Lines(limit) => format!(
+                "{}: output truncated at {} lines (limit reached)",
+                program, limit
+            ),
+            Self::Cpu(limit) => format!("{}: killed: CPU time limit exceeded ({})", program, format_secs(limit)),
+            Self::Memory { limit, signal } => format!(
+                "{}: killed by {} (memory limit {} may have been hit)",
+                program,
+                signal_name(signal),
+                format_bytes(limit)
+            ),
+            Self::FileSize(limit) => format!("{}: killed: file size limit exceeded ({})", program, format_bytes(limit)),
+        };
+        TerminalLine {
+            content,
+            line_type: LineType::Error,
+            spans: Vec::new(),
+        }
+    }
+}
+
+impl ExecLimits {
+    /// One line per limit, as printed by `limits`.
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            format!(
+                "timeout  {}",
+                self.timeout.map_or("off".to_string(), format_secs)
+            ),
+            format!(
+                "bytes    {}",
+                self.max_output_bytes
+                    .map_or("off".to_string(), format_bytes)
+            ),
+            format!(
+                "lines    {}",
+                self.max_output_lines
+                    .map_or("off".to_string(), |n| n.to_string())
+            ),
+            #[cfg(not(target_os = "windows"))]
+            format!("cpu      {}", self.cpu.map_or("off".to_string(), format_secs)),
+            #[cfg(not(target_os = "windows"))]
+            format!("memory   {}", self.memory.map_or("off".to_string(), format_bytes)),
+            #[cfg(not(target_os = "windows"))]
+            format!("filesize {}", self.file_size.map_or("off".to_string(), format_bytes)),
+            #[cfg(not(target_os = "windows"))]
+            format!("procs    {}", self.processes.map_or("off".to_string(), |n| n.to_string())),
+        ]
+    }
+
+    /// Change one limit: `timeout <seconds>`, `bytes <n>[K|M|G]` or
+    /// `lines <n>`, and on Unix `cpu <seconds>`, `memory <n>[K|M|G]`,
+    /// `filesize <n>[K|M|G]` or `procs <n>`. `off` disables a limit.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let off = value.eq_ignore_ascii_case("off");
+        match key {
+            "timeout" => {
+                self.timeout = if off { None } else { Some(parse_secs(value)?) };
+            }
+            "bytes" => {
+                self.max_output_bytes = if off { None } else { Some(parse_bytes(value)?) };
+            }
+            "lines" => {
+                self.max_output_lines = if off {
+                    None
+                } else {
+                    Some(
+                        parse_positive(value)
+                            .ok_or_else(|| format!("invalid line count: {}", value))?,
+                    )
+                };
+            }
+            #[cfg(not(target_os = "windows"))]
+            "cpu" => {
+                self.cpu = if off { None } else { Some(parse_secs(value)?) };
+            }
+            #[cfg(not(target_os = "windows"))]
+            "memory" => {
+                self.memory = if off { None } else { Some(parse_bytes(value)?) };
+            }
+            #[cfg(not(target_os = "windows"))]
+            "filesize" => {
+                self.file_size = if off { None } else { Some(parse_bytes(value)?) };
+            }
+            #[cfg(not(target_os = "windows"))]
+            "procs" => {
+                self.processes = if off {
+                    None
+                } else {
+                    Some(
+                        parse_positive(value)
+                            .ok_or_else(|| format!("invalid process count: {}", value))? as u64,
+                    )
+                };
+            }
+            _ => {
+                #[cfg(target_os = "windows")]
+                let expected = "timeout, bytes or lines";
+                #[cfg(not(target_os = "windows"))]
+                let expected = "timeout, bytes, lines, cpu, memory, filesize or procs";
+                return Err(format!("unknown limit '{}' (expected {})", key, expected));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A resource limit for `setrlimit`.
+#[cfg(not(target_os = "windows"))]
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Rlimit {
+    Cpu(libc::rlim_t),
+    AddressSpace(libc::rlim_t),
+    FileSize(libc::rlim_t),
+    Processes(libc::rlim_t),
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Rlimit {
+    /// Lower the calling process's limit. Runs in the child between fork and
+    /// exec: it only calls `getrlimit`/`setrlimit`, and never raises a hard
+    /// limit the user already has.
+    pub(super) fn apply(self) -> std::io::Result<()> {
+        let (resource, soft, hard) = match self {
+            // SIGXCPU at the soft limit, SIGKILL a second later.
+            Self::Cpu(secs) => (libc::RLIMIT_CPU, secs, secs.saturating_add(1)),
+            Self::AddressSpace(bytes) => (libc::RLIMIT_AS, bytes, bytes),
+            Self::FileSize(bytes) => (libc::RLIMIT_FSIZE, bytes, bytes),
+            Self::Processes(count) => (libc::RLIMIT_NPROC, count, count),
+        };
+        let mut current = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `current` is a valid, writable rlimit.
+        if unsafe { libc::getrlimit(resource, &mut current) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let hard = current.rlim_max.min(hard);
+        let limit = libc::rlimit {
+            rlim_cur: hard.min(soft),
+            rlim_max: hard,
+        };
+        // SAFETY: `limit` is a valid rlimit.
+        if unsafe { libc::setrlimit(resource, &limit) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+impl ExecLimits {
+    /// The resource limits to set on a spawned command.
+    pub(super) fn rlimits(&self) -> Vec<Rlimit> {
+        let mut rlimits = Vec::new();
+        if let Some(cpu) = self.cpu {
+            // Whole seconds, rounded up.
+            let secs = cpu.as_secs() + u64::from(cpu.subsec_nanos() > 0);
+            rlimits.push(Rlimit::Cpu(secs as libc::rlim_t));
+        }
+        if let Some(bytes) = self.memory {
+            rlimits.push(Rlimit::AddressSpace(bytes as libc::rlim_t));
+        }
+        if let Some(bytes) = self.file_size {
+            rlimits.push(Rlimit::FileSize(bytes as libc::rlim_t));
+        }
+        if let Some(count) = self.processes {
+            rlimits.push(Rlimit::Processes(count as libc::rlim_t));
+        }
+        rlimits
+    }
+
+    /// The resource limit that may have ended a command with `outcome`.
+    /// SIGXCPU and SIGXFSZ are only sent for their limits. Running out of
+    /// address space makes allocations fail, which programs usually answer by
+    /// aborting or crashing, but so do plain bugs, so those crashes only
+    /// mention the memory limit as a possible cause.
+    pub(super) fn exceeded_by(&self, outcome: ExitOutcome) -> Option<LimitExceeded> {
+        match outcome {
+            ExitOutcome::Signal(libc::SIGXCPU) => self.cpu.map(LimitExceeded::Cpu),
+            ExitOutcome::Signal(libc::SIGXFSZ) => self.file_size.map(LimitExceeded::FileSize),
+            ExitOutcome::Signal(signal @ (libc::SIGABRT | libc::SIGSEGV | libc::SIGBUS)) => {
+                self.memory.map(|limit| LimitExceeded::Memory { limit, signal })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn parse_positive(value: &str) -> Option<usize> {
+    value.parse::<usize>().ok().filter(|n| *n > 0)
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs = value.strip_suffix('s').unwrap_or(value);
+    match secs.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n)),
+        _ => Err(format!("invalid timeout: {}", value)),
+    }
+}
+
+fn parse_bytes(value: &str) -> Result<usize, String> {
+    let upper = value.to_ascii_uppercase();
+    let digits = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (digits, unit) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1024),
+        Some('M') => (&digits[..digits.len() - 1], 1024 * 1024),
+        Some('G') => (&digits[..digits.len() - 1], 1024 * 1024 * 1024),
+        _ => (digits, 1),
+    };
+    parse_positive(digits)
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("invalid byte count: {}", value))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn signal_name(signal: i32) -> &'static str {
+    match signal {
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGBUS => "SIGBUS",
+        _ => "a signal",
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn signal_name(_signal: i32) -> &'static str {
+    "a signal"
+}
+
+fn format_secs(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs.fract() == 0.0 {
+        format!("{}s", secs)
+    } else {
+        format!("{:.2}s", secs)
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const KIB: usize = 1024;
+    const MIB: usize = 1024 * KIB;
+    if bytes >= MIB && bytes.is_multiple_of(MIB) {
+        format!("{} MiB", bytes / MIB)
+    } else if bytes >= KIB && bytes.is_multiple_of(KIB) {
+        format!("{} KiB", bytes / KIB)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExecLimits, LimitExceeded};
+    use std::time::Duration;
+
+    #[test]
+    fn set_parses_values_and_off() {
+        let mut limits = ExecLimits::default();
+        limits.set("timeout", "2.5").expect("timeout");
+        limits.set("bytes", "512K").expect("bytes");
+        limits.set("lines", "off").expect("lines");
+
+        assert_eq!(limits.timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(limits.max_output_bytes, Some(512 * 1024));
+        assert_eq!(limits.max_output_lines, None);
+    }
+
+    #[test]
+    fn set_rejects_invalid_input() {
+        let mut limits = ExecLimits::default();
+        assert!(limits.set("timeout", "soon").is_err());
+        assert!(limits.set("bytes", "0").is_err());
+        assert!(limits.set("swap", "1M").is_err());
+        assert!(limits.set("procs", "-1").is_err());
+        assert_eq!(limits, ExecLimits::default());
+    }
+
+    #[test]
+    fn notices_name_the_limit() {
+        let notice = LimitExceeded::Bytes(1024 * 1024).notice("cat");
+        assert_eq!(
+            notice.content,
+            "cat: output truncated at 1 MiB (limit reached)"
+        );
+        let notice = LimitExceeded::Timeout(Duration::from_secs(15)).notice("curl");
+        assert_eq!(notice.content, "curl: timed out after 15s");
+        #[cfg(not(target_os = "windows"))]
+        {
+            let notice = LimitExceeded::Memory { limit: 512 * 1024 * 1024, signal: libc::SIGSEGV }.notice("node");
+            assert_eq!(notice.content, "node: killed by SIGSEGV (memory limit 512 MiB may have been hit)");
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn resource_limits_are_set_and_reported() {
+        use crate::terminal::commands::desktop::pty::spawn_in_pty;
+        use crate::terminal::commands::desktop::sandbox::Sandbox;
+        use crate::terminal::state::{ExitOutcome, TermSize};
+        use crate::terminal::testing::TempDir;
+        use std::io::Read;
+
+        let mut limits = ExecLimits::default();
+        limits.set("cpu", "1").expect("cpu");
+        limits.set("filesize", "4K").expect("filesize");
+        limits.set("procs", "off").expect("procs");
+        assert_eq!(limits.processes, None);
+
+        let run = |script: &str| {
+            let args = vec!["-c".to_string(), script.to_string()];
+            let mut pty = spawn_in_pty("sh", &args, ".", TermSize::default(), &limits, &Sandbox::default())
+                .expect("spawn in pty");
+            let mut sink = Vec::new();
+            let _ = pty.master.read_to_end(&mut sink);
+            ExitOutcome::from(pty.child.wait().expect("wait"))
+        };
+
+        let outcome = run("ulimit -t; ulimit -f; while :; do :; done");
+        assert_eq!(limits.exceeded_by(outcome), Some(LimitExceeded::Cpu(Duration::from_secs(1))));
+
+        let dir = TempDir::new("fsize");
+        let outcome = run(&format!("exec head -c 65536 /dev/zero > {}", dir.join("zeros").display()));
+        assert_eq!(limits.exceeded_by(outcome), Some(LimitExceeded::FileSize(4096)));
+
+        assert_eq!(
+            limits.exceeded_by(ExitOutcome::Signal(libc::SIGSEGV)),
+            Some(LimitExceeded::Memory { limit: 8 << 30, signal: libc::SIGSEGV })
+        );
+        assert_eq!(limits.exceeded_by(ExitOutcome::Signal(libc::SIGINT)), None);
+        assert_eq!(limits.exceeded_by(ExitOutcome::Code(1)), None);
+    }
+}
@@ -1,77 +1,166 @@
+mod allowlist;
 mod fs;
+mod jail;
+mod job;
+mod limits;
+mod pipeline;
+mod policy;
 mod process;
+mod profile;
+#[cfg(not(target_os = "windows"))]
+mod pty;
+mod resolve;
+#[cfg(not(target_os = "windows"))]
+mod sandbox;
+mod trash;
+
+use crate::terminal::state::{ExitOutcome, LineType, TerminalLine};
+#[cfg(target_os = "windows")]
+use crate::terminal::state::TermSize;
 
-use crate::terminal::state::{LineType, TerminalLine};
+pub fn is_allowed_external(profile: Profile, command: &str) -> bool {
+    profile.allows(command)
+}
 
-pub fn is_allowed_external(command: &str) -> bool {
-    match command {
-        "ls" | "dir" | "echo" | "vim" | "whoami" | "cat" | "grep" | "curl" | "wget" | "ip" => true,
+/// Whether `program` is handled inside Blaze rather than by spawning a binary
+/// (unless `command <program>` asks for the binary).
+fn runs_in_process(program: &str) -> bool {
+    match program {
+        "ls" | "dir" | "cat" | "type" | "grep" | "mkdir" | "rm" | "del" | "mv" => true,
         #[cfg(target_os = "windows")]
-        "type" | "ipconfig" => true,
-        #[cfg(not(target_os = "windows"))]
-        "ifconfig" => true,
-        #[cfg(all(not(feature = "safe-mode"), feature = "unsafe-fs"))]
-        "mkdir" | "rm" | "del" | "mv" => true,
+        "echo" | "whoami" | "vim" => true,
         _ => false,
     }
 }
 
+/// What `which` prints for an allowed `program`: the binary it runs, or that
+/// Blaze handles it itself.
+pub fn which(
+    profile: Profile,
+    commands: &CommandList,
+    executables: &Executables,
+    program: &str,
+) -> Result<String, String> {
+    if commands.denied_at(program).is_some() || !(profile.allows(program) || commands.allows_in(profile, program)) {
+        return Err(format!("{}: not allowed", program));
+    }
+    if runs_in_process(program) {
+        return Ok(match executables.describe(program) {
+            Ok(path) => format!("{}: Blaze builtin ('command {}' runs {})", program, program, path),
+            Err(_) => format!("{}: Blaze builtin", program),
+        });
+    }
+    executables.describe(program).map(|path| format!("{}: {}", program, path))
+}
+
+/// Run `program` as one of the native filesystem builtins (`ls`/`dir`,
+/// `cat`/`type`, `grep`, and in the `full` profile also `mkdir`, `rm`/`del`,
+/// `mv`), which behave the same on every platform.
+///
+/// Returns `None` if `program` is not a builtin. `command <program>` skips the
+/// builtins and runs the system binary instead. `input` is the output of the
+/// previous command in a pipeline. `rm` and `mv` keep what they delete or
+/// replace in `trash`.
+pub fn run_native_builtin(
+    profile: Profile,
+    trash: &Trash,
+    cwd: &str,
+    program: &str,
+    argv: &[String],
+    input: Option<&str>,
+) -> Option<(Vec<TerminalLine>, ExitOutcome)> {
+    let lines = fs::handle_fs_command(profile, trash, cwd, program, argv, input)?;
+    // The builtins run in-process; they fail if they reported an error.
+    let failed = lines.iter().any(|l| l.line_type == LineType::Error);
+    Some((lines, ExitOutcome::Code(i32::from(failed))))
+}
+
+/// Run an allowed command on Windows. With `passthrough` (`command <program>`)
+/// the system binary is run even if Blaze has a builtin of the same name.
+/// `argv[0]` is the executable chosen by the policy.
 #[cfg(target_os = "windows")]
-pub fn execute_windows_command(cwd: &str, program: &str, argv: &[String]) -> Vec<TerminalLine> {
-    if let Some(lines) = fs::handle_windows_fs_command(cwd, program, argv) {
-        return lines;
+#[allow(clippy::too_many_arguments)]
+pub fn execute_windows_command(
+    profile: Profile,
+    trash: &Trash,
+    cwd: &str,
+    program: &str,
+    argv: &[String],
+    size: TermSize,
+    limits: ExecLimits,
+    passthrough: bool,
+) -> (Vec<TerminalLine>, ExitOutcome) {
+    if passthrough {
+        return process::run_external_command_lines(cwd, &argv[0], &argv[1..], size, limits);
     }
 
-    if let Some(lines) = process::handle_windows_process_command(cwd, program, argv) {
-        return lines;
+    if let Some(result) = run_native_builtin(profile, trash, cwd, program, argv, None) {
+        return result;
     }
 
-    vec![TerminalLine {
-        content: format!("Unhandled command: {}", program),
-        line_type: LineType::Error,
-    }]
+    if let Some(result) = process::handle_windows_process_command(cwd, program, argv, size, limits) {
+        return result;
+    }
+
+    // Allowed by the commands file rather than handled by Blaze itself.
+    process::run_external_command_lines(cwd, &argv[0], &argv[1..], size, limits)
 }
 
+pub use allowlist::{commands_file_path, CommandList};
+pub use fs::confirmation_prompt;
+pub use jail::Jail;
+pub use job::{ForegroundJob, JobState};
+pub use limits::ExecLimits;
+pub use pipeline::{run_pipeline, Progress, Stage};
+pub use policy::{authorize, PolicyContext};
+pub use profile::Profile;
+pub use resolve::{Executables, Pin};
+pub use trash::{handle_trash_command, Trash};
+#[cfg(not(target_os = "windows"))]
+pub use job::JobSignal;
 #[cfg(not(target_os = "windows"))]
 pub use process::stream_unix_command;
+#[cfg(not(target_os = "windows"))]
+pub use sandbox::{availability_warning, Sandbox};
 
 #[cfg(test)]
 mod tests {
-    use super::is_allowed_external;
+    use super::{is_allowed_external, Profile};
 
     #[test]
     fn allows_core_non_mutating_commands() {
-        for cmd in ["ls", "dir", "echo", "whoami", "cat", "grep", "curl", "wget", "ip"] {
-            assert!(is_allowed_external(cmd), "expected '{cmd}' to be allowed");
+        for profile in Profile::ALL {
+            for cmd in ["ls", "dir", "echo", "whoami", "cat", "grep", "curl", "wget", "ip"] {
+                assert!(is_allowed_external(profile, cmd), "expected '{cmd}' to be allowed in {profile}");
+            }
         }
     }
 
     #[cfg(target_os = "windows")]
     #[test]
     fn allows_windows_specific_non_mutating_commands() {
-        assert!(is_allowed_external("type"));
-        assert!(is_allowed_external("ipconfig"));
+        assert!(is_allowed_external(Profile::ReadOnly, "type"));
+        assert!(is_allowed_external(Profile::ReadOnly, "ipconfig"));
     }
 
     #[cfg(not(target_os = "windows"))]
     #[test]
     fn allows_unix_specific_non_mutating_commands() {
-        assert!(is_allowed_external("ifconfig"));
+        assert!(is_allowed_external(Profile::ReadOnly, "ifconfig"));
     }
 
-    #[cfg(all(not(feature = "safe-mode"), feature = "unsafe-fs"))]
     #[test]
-    fn allows_mutating_commands_when_unsafe_fs_enabled() {
+    fn allows_mutating_commands_in_the_full_profile() {
         for cmd in ["mkdir", "rm", "del", "mv"] {
-            assert!(is_allowed_external(cmd), "expected '{cmd}' to be allowed");
+            assert!(is_allowed_external(Profile::Full, cmd), "expected '{cmd}' to be allowed");
         }
     }
 
-    #[cfg(not(all(not(feature = "safe-mode"), feature = "unsafe-fs")))]
     #[test]
-    fn denies_mutating_commands_when_unsafe_fs_disabled() {
+    fn denies_mutating_commands_in_other_profiles() {
         for cmd in ["mkdir", "rm", "del", "mv"] {
-            assert!(!is_allowed_external(cmd), "expected '{cmd}' to be denied");
+            assert!(!is_allowed_external(Profile::ReadOnly, cmd), "expected '{cmd}' to be denied");
+            assert!(!is_allowed_external(Profile::Dev, cmd), "expected '{cmd}' to be denied");
         }
     }
 }
@@ -0,0 +1,732 @@
+//! Pipelines: `a | b | c`, where each command's output is the next one's
+//! input, and redirections of a command's output to files.
+//!
+//! Every command in a pipeline has passed the policy on its own. They all run
+//! at once, connected by pipes, and the last command's output is shown as it
+//! arrives. Spawned programs share one process group, which the job-control
+//! keys signal and the timeout kills. Each command's output is capped at the
+//! byte limit on its way to the next one. Builtins that read their input
+//! (`cat`, `grep`) take it a line at a time. Error output of every command
+//! goes straight to the terminal unless it is redirected. Commands in a
+//! pipeline have no terminal, so full-screen programs cannot run in one.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::fs::reads_input;
+use super::limits::{ExecLimits, LimitExceeded};
+use super::policy::{authorize_redirect, Denial, PolicyContext};
+#[cfg(not(target_os = "windows"))]
+use super::sandbox::{confine, Sandbox};
+use super::trash::Trash;
+use super::{run_native_builtin, runs_in_process, Profile};
+use crate::terminal::parser::{Redirect, RedirectTarget};
+use crate::terminal::state::{ExitOutcome, LineType, TerminalLine};
+
+/// One command of a pipeline, as approved by the policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stage {
+    /// The lowercased command name.
+    pub program: String,
+    /// The command line returned by [`authorize`](super::authorize).
+    pub argv: Vec<String>,
+    /// Run the system binary even where Blaze has a builtin.
+    pub passthrough: bool,
+    /// Where the command's output goes.
+    pub stdout: Sink,
+    /// Where the command's error output goes.
+    pub stderr: Sink,
+}
+
+/// Where one output stream of a [`Stage`] goes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sink {
+    /// To the next command, or to the terminal after the last one.
+    Output,
+    /// To the terminal, as error lines.
+    Errors,
+    /// To a file, emptied first unless `append` is set.
+    File { path: PathBuf, append: bool },
+}
+
+impl Stage {
+    /// A stage whose output goes down the pipeline and whose error output
+    /// goes to the terminal.
+    pub fn new(program: String, argv: Vec<String>, passthrough: bool) -> Self {
+        Self {
+            program,
+            argv,
+            passthrough,
+            stdout: Sink::Output,
+            stderr: Sink::Errors,
+        }
+    }
+
+    /// Apply `redirects` in order, checking every file against the policy.
+    pub fn redirect(&mut self, ctx: PolicyContext, redirects: &[Redirect<String>]) -> Result<(), Denial> {
+        for redirect in redirects {
+            let sink = match &redirect.target {
+                RedirectTarget::File { path, append } => Sink::File {
+                    path: authorize_redirect(ctx, path)?,
+                    append: *append,
+                },
+                RedirectTarget::Fd(1) => self.stdout.clone(),
+                RedirectTarget::Fd(_) => self.stderr.clone(),
+            };
+            if redirect.fd == 2 {
+                self.stderr = sink;
+            } else {
+                self.stdout = sink;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a running pipeline reports as it goes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Progress {
+    /// Its programs were started in this process group.
+    #[cfg(not(target_os = "windows"))]
+    Group(u32),
+    /// A line to show.
+    Line(TerminalLine),
+}
+
+/// One command of a running pipeline.
+enum Running {
+    Child(Child),
+    Builtin(JoinHandle<ExitOutcome>),
+    /// Finished, or never started.
+    Done(ExitOutcome),
+}
+
+impl Running {
+    /// Note whether the command has finished, without waiting for it.
+    fn poll(&mut self) {
+        let outcome = match self {
+            Self::Child(child) => match child.try_wait() {
+                Ok(Some(status)) => ExitOutcome::from(status),
+                Ok(None) => return,
+                Err(_) => ExitOutcome::Code(1),
+            },
+            Self::Builtin(handle) if handle.is_finished() => {
+                let Self::Builtin(handle) = std::mem::replace(self, Self::Done(ExitOutcome::Code(1))) else {
+                    return;
+                };
+                handle.join().unwrap_or(ExitOutcome::Code(1))
+            }
+            _ => return,
+        };
+        *self = Self::Done(outcome);
+    }
+
+    fn outcome(&self) -> Option<ExitOutcome> {
+        match self {
+            Self::Done(outcome) => Some(*outcome),
+            _ => None,
+        }
+    }
+}
+
+/// Run `stages` in `cwd`, each one's output feeding the next, and return the
+/// outcome of the last command. Lines to show and the process group are
+/// passed to `report` as they come.
+pub fn run_pipeline(
+    profile: Profile,
+    trash: &Trash,
+    cwd: &str,
+    stages: &[Stage],
+    limits: ExecLimits,
+    #[cfg(not(target_os = "windows"))] sandbox: &Sandbox,
+    report: &mut dyn FnMut(Progress),
+) -> ExitOutcome {
+    let (lines, shown) = mpsc::channel();
+    let mut running = Vec::new();
+    let mut over_caps = Vec::new();
+    #[cfg(not(target_os = "windows"))]
+    let mut group = None;
+    let mut input = None;
+    for (i, stage) in stages.iter().enumerate() {
+        let last = i + 1 == stages.len();
+        let over_cap = Arc::new(AtomicBool::new(false));
+        let pipes = Pipes::new(&stage.program, last, limits.max_output_bytes, &over_cap, &lines);
+        let (pipes, next_input) = match pipes {
+            Ok(pipes) => pipes,
+            Err(e) => {
+                let _ = lines.send(error_line(format!("{}: {}", stage.program, e)));
+                running.push(Running::Done(ExitOutcome::Code(1)));
+                over_caps.push(over_cap);
+                break;
+            }
+        };
+        let started = start_stage(
+            profile,
+            trash,
+            cwd,
+            stage,
+            input.take(),
+            &pipes,
+            &lines,
+            limits,
+            #[cfg(not(target_os = "windows"))]
+            sandbox,
+            #[cfg(not(target_os = "windows"))]
+            group,
+        );
+        // Only the commands may hold the write ends now, or the next command
+        // would never see the end of its input.
+        drop(pipes);
+        let started = started.unwrap_or_else(|(message, outcome)| {
+            let _ = lines.send(error_line(format!("{}: {}", stage.program, message)));
+            Running::Done(outcome)
+        });
+        #[cfg(not(target_os = "windows"))]
+        if let (None, Running::Child(child)) = (group, &started) {
+            group = Some(child.id());
+            report(Progress::Group(child.id()));
+        }
+        running.push(started);
+        over_caps.push(over_cap);
+        input = next_input;
+    }
+    drop((lines, input));
+
+    // Show lines as they arrive until every command has finished and every
+    // pipe has been drained.
+    let started = Instant::now();
+    let kill_all = |running: &mut [Running]| {
+        #[cfg(not(target_os = "windows"))]
+        if let Some(group) = group {
+            // SAFETY: killpg has no memory-safety preconditions.
+            unsafe { libc::killpg(group as libc::pid_t, libc::SIGKILL) };
+        }
+        for command in running.iter_mut() {
+            if let Running::Child(child) = command {
+                let _ = child.kill();
+            }
+        }
+    };
+    let mut shown_lines = 0;
+    let mut drained = false;
+    let mut stopped_once = false;
+    let mut killed = vec![false; running.len()];
+    let mut exceeded = None;
+    while !drained || running.iter().any(|command| command.outcome().is_none()) {
+        match shown.recv_timeout(Duration::from_millis(10)) {
+            Ok(line) if line.line_type == LineType::Output && exceeded.is_none() => {
+                shown_lines += 1;
+                match limits.max_output_lines.filter(|limit| shown_lines > *limit) {
+                    Some(limit) => {
+                        exceeded = Some(LimitExceeded::Lines(limit));
+                        let last = stages.last().map_or("", |stage| stage.program.as_str());
+                        report(Progress::Line(LimitExceeded::Lines(limit).notice(last)));
+                        kill_all(&mut running);
+                    }
+                    None => report(Progress::Line(line)),
+                }
+            }
+            Ok(line) if line.line_type != LineType::Output => report(Progress::Line(line)),
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                drained = true;
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        for (i, command) in running.iter_mut().enumerate() {
+            #[cfg_attr(target_os = "windows", allow(unused_variables))]
+            let was_running = command.outcome().is_none();
+            command.poll();
+            #[cfg(not(target_os = "windows"))]
+            if let (true, Some(outcome)) = (was_running, command.outcome()) {
+                if let Some(limit) = limits.exceeded_by(outcome).filter(|_| !killed[i]) {
+                    report(Progress::Line(limit.notice(&stages[i].program)));
+                }
+            }
+            // A command whose output went over the cap is stopped, so the
+            // rest of the pipeline sees the end of its input.
+            if over_caps[i].load(Ordering::Relaxed) && !killed[i] {
+                killed[i] = true;
+                if let Running::Child(child) = command {
+                    let _ = child.kill();
+                }
+            }
+        }
+
+        // Like a job in the terminal, a pipeline that has been suspended is
+        // no longer timed.
+        #[cfg(not(target_os = "windows"))]
+        {
+            stopped_once = stopped_once
+                || running.iter().any(|command| matches!(command, Running::Child(child) if super::job::is_stopped(child.id())));
+        }
+        if let Some(limit) = limits.timeout.filter(|limit| !stopped_once && exceeded.is_none() && started.elapsed() >= *limit) {
+            exceeded = Some(LimitExceeded::Timeout(limit));
+            let stuck = running.iter().position(|command| command.outcome().is_none()).unwrap_or_default();
+            report(Progress::Line(LimitExceeded::Timeout(limit).notice(&stages[stuck].program)));
+            killed.fill(true);
+            kill_all(&mut running);
+        }
+    }
+
+    match exceeded {
+        // 124 is what coreutils' `timeout` reports.
+        Some(LimitExceeded::Timeout(_)) => ExitOutcome::Code(124),
+        _ => running.last().and_then(Running::outcome).unwrap_or(ExitOutcome::Code(0)),
+    }
+}
+
+/// The pipes a stage writes to. Whatever is written to them is read on
+/// threads of their own, up to the byte cap: output goes to the next stage's
+/// input, or is shown after the last stage, and errors are shown.
+struct Pipes {
+    output: PipeWriter,
+    errors: PipeWriter,
+}
+
+impl Pipes {
+    /// Also returns the read end of the next stage's input.
+    fn new(
+        program: &str,
+        last: bool,
+        cap: Option<usize>,
+        over_cap: &Arc<AtomicBool>,
+        lines: &Sender<TerminalLine>,
+    ) -> std::io::Result<(Self, Option<PipeReader>)> {
+        let (output, output_writer) = std::io::pipe()?;
+        let (errors, errors_writer) = std::io::pipe()?;
+        let relay = Relay {
+            program: program.to_string(),
+            cap,
+            over_cap: over_cap.clone(),
+            lines: lines.clone(),
+        };
+        let next_input = if last {
+            relay.clone().show(output, LineType::Output);
+            None
+        } else {
+            let (next_input, next) = std::io::pipe()?;
+            relay.clone().forward(output, next);
+            Some(next_input)
+        };
+        relay.show(errors, LineType::Error);
+        Ok((Self { output: output_writer, errors: errors_writer }, next_input))
+    }
+}
+
+/// Copies what one stream of a stage writes to where it goes, stopping at
+/// the byte cap. The output and errors of a stage share one cap flag.
+#[derive(Clone)]
+struct Relay {
+    program: String,
+    cap: Option<usize>,
+    over_cap: Arc<AtomicBool>,
+    lines: Sender<TerminalLine>,
+}
+
+impl Relay {
+    /// Whether `total` bytes are over the cap. The first stream to go over
+    /// it says so.
+    fn over(&self, total: usize) -> bool {
+        let Some(cap) = self.cap.filter(|cap| total > *cap) else {
+            return false;
+        };
+        if !self.over_cap.swap(true, Ordering::Relaxed) {
+            let _ = self.lines.send(LimitExceeded::Bytes(cap).notice(&self.program));
+        }
+        true
+    }
+
+    /// Copy `from` into `to`, the next stage's input.
+    fn forward(self, mut from: PipeReader, mut to: PipeWriter) {
+        std::thread::spawn(move || {
+            let mut total = 0;
+            let mut buf = [0u8; 8192];
+            loop {
+                let n = match from.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+                let keep = self.cap.map_or(n, |cap| n.min(cap.saturating_sub(total)));
+                total += n;
+                // A command that stops reading early closes the pipe; that is
+                // fine.
+                if to.write_all(&buf[..keep]).is_err() || self.over(total) {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Show `from` line by line as `line_type` lines.
+    fn show(self, from: PipeReader, line_type: LineType) {
+        std::thread::spawn(move || {
+            let mut from = BufReader::new(from);
+            let mut total = 0;
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match from.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(n) => total += n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+                if let Some(cap) = self.cap.filter(|cap| total > *cap) {
+                    line.truncate(line.len() - (total - cap));
+                }
+                if !line.is_empty() {
+                    let text = String::from_utf8_lossy(&line);
+                    let text = text.strip_suffix('\n').unwrap_or(&text);
+                    let text = text.strip_suffix('\r').unwrap_or(text);
+                    let line = TerminalLine {
+                        content: text.to_string(),
+                        line_type: line_type.clone(),
+                        spans: Vec::new(),
+                    };
+                    if self.lines.send(line).is_err() {
+                        break;
+                    }
+                }
+                if self.over(total) {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Start one stage: spawn its program in the pipeline's process group, or run
+/// the builtin on a thread of its own.
+#[allow(clippy::too_many_arguments)]
+fn start_stage(
+    profile: Profile,
+    trash: &Trash,
+    cwd: &str,
+    stage: &Stage,
+    input: Option<PipeReader>,
+    pipes: &Pipes,
+    lines: &Sender<TerminalLine>,
+    limits: ExecLimits,
+    #[cfg(not(target_os = "windows"))] sandbox: &Sandbox,
+    #[cfg(not(target_os = "windows"))] group: Option<u32>,
+) -> Result<Running, (String, ExitOutcome)> {
+    let failed = |e: std::io::Error| (e.to_string(), ExitOutcome::Code(1));
+    let files = open_files(stage).map_err(|message| (message, ExitOutcome::Code(1)))?;
+
+    if !stage.passthrough && runs_in_process(&stage.program) {
+        let dest = |sink: &Sink, file: Option<&File>| -> std::io::Result<Dest> {
+            Ok(match (sink, file) {
+                (Sink::File { path, .. }, Some(file)) => Dest::File(file.try_clone()?, path.clone()),
+                (Sink::Errors, _) => Dest::Shown(lines.clone()),
+                _ => Dest::Pipe(pipes.output.try_clone()?),
+            })
+        };
+        let mut stdout = dest(&stage.stdout, files.stdout.as_ref()).map_err(failed)?;
+        let mut stderr = dest(&stage.stderr, files.stderr.as_ref()).map_err(failed)?;
+        let (stage, trash, cwd, lines) = (stage.clone(), trash.clone(), cwd.to_string(), lines.clone());
+        return Ok(Running::Builtin(std::thread::spawn(move || {
+            let mut succeeded = true;
+            let mut run = |text: Option<&str>| {
+                let (output, outcome) = run_builtin(profile, &trash, &cwd, &stage, text, limits);
+                succeeded &= outcome.success();
+                let (output, errors): (Vec<_>, Vec<_>) =
+                    output.into_iter().partition(|line| line.line_type == LineType::Output);
+                for (dest, output) in [(&mut stdout, output), (&mut stderr, errors)] {
+                    if let Err(message) = dest.write(output) {
+                        let _ = lines.send(error_line(format!("{}: {}", stage.program, message)));
+                        succeeded = false;
+                    }
+                }
+            };
+            match input.filter(|_| reads_input(&stage.program, &stage.argv)) {
+                Some(input) => {
+                    let mut input = BufReader::new(input);
+                    let mut line = Vec::new();
+                    while input.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+                        run(Some(&String::from_utf8_lossy(&line)));
+                        line.clear();
+                    }
+                }
+                None => run(None),
+            }
+            ExitOutcome::Code(i32::from(!succeeded))
+        })));
+    }
+
+    let stdio = |sink: &Sink, file: Option<&File>| -> std::io::Result<Stdio> {
+        match (sink, file) {
+            (Sink::File { .. }, Some(file)) => file.try_clone().map(Stdio::from),
+            (Sink::Errors, _) => pipes.errors.try_clone().map(Stdio::from),
+            _ => pipes.output.try_clone().map(Stdio::from),
+        }
+    };
+    let stdout = stdio(&stage.stdout, files.stdout.as_ref()).map_err(failed)?;
+    let stderr = stdio(&stage.stderr, files.stderr.as_ref()).map_err(failed)?;
+
+    #[cfg(target_os = "windows")]
+    let mut command = crate::terminal::utils::windows_hidden_command(&stage.argv[0], cwd);
+    #[cfg(not(target_os = "windows"))]
+    let mut command = std::process::Command::new(&stage.argv[0]);
+    command
+        .args(&stage.argv[1..])
+        .current_dir(cwd)
+        .stdin(input.map_or_else(Stdio::null, Stdio::from))
+        .stdout(stdout)
+        .stderr(stderr);
+    #[cfg(not(target_os = "windows"))]
+    {
+        command.process_group(group.map_or(0, |group| group as i32));
+        confine(&mut command, &limits, sandbox).map_err(failed)?;
+    }
+    command
+        .spawn()
+        .map(Running::Child)
+        .map_err(|e| (e.to_string(), ExitOutcome::from_spawn_error(&e)))
+}
+
+/// Run a builtin stage on `input`.
+fn run_builtin(
+    profile: Profile,
+    trash: &Trash,
+    cwd: &str,
+    stage: &Stage,
+    input: Option<&str>,
+    #[cfg_attr(not(target_os = "windows"), allow(unused_variables))] limits: ExecLimits,
+) -> (Vec<TerminalLine>, ExitOutcome) {
+    if let Some(result) = run_native_builtin(profile, trash, cwd, &stage.program, &stage.argv, input) {
+        return result;
+    }
+    #[cfg(target_os = "windows")]
+    if let Some(result) = super::process::handle_windows_process_command(
+        cwd,
+        &stage.program,
+        &stage.argv,
+        crate::terminal::state::TermSize::default(),
+        limits,
+    ) {
+        return result;
+    }
+    (vec![error_line(format!("{}: not a builtin", stage.program))], ExitOutcome::Code(127))
+}
+
+/// The files a stage's output and error output are redirected to, opened
+/// once. Both streams redirected to the same file share it.
+struct Files {
+    stdout: Option<File>,
+    stderr: Option<File>,
+}
+
+fn open_files(stage: &Stage) -> Result<Files, String> {
+    let open = |sink: &Sink| match sink {
+        Sink::File { path, append } => OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(*append)
+            .truncate(!*append)
+            .open(path)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        _ => Ok(None),
+    };
+    let stdout = open(&stage.stdout)?;
+    let stderr = match &stdout {
+        Some(file) if stage.stderr == stage.stdout => Some(file.try_clone().map_err(|e| e.to_string())?),
+        _ => open(&stage.stderr)?,
+    };
+    Ok(Files { stdout, stderr })
+}
+
+/// Where one stream of a builtin stage goes.
+enum Dest {
+    /// Down the pipeline.
+    Pipe(PipeWriter),
+    File(File, PathBuf),
+    /// To the terminal, as error lines.
+    Shown(Sender<TerminalLine>),
+}
+
+impl Dest {
+    /// Write `lines`. Fails if a file could not be written; a next command
+    /// that stopped reading is fine.
+    fn write(&mut self, lines: Vec<TerminalLine>) -> Result<(), String> {
+        let bytes = || {
+            let mut bytes = Vec::new();
+            for line in &lines {
+                bytes.extend_from_slice(line.content.as_bytes());
+                bytes.push(b'\n');
+            }
+            bytes
+        };
+        match self {
+            Self::Pipe(pipe) => {
+                let _ = pipe.write_all(&bytes());
+            }
+            Self::File(file, path) => file.write_all(&bytes()).map_err(|e| format!("{}: {}", path.display(), e))?,
+            Self::Shown(shown) => {
+                for mut line in lines {
+                    if line.line_type == LineType::Output {
+                        line.line_type = LineType::Error;
+                    }
+                    let _ = shown.send(line);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn error_line(content: String) -> TerminalLine {
+    TerminalLine {
+        content,
+        line_type: LineType::Error,
+        spans: Vec::new(),
+    }
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::{run_pipeline, Progress, Sink, Stage};
+    use crate::terminal::commands::desktop::job::{ForegroundJob, JobSignal};
+    use crate::terminal::commands::desktop::limits::ExecLimits;
+    use crate::terminal::commands::desktop::sandbox::Sandbox;
+    use crate::terminal::commands::desktop::{Profile, Trash};
+    use crate::terminal::state::{ExitOutcome, LineType, TerminalLine};
+    use crate::terminal::testing::TempDir;
+    use std::time::Duration;
+
+    fn stage(words: &[&str], passthrough: bool) -> Stage {
+        let argv: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        Stage::new(argv[0].rsplit('/').next().unwrap_or_default().to_string(), argv, passthrough)
+    }
+
+    /// A trash of its own for every run, kept while the directory lives. No
+    /// test pipeline removes files, so nothing is ever put there.
+    fn trash() -> (TempDir, Trash) {
+        let dir = TempDir::new("pipeline_trash");
+        let trash = Trash::at(dir.to_path_buf());
+        (dir, trash)
+    }
+
+    /// The lines `stages` show when run in `cwd`, and the outcome.
+    fn run_in(cwd: &str, stages: &[Stage], limits: ExecLimits) -> (Vec<TerminalLine>, ExitOutcome) {
+        let mut lines = Vec::new();
+        let (_dir, trash) = trash();
+        let outcome = run_pipeline(Profile::Dev, &trash, cwd, stages, limits, &Sandbox::default(), &mut |progress| {
+            if let Progress::Line(line) = progress {
+                lines.push(line);
+            }
+        });
+        (lines, outcome)
+    }
+
+    fn output(stages: &[Stage], limits: ExecLimits) -> (Vec<String>, ExitOutcome) {
+        let (lines, outcome) = run_in(&std::env::temp_dir().display().to_string(), stages, limits);
+        (lines.into_iter().map(|l| format!("{:?}:{}", l.line_type, l.content)).collect(), outcome)
+    }
+
+    #[test]
+    fn builtins_and_programs_read_the_previous_output() {
+        let stages = [
+            stage(&["/bin/sh", "-c", "printf 'ERROR one\\nok\\nERROR two\\n'; echo oops >&2"], true),
+            stage(&["grep", "ERROR"], false),
+            stage(&["/bin/sh", "-c", "tr a-z A-Z; exit 3"], true),
+        ];
+        let (lines, outcome) = output(&stages, ExecLimits::default());
+        assert_eq!(lines, vec!["Error:oops", "Output:ERROR ONE", "Output:ERROR TWO"]);
+        assert_eq!(outcome, ExitOutcome::Code(3));
+
+        let stages = [stage(&["/bin/echo", "piped"], true), stage(&["cat"], false)];
+        assert_eq!(output(&stages, ExecLimits::default()).0, vec!["Output:piped"]);
+    }
+
+    #[test]
+    fn endless_producers_stop_at_the_output_cap() {
+        let limits = ExecLimits { max_output_bytes: Some(4096), ..ExecLimits::default() };
+        let stages = [stage(&["/usr/bin/yes"], true), stage(&["/usr/bin/wc", "-c"], true)];
+        let (lines, _) = output(&stages, limits);
+        assert_eq!(lines, vec!["Error:yes: output truncated at 4 KiB (limit reached)", "Output:4096"]);
+    }
+
+    #[test]
+    fn output_streams_and_job_signals_reach_every_command() {
+        let (progress, reported) = std::sync::mpsc::channel();
+        let (done, finished) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let stages = [
+                stage(&["/bin/sh", "-c", "echo first; exec sleep 30"], true),
+                stage(&["cat"], false),
+                stage(&["/bin/cat"], true),
+            ];
+            let cwd = std::env::temp_dir().display().to_string();
+            let limits = ExecLimits { timeout: None, ..ExecLimits::default() };
+            let (_dir, trash) = trash();
+            let outcome = run_pipeline(Profile::Dev, &trash, &cwd, &stages, limits, &Sandbox::default(), &mut |p| {
+                let _ = progress.send(p);
+            });
+            let _ = done.send(outcome);
+        });
+
+        let next = || reported.recv_timeout(Duration::from_secs(5)).expect("pipeline progress");
+        let Progress::Group(pgid) = next() else {
+            panic!("the process group is reported first");
+        };
+        assert_eq!(next(), Progress::Line(TerminalLine {
+            content: "first".into(),
+            line_type: LineType::Output,
+            spans: Vec::new(),
+        }));
+
+        let mut job = ForegroundJob::new("sh | cat | cat".into());
+        job.pgid = Some(pgid);
+        job.send(JobSignal::Interrupt).expect("interrupt");
+        let outcome = finished.recv_timeout(Duration::from_secs(5)).expect("pipeline ended");
+        assert_eq!(outcome, ExitOutcome::Signal(libc::SIGINT));
+    }
+
+    #[test]
+    fn output_and_errors_go_to_separate_or_shared_files() {
+        let dir = TempDir::new("pipeline_redirect");
+        let file = |name: &str, append| Sink::File { path: dir.join(name), append };
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).expect("read redirected file");
+
+        let mut both = stage(&["/bin/sh", "-c", "echo out; echo err >&2; echo out2"], true);
+        both.stdout = file("both.txt", false);
+        both.stderr = both.stdout.clone();
+        assert_eq!(output(&[both], ExecLimits::default()).0, Vec::<String>::new());
+        assert_eq!(read("both.txt"), "out\nerr\nout2\n");
+
+        let mut split = stage(&["/bin/sh", "-c", "echo out; echo err >&2"], true);
+        split.stdout = file("both.txt", true);
+        split.stderr = Sink::Output;
+        let (lines, _) = output(&[split, stage(&["cat"], false)], ExecLimits::default());
+        assert_eq!(lines, vec!["Output:err"]);
+        assert_eq!(read("both.txt"), "out\nerr\nout2\nout\n");
+
+        // Builtins print lines of their own, which are routed the same way.
+        let cwd = dir.display().to_string();
+        let mut shown = stage(&["cat", "both.txt"], false);
+        shown.stdout = Sink::Errors;
+        let (lines, _) = run_in(&cwd, &[shown], ExecLimits::default());
+        assert_eq!(lines.iter().map(|l| l.content.as_str()).collect::<Vec<_>>(), vec!["out", "err", "out2", "out"]);
+        assert!(lines.iter().all(|l| l.line_type == LineType::Error));
+        let mut missing = stage(&["cat", "no_such_file"], false);
+        missing.stderr = file("err.txt", false);
+        let (lines, outcome) = run_in(&cwd, &[missing], ExecLimits::default());
+        assert!(lines.is_empty(), "{:?}", lines);
+        assert!(read("err.txt").contains("No such file"), "{}", read("err.txt"));
+        assert_ne!(outcome, ExitOutcome::Code(0));
+    }
+}
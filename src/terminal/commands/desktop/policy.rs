@@ -0,0 +1,949 @@
+//! The command policy: decides whether a command line may run, on every
+//! platform, before anything is spawned or executed.
+//!
+//! The policy is a list of named rules that see the program and its
+//! arguments. The first rule that objects blocks the command, and its name is
+//! shown alongside the reason so the user knows exactly what stopped it.
+//!
+//! What is allowed depends on the active [`Profile`] and the user's
+//! [`CommandList`], which can allow more programs or deny any of them. Filesystem-mutating
+//! commands (`mkdir`, `rm`/`del`, `mv`) only ever run as the native builtins
+//! in `fs.rs`, and only in the `full` profile. In the `readonly` profile only
+//! the profile's own programs run (the commands file cannot add any), output
+//! cannot be redirected to a file, and the arguments that make `vim`, `wget`
+//! and `curl` write files are refused; see [`Profile`] for what the sandbox
+//! adds. With a workspace [`Jail`], the filesystem builtins only take paths
+//! inside it.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::jail::normalize;
+use super::{is_allowed_external, runs_in_process, CommandList, Executables, Jail, Profile};
+
+/// Where a command is being run from.
+#[derive(Clone, Copy, Debug)]
+pub struct PolicyContext<'a> {
+    pub profile: Profile,
+    pub commands: &'a CommandList,
+    /// The binary behind each allowed command.
+    pub executables: &'a Executables,
+    pub cwd: &'a Path,
+    /// The tree that recursive searches must stay inside.
+    pub workspace: &'a Path,
+    /// The configured workspace root, if Blaze is confined to it.
+    pub jail: Option<&'a Jail>,
+}
+
+/// A command blocked by the policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Denial {
+    /// Name of the rule that blocked the command.
+    pub rule: &'static str,
+    pub reason: String,
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [policy: {}]", self.reason, self.rule)
+    }
+}
+
+/// A command line as the rules see it.
+struct Invocation<'a> {
+    program: &'a str,
+    /// The arguments after the program name.
+    args: &'a [String],
+    passthrough: bool,
+    ctx: PolicyContext<'a>,
+}
+
+struct Rule {
+    name: &'static str,
+    check: fn(&Invocation) -> Result<(), String>,
+}
+
+/// Checked in order; the first rule that objects blocks the command.
+const RULES: &[Rule] = &[
+    Rule { name: "fs-mutations-disabled", check: fs_mutations_disabled },
+    Rule { name: "fs-builtins-only", check: fs_builtins_only },
+    Rule { name: "denylist", check: denylist },
+    Rule { name: "allowlist", check: allowlist },
+    Rule { name: "known-options", check: known_options },
+    Rule { name: "workspace-jail", check: workspace_jail },
+    Rule { name: "curl-no-file-io", check: curl_no_file_io },
+    Rule { name: "wget-stdout-only", check: wget_stdout_only },
+    Rule { name: "grep-recursive-in-workspace", check: grep_recursive_in_workspace },
+];
+
+/// Check a command line against the policy. `program` is the lowercased
+/// command name and `argv` the full command line, program included.
+///
+/// `passthrough` is set for `command <program>`, which runs the system binary
+/// instead of a builtin. On success, returns the command line to run, which
+/// the policy may have tightened. Its first element is the resolved binary for
+/// commands that spawn one, and the lowercased name for those Blaze runs
+/// itself.
+pub fn authorize(
+    ctx: PolicyContext,
+    program: &str,
+    argv: &[String],
+    passthrough: bool,
+) -> Result<Vec<String>, Denial> {
+    let invocation = Invocation {
+        program,
+        args: argv.get(1..).unwrap_or_default(),
+        passthrough,
+        ctx,
+    };
+    for rule in RULES {
+        (rule.check)(&invocation).map_err(|reason| Denial { rule: rule.name, reason })?;
+    }
+
+    let mut argv = argv.to_vec();
+    argv[0] = program.to_string();
+    if passthrough || !runs_in_process(program) {
+        let path = ctx.executables.lookup(program).map_err(|reason| Denial { rule: "resolved-binary", reason })?;
+        argv[0] = path.display().to_string();
+    }
+    // `-q` first stops curl from reading `~/.curlrc`, which could set
+    // options the curl rule denies.
+    if program == "curl" && !matches!(argv.get(1).map(String::as_str), Some("-q" | "--disable")) {
+        argv.insert(1, "-q".to_string());
+    }
+    // vim can write files and run shell commands; read-only it only views.
+    if ctx.profile.is_read_only() && program == "vim" {
+        argv.splice(1..1, ["-M".to_string(), "-Z".to_string()]);
+    }
+    Ok(argv)
+}
+
+/// Check that a command's output may be written to `path` (as typed) and
+/// return the file's absolute path. Writing a file needs the profile that
+/// allows the mutating builtins, and must stay inside the workspace jail; the
+/// null device is always allowed.
+pub fn authorize_redirect(ctx: PolicyContext, path: &str) -> Result<PathBuf, Denial> {
+    let target = ctx.cwd.join(path);
+    let null_device = if cfg!(target_os = "windows") { path.eq_ignore_ascii_case("NUL") } else { path == "/dev/null" };
+    if null_device {
+        return Ok(target);
+    }
+    if !ctx.profile.allows_mutations() {
+        return Err(Denial {
+            rule: "fs-mutations-disabled",
+            reason: format!(
+                "{}: redirecting output to a file is disabled in the '{}' profile (start Blaze with --profile full).",
+                path, ctx.profile
+            ),
+        });
+    }
+    if let Some(jail) = ctx.jail {
+        jail.check(&target, path).map_err(|reason| Denial { rule: "workspace-jail", reason })?;
+    }
+    Ok(target)
+}
+
+/// Commands that change the filesystem and are handled by the native builtins.
+fn is_mutating(program: &str) -> bool {
+    matches!(program, "mkdir" | "rm" | "del" | "mv")
+}
+
+fn fs_mutations_disabled(inv: &Invocation) -> Result<(), String> {
+    let profile = inv.ctx.profile;
+    if is_mutating(inv.program) && !profile.allows_mutations() {
+        return Err(format!(
+            "{} is disabled in the '{}' profile (start Blaze with --profile full).",
+            inv.program, profile
+        ));
+    }
+    Ok(())
+}
+
+fn fs_builtins_only(inv: &Invocation) -> Result<(), String> {
+    if is_mutating(inv.program) && inv.passthrough {
+        return Err(format!(
+            "{}: 'command' cannot run filesystem-mutating system binaries; use the builtin instead.",
+            inv.program
+        ));
+    }
+    Ok(())
+}
+
+fn denylist(inv: &Invocation) -> Result<(), String> {
+    if let Some(line) = inv.ctx.commands.denied_at(inv.program) {
+        return Err(format!(
+            "Command '{}' is denied by {}:{}.",
+            inv.program,
+            inv.ctx.commands.path().display(),
+            line
+        ));
+    }
+    Ok(())
+}
+
+fn allowlist(inv: &Invocation) -> Result<(), String> {
+    let ctx = inv.ctx;
+    if is_allowed_external(ctx.profile, inv.program) {
+        return Ok(());
+    }
+    if ctx.commands.allows(inv.program) && !ctx.commands.allows_in(ctx.profile, inv.program) {
+        return Err(format!(
+            "{} is allowed by {}, but the '{}' profile only runs its own commands.",
+            inv.program,
+            ctx.commands.path().display(),
+            ctx.profile
+        ));
+    }
+    if !ctx.commands.allows_in(ctx.profile, inv.program) {
+        return Err(format!(
+            "Command '{}' is not allowed. Type 'help' for a list of available commands.",
+            inv.program
+        ));
+    }
+    Ok(())
+}
+
+fn workspace_jail(inv: &Invocation) -> Result<(), String> {
+    let Some(jail) = inv.ctx.jail else {
+        return Ok(());
+    };
+    let paths = match inv.program {
+        "ls" | "dir" | "cat" | "type" | "mkdir" | "rm" | "del" | "mv" => builtin_operands(inv.args),
+        "grep" => grep_paths(inv.args)?.1,
+        _ => return Ok(()),
+    };
+    for path in paths {
+        jail.check(&inv.ctx.cwd.join(path), path)
+            .map_err(|e| format!("{}: {}", inv.program, e))?;
+    }
+    Ok(())
+}
+
+/// The options of the programs whose arguments the rules look at.
+fn option_table(program: &str) -> Option<&'static OptionTable> {
+    match program {
+        "curl" => Some(&CURL_OPTIONS),
+        "wget" => Some(&WGET_OPTIONS),
+        "grep" => Some(&GREP_OPTIONS),
+        _ => None,
+    }
+}
+
+/// The argument rules only understand options from the program's table, so
+/// an unknown or ambiguous long option is refused instead of passed on.
+fn known_options(inv: &Invocation) -> Result<(), String> {
+    if let Some(table) = option_table(inv.program) {
+        parse_options(inv.args, table).map_err(|e| format!("{}: {}", inv.program, e))?;
+    }
+    Ok(())
+}
+
+fn curl_no_file_io(inv: &Invocation) -> Result<(), String> {
+    if inv.program != "curl" {
+        return Ok(());
+    }
+    // The options below are denied by name, whatever their value.
+    // `.curlrc` is kept out by the `-q` that `authorize` puts first.
+    let parsed = parse_options(inv.args, &CURL_OPTIONS)?;
+    for (name, value) in &parsed.options {
+        let value = value.unwrap_or_default();
+        match name.as_str() {
+            "-o" | "--output" | "-O" | "--remote-name" | "--remote-name-all" | "--output-dir"
+            | "-D" | "--dump-header" | "-c" | "--cookie-jar" | "--trace" | "--trace-ascii"
+            | "--libcurl" | "--stderr" | "--etag-save" | "--hsts" | "--alt-svc" | "--ssl-sessions" => {
+                return Err(format!("curl: {} writes to a file; output may only go to the terminal.", name));
+            }
+            "-w" | "--write-out" if value.contains("%output{") => {
+                return Err(format!("curl: {} {} writes to a file; output may only go to the terminal.", name, value));
+            }
+            // A config file can set any of the above.
+            "-K" | "--config" => {
+                return Err(format!("curl: {} reads options from a file; give them on the command line.", name));
+            }
+            "-T" | "--upload-file" => {
+                return Err(format!("curl: {} uploads a local file.", name));
+            }
+            "-d" | "--data" | "--data-ascii" | "--data-binary" | "--json" if value.starts_with('@') => {
+                return Err(format!("curl: {} {} uploads a local file.", name, value));
+            }
+            "--data-urlencode" | "--url-query" if value.contains('@') => {
+                return Err(format!("curl: {} {} uploads a local file.", name, value));
+            }
+            "-F" | "--form" if value.contains("=@") || value.contains("=<") => {
+                return Err(format!("curl: {} {} uploads a local file.", name, value));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn wget_stdout_only(inv: &Invocation) -> Result<(), String> {
+    if inv.program != "wget" || !inv.ctx.profile.is_read_only() {
+        return Ok(());
+    }
+    let parsed = parse_options(inv.args, &WGET_OPTIONS)?;
+    let mut to_stdout = false;
+    for (name, value) in &parsed.options {
+        match name.as_str() {
+            "-O" | "--output-document" => to_stdout = *value == Some("-"),
+            "-o" | "--output-file" | "-a" | "--append-output" => {
+                return Err(format!("wget: {} writes a log file.", name));
+            }
+            "--save-cookies" | "--warc-file" | "--rejected-log" => {
+                return Err(format!("wget: {} writes a file.", name));
+            }
+            // wgetrc commands can redirect output as well.
+            "-e" | "--execute" | "--config" => {
+                return Err(format!("wget: {} is not allowed in the readonly profile.", name));
+            }
+            _ => {}
+        }
+    }
+    if !to_stdout {
+        return Err("wget: in the readonly profile downloads must go to the terminal; use '-O -'.".to_string());
+    }
+    Ok(())
+}
+
+fn grep_recursive_in_workspace(inv: &Invocation) -> Result<(), String> {
+    if inv.program != "grep" {
+        return Ok(());
+    }
+    let (recursive, paths) = grep_paths(inv.args)?;
+    if !recursive {
+        return Ok(());
+    }
+    // Without a path, a recursive grep searches the working directory.
+    let paths = if paths.is_empty() { vec!["."] } else { paths };
+
+    let workspace = normalize(inv.ctx.workspace);
+    for path in paths {
+        if !normalize(&inv.ctx.cwd.join(path)).starts_with(&workspace) {
+            return Err(format!(
+                "grep: recursive search of '{}' is outside the workspace ({}).",
+                path,
+                workspace.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a grep command line searches recursively, and the paths it
+/// searches (the operands after the pattern).
+fn grep_paths(args: &[String]) -> Result<(bool, Vec<&str>), String> {
+    let parsed = parse_options(args, &GREP_OPTIONS)?;
+    let mut recursive = false;
+    let mut pattern_given = false;
+    for (name, value) in &parsed.options {
+        match name.as_str() {
+            "-r" | "-R" | "--recursive" | "--dereference-recursive" => recursive = true,
+            "-d" | "--directories" => recursive = *value == Some("recurse"),
+            "-e" | "--regexp" | "-f" | "--file" => pattern_given = true,
+            _ => {}
+        }
+    }
+    let mut paths = parsed.operands;
+    if !pattern_given && !paths.is_empty() {
+        paths.remove(0);
+    }
+    Ok((recursive, paths))
+}
+
+/// The operands of a builtin's command line. The builtins refuse options
+/// they do not know, so every argument that looks like an option is one.
+fn builtin_operands(args: &[String]) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            operands.extend(iter.map(String::as_str));
+            break;
+        }
+        if arg.len() < 2 || !arg.starts_with('-') {
+            operands.push(arg.as_str());
+        }
+    }
+    operands
+}
+
+/// The options a program accepts, as [`parse_options`] needs them.
+struct OptionTable {
+    /// Short options that take a value.
+    short_with_value: &'static str,
+    /// Every long option, without the leading `--`. Those that take a value
+    /// end in `=`; options whose value is optional only take one after `=`.
+    long: &'static [&'static str],
+    /// Whether `--no-<option>` turns off any option that takes no value.
+    negatable: bool,
+}
+
+/// An option missing from a table is refused rather than guessed at, so the
+/// tables only list options the program is known to have. Taking a value
+/// where the program does not would hide the next argument from the rules,
+/// so an option is only marked with `=` when it always takes one.
+const CURL_OPTIONS: OptionTable = OptionTable {
+    short_with_value: "ACDEFHKPQTUXYbcdehmortuwxyz",
+    long: &[
+        "abstract-unix-socket=", "alt-svc=", "anyauth", "append", "aws-sigv4=", "basic", "ca-native", "cacert=",
+        "capath=", "cert=", "cert-status", "cert-type=", "ciphers=", "compressed", "compressed-ssh", "config=",
+        "connect-timeout=", "connect-to=", "continue-at=", "cookie=", "cookie-jar=", "create-dirs",
+        "create-file-mode=", "crlf", "crlfile=", "curves=", "data=", "data-ascii=", "data-binary=", "data-raw=",
+        "data-urlencode=", "delegation=", "digest", "disable", "disable-eprt", "disable-epsv",
+        "disallow-username-in-url", "dns-interface=", "dns-ipv4-addr=", "dns-ipv6-addr=", "dns-servers=",
+        "doh-cert-status", "doh-insecure", "doh-url=", "dump-header=", "egd-file=", "engine=", "etag-compare=",
+        "etag-save=", "expect100-timeout=", "fail", "fail-early", "fail-with-body", "false-start", "form=",
+        "form-escape", "form-string=", "ftp-account=", "ftp-alternative-to-user=", "ftp-create-dirs",
+        "ftp-method=", "ftp-pasv", "ftp-port=", "ftp-pret", "ftp-skip-pasv-ip", "ftp-ssl-ccc", "ftp-ssl-ccc-mode=",
+        "ftp-ssl-control", "get", "globoff", "happy-eyeballs-timeout-ms=", "haproxy-clientip=", "haproxy-protocol",
+        "head", "header=", "help", "hostpubmd5=", "hostpubsha256=", "hsts=", "http0.9", "http1.0", "http1.1",
+        "http2", "http2-prior-knowledge", "http3", "http3-only", "ignore-content-length", "include", "insecure",
+        "interface=", "ipv4", "ipv6", "json=", "junk-session-cookies", "keepalive-time=", "key=", "key-type=",
+        "krb=", "libcurl=", "limit-rate=", "list-only", "local-port=", "location", "location-trusted",
+        "login-options=", "mail-auth=", "mail-from=", "mail-rcpt=", "mail-rcpt-allowfails", "manual",
+        "max-filesize=", "max-redirs=", "max-time=", "negotiate", "netrc", "netrc-file=", "netrc-optional", "next",
+        "no-alpn", "no-buffer", "no-keepalive", "no-npn", "no-progress-meter", "no-sessionid", "noproxy=", "ntlm",
+        "oauth2-bearer=", "output=", "output-dir=", "parallel", "parallel-immediate", "parallel-max=", "pass=",
+        "path-as-is", "pinnedpubkey=", "post301", "post302", "post303", "preproxy=", "progress-bar", "proto=",
+        "proto-default=", "proto-redir=", "proxy=", "proxy-anyauth", "proxy-basic", "proxy-cacert=",
+        "proxy-capath=", "proxy-cert=", "proxy-cert-type=", "proxy-ciphers=", "proxy-crlfile=", "proxy-digest",
+        "proxy-header=", "proxy-insecure", "proxy-key=", "proxy-key-type=", "proxy-negotiate", "proxy-ntlm",
+        "proxy-pass=", "proxy-pinnedpubkey=", "proxy-service-name=", "proxy-ssl-allow-beast",
+        "proxy-tls13-ciphers=", "proxy-tlsauthtype=", "proxy-tlspassword=", "proxy-tlsuser=", "proxy-tlsv1",
+        "proxy-user=", "proxy1.0=", "proxytunnel", "pubkey=", "quote=", "random-file=", "range=", "rate=", "raw",
+        "referer=", "remote-header-name", "remote-name", "remote-name-all", "remote-time", "remove-on-error",
+        "request=", "request-target=", "resolve=", "retry=", "retry-all-errors", "retry-connrefused",
+        "retry-delay=", "retry-max-time=", "sasl-authzid=", "sasl-ir", "service-name=", "show-error", "silent",
+        "socks4=", "socks4a=", "socks5=", "socks5-basic", "socks5-gssapi", "socks5-gssapi-nec",
+        "socks5-gssapi-service=", "socks5-hostname=", "speed-limit=", "speed-time=", "ssl", "ssl-allow-beast",
+        "ssl-no-revoke", "ssl-reqd", "ssl-revoke-best-effort", "ssl-sessions=", "sslv2", "sslv3", "stderr=",
+        "styled-output", "suppress-connect-headers", "tcp-fastopen", "tcp-nodelay", "telnet-option=",
+        "tftp-blksize=", "tftp-no-options", "time-cond=", "tls-max=", "tls13-ciphers=", "tlsauthtype=",
+        "tlspassword=", "tlsuser=", "tlsv1", "tlsv1.0", "tlsv1.1", "tlsv1.2", "tlsv1.3", "tr-encoding", "trace=",
+        "trace-ascii=", "trace-config=", "trace-ids", "trace-time", "unix-socket=", "upload-file=", "url=",
+        "url-query=", "use-ascii", "user=", "user-agent=", "variable=", "verbose", "version", "write-out=", "xattr",
+    ],
+    negatable: true,
+};
+
+const WGET_OPTIONS: OptionTable = OptionTable {
+    short_with_value: "ADIOPQRTUXaelotw",
+    long: &[
+        "accept=", "accept-regex=", "adjust-extension", "append-output=", "ask-password", "auth-no-challenge",
+        "background", "backup-converted", "backups=", "base=", "bind-address=", "body-data=", "body-file=",
+        "ca-certificate=", "ca-directory=", "certificate=", "certificate-type=", "compression=", "config=",
+        "connect-timeout=", "content-disposition", "content-on-error", "continue", "convert-file-only",
+        "convert-links", "crl-file=", "cut-dirs=", "debug", "default-page=", "delete-after", "directory-prefix=",
+        "dns-timeout=", "domains=", "egd-file=", "exclude-directories=", "exclude-domains=", "execute=",
+        "follow-ftp", "follow-tags=", "force-directories", "force-html", "ftp-password=", "ftp-user=",
+        "header=", "help", "hsts-file=", "http-password=", "http-user=", "https-only", "ignore-case",
+        "ignore-length", "ignore-tags=", "include-directories=", "inet4-only", "inet6-only", "input-file=",
+        "keep-session-cookies", "level=", "limit-rate=", "load-cookies=", "local-encoding=", "max-redirect=",
+        "method=", "mirror", "no-cache", "no-check-certificate", "no-clobber", "no-config", "no-cookies",
+        "no-directories", "no-dns-cache", "no-glob", "no-host-directories", "no-hsts", "no-http-keep-alive",
+        "no-if-modified-since", "no-iri", "no-netrc", "no-parent", "no-passive-ftp", "no-proxy",
+        "no-remove-listing", "no-use-server-timestamps", "no-verbose", "output-document=", "output-file=",
+        "page-requisites", "password=", "pinnedpubkey=", "post-data=", "post-file=", "prefer-family=",
+        "preserve-permissions", "private-key=", "private-key-type=", "progress=", "protocol-directories",
+        "proxy-password=", "proxy-user=", "quiet", "quota=", "random-file=", "random-wait", "read-timeout=",
+        "recursive", "referer=", "reject=", "reject-regex=", "rejected-log=", "relative", "remote-encoding=",
+        "restrict-file-names=", "retr-symlinks", "retry-connrefused", "retry-on-http-error=", "save-cookies=",
+        "save-headers", "secure-protocol=", "server-response", "show-progress", "span-hosts", "spider",
+        "start-pos=", "strict-comments", "timeout=", "timestamping", "tries=", "trust-server-names", "unlink",
+        "user=", "user-agent=", "verbose", "version", "wait=", "waitretry=", "warc-cdx", "warc-dedup=",
+        "warc-file=", "warc-header=", "warc-max-size=", "warc-tempdir=",
+    ],
+    negatable: true,
+};
+
+const GREP_OPTIONS: OptionTable = OptionTable {
+    short_with_value: "ABCDdefm",
+    long: &[
+        "after-context=", "basic-regexp", "before-context=", "binary", "binary-files=", "byte-offset", "color",
+        "colour", "context=", "count", "dereference-recursive", "devices=", "directories=", "exclude=",
+        "exclude-dir=", "exclude-from=", "extended-regexp", "file=", "files-with-matches", "files-without-match",
+        "fixed-strings", "group-separator=", "help", "ignore-case", "include=", "initial-tab", "invert-match",
+        "label=", "line-buffered", "line-number", "line-regexp", "max-count=", "no-filename",
+        "no-group-separator", "no-ignore-case", "no-messages", "null", "null-data", "only-matching",
+        "perl-regexp", "quiet", "recursive", "regexp=", "silent", "text", "version", "with-filename",
+        "word-regexp",
+    ],
+    negatable: false,
+};
+
+/// A command line split into options and operands.
+struct ParsedArgs<'a> {
+    /// Each option with its value, if it takes one: `-sSo out` gives `-s`,
+    /// `-S` and `-o` with `out`; `--output=out` gives `--output` with `out`.
+    options: Vec<(String, Option<&'a str>)>,
+    operands: Vec<&'a str>,
+}
+
+/// Split `args` the way getopt does, with the options in `table`. Long
+/// options are named by their full name even when abbreviated; everything
+/// after `--` is an operand. Fails on a long option that is not in the table
+/// or is an abbreviation of more than one.
+fn parse_options<'a>(args: &'a [String], table: &OptionTable) -> Result<ParsedArgs<'a>, String> {
+    let mut parsed = ParsedArgs { options: Vec::new(), operands: Vec::new() };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            parsed.operands.extend(iter.map(String::as_str));
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let (name, takes_value) = resolve_long(name, table)?;
+            let value = match value {
+                None if takes_value => iter.next().map(String::as_str),
+                value => value,
+            };
+            parsed.options.push((format!("--{}", name), value));
+        } else if let Some(cluster) = arg.strip_prefix('-').filter(|c| !c.is_empty()) {
+            for (i, c) in cluster.char_indices() {
+                if !table.short_with_value.contains(c) {
+                    parsed.options.push((format!("-{}", c), None));
+                    continue;
+                }
+                let rest = &cluster[i + c.len_utf8()..];
+                let value = if rest.is_empty() { iter.next().map(String::as_str) } else { Some(rest) };
+                parsed.options.push((format!("-{}", c), value));
+                break;
+            }
+        } else {
+            parsed.operands.push(arg);
+        }
+    }
+    Ok(parsed)
+}
+
+/// The full name of the long option `name` stands for, and whether it takes
+/// a value. Like getopt, an exact name wins and otherwise any prefix of just
+/// one option will do.
+fn resolve_long(name: &str, table: &OptionTable) -> Result<(String, bool), String> {
+    let matching = |name: &str| {
+        let found: Vec<&'static str> =
+            table.long.iter().copied().filter(|option| option.trim_end_matches('=').starts_with(name)).collect();
+        match found.iter().find(|option| option.trim_end_matches('=') == name) {
+            Some(&exact) => vec![exact],
+            None => found,
+        }
+    };
+    let mut found = matching(name);
+    let mut negated = false;
+    if let Some(option) = name.strip_prefix("no-").filter(|_| found.is_empty() && table.negatable) {
+        found = matching(option).into_iter().filter(|option| !option.ends_with('=')).collect();
+        negated = true;
+    }
+    match found[..] {
+        [option] => {
+            let full = option.trim_end_matches('=');
+            let full = if negated { format!("no-{}", full) } else { full.to_string() };
+            Ok((full, option.ends_with('=')))
+        }
+        [] => Err(format!("--{} is not an option the policy knows, so it cannot be checked.", name)),
+        _ => {
+            let options: Vec<String> = found.iter().map(|option| format!("--{}", option.trim_end_matches('='))).collect();
+            Err(format!("--{} is ambiguous; it could be {}.", name, options.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authorize, authorize_redirect, Denial, PolicyContext};
+    use crate::terminal::commands::desktop::{CommandList, Executables, Profile};
+    use crate::terminal::testing::TempDir;
+    use std::path::Path;
+
+    const INSTALLED: &[&str] = &["grep", "curl", "wget", "vim", "echo"];
+
+    fn argv(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// `line` as authorized: spawned programs resolved to `/usr/bin`.
+    fn resolved(line: &str) -> Vec<String> {
+        argv(&format!("/usr/bin/{}", line))
+    }
+
+    fn check_in(
+        profile: Profile,
+        cwd: &Path,
+        workspace: &Path,
+        line: &str,
+        passthrough: bool,
+    ) -> Result<Vec<String>, Denial> {
+        let argv = argv(line);
+        let commands = CommandList::default();
+        let executables = Executables::stub(INSTALLED);
+        let ctx = PolicyContext {
+            profile,
+            commands: &commands,
+            executables: &executables,
+            cwd,
+            workspace,
+            jail: None,
+        };
+        authorize(ctx, &argv[0], &argv, passthrough)
+    }
+
+    fn check_as(profile: Profile, line: &str, passthrough: bool) -> Result<Vec<String>, Denial> {
+        let dir = std::env::temp_dir();
+        check_in(profile, &dir, &dir, line, passthrough)
+    }
+
+    fn check(line: &str, passthrough: bool) -> Result<Vec<String>, Denial> {
+        check_as(Profile::Dev, line, passthrough)
+    }
+
+    fn denied_by(line: &str) -> &'static str {
+        check(line, false).expect_err(line).rule
+    }
+
+    #[test]
+    fn allows_read_only_commands() {
+        assert_eq!(check("ls -la", false), Ok(argv("ls -la")));
+        assert_eq!(check("grep -rn x .", true), Ok(resolved("grep -rn x .")));
+        assert_eq!(check("curl -sSL -H X-A:b https://x", false), Ok(resolved("curl -q -sSL -H X-A:b https://x")));
+    }
+
+    #[test]
+    fn denies_unknown_commands_and_names_the_rule() {
+        let denial = check("python3 -c 1", false).unwrap_err();
+        assert_eq!(denial.rule, "allowlist");
+        assert!(denial.reason.contains("'python3' is not allowed"), "{denial}");
+        assert!(denial.to_string().ends_with("[policy: allowlist]"), "{denial}");
+    }
+
+    #[test]
+    fn spawned_commands_need_a_resolved_binary() {
+        let denial = check("ip addr", false).unwrap_err();
+        assert_eq!(denial.rule, "resolved-binary");
+        assert!(denial.reason.starts_with("ip: "), "{denial}");
+        // Builtins only need the binary when `command` asks for it.
+        assert!(check("cat notes.txt", false).is_ok());
+        assert_eq!(check("cat notes.txt", true).unwrap_err().rule, "resolved-binary");
+    }
+
+    #[test]
+    fn commands_file_allows_denies_and_pins() {
+        let dir = TempDir::new("policy_commands");
+        let (git, rg) = (dir.join("git"), dir.join("rg"));
+        for binary in [&git, &rg] {
+            std::fs::write(binary, "#!/bin/sh\n").expect("write binary");
+            #[cfg(not(target_os = "windows"))]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(binary, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+            }
+        }
+        let path = dir.join("commands");
+        let list = format!("allow git {}\nallow rg {}\ndeny curl\n", git.display(), rg.display());
+        std::fs::write(&path, list).expect("write commands file");
+
+        let commands = CommandList::load(path.clone());
+        let executables = commands.resolve_executables(Profile::Dev);
+        let ctx = PolicyContext {
+            profile: Profile::Dev,
+            commands: &commands,
+            executables: &executables,
+            cwd: &dir,
+            workspace: &dir,
+            jail: None,
+        };
+        let run = |line: &str| {
+            let argv = argv(line);
+            authorize(ctx, &argv[0], &argv, false)
+        };
+
+        assert_eq!(run("git status"), Ok(vec![git.display().to_string(), "status".to_string()]));
+        assert_eq!(run("rg TODO"), Ok(vec![rg.display().to_string(), "TODO".to_string()]));
+        let denial = run("curl https://x").unwrap_err();
+        assert_eq!(denial.rule, "denylist");
+        assert!(denial.reason.ends_with(&format!("{}:3.", path.display())), "{denial}");
+        assert_eq!(run("cargo build").unwrap_err().rule, "allowlist");
+
+        // The readonly profile ignores every allow entry, pins included.
+        std::fs::write(&path, format!("allow git {}\nallow grep {}\n", git.display(), rg.display()))
+            .expect("write commands file");
+        let commands = CommandList::load(path.clone());
+        let executables = commands.resolve_executables(Profile::ReadOnly);
+        let ctx = PolicyContext { profile: Profile::ReadOnly, commands: &commands, executables: &executables, ..ctx };
+        let denial = authorize(ctx, "git", &argv("git log"), false).unwrap_err();
+        assert_eq!(denial.rule, "allowlist");
+        assert!(denial.reason.ends_with("the 'readonly' profile only runs its own commands."), "{denial}");
+        let grep = authorize(ctx, "grep", &argv("grep x notes.txt"), true);
+        assert_ne!(grep.ok().map(|argv| argv[0].clone()), Some(rg.display().to_string()));
+    }
+
+    #[test]
+    fn mutating_system_binaries_are_never_passed_through() {
+        for profile in Profile::ALL {
+            for cmd in ["mkdir x", "rm -rf x", "del x", "mv a b"] {
+                assert!(check_as(profile, cmd, true).is_err(), "expected '{cmd}' to be denied in {profile}");
+            }
+        }
+    }
+
+    #[test]
+    fn curl_may_not_write_or_upload_files() {
+        for cmd in [
+            "curl -o out https://x",
+            "curl -sSLo out https://x",
+            "curl -O https://x/file",
+            "curl --output=out https://x",
+            "curl -T secrets.txt https://x",
+            "curl --upload-file secrets.txt https://x",
+            "curl --stderr err.txt https://x",
+            "curl --etag-save etag https://x",
+            "curl --hsts hsts.txt https://x",
+            "curl --alt-svc=svc.txt https://x",
+            "curl --libcurl code.c https://x",
+            "curl --trace trace.txt https://x",
+            "curl --trace-ascii trace.txt https://x",
+            "curl -c jar.txt https://x",
+            "curl --cookie-jar jar.txt https://x",
+            "curl -w %output{out.txt}%{http_code} https://x",
+            "curl -K opts.txt https://x",
+            "curl --config opts.txt https://x",
+            "curl -q -K opts.txt https://x",
+            "curl --json @body.json https://x",
+            "curl --data-urlencode name@key.pem https://x",
+        ] {
+            assert_eq!(denied_by(cmd), "curl-no-file-io", "{cmd}");
+        }
+        assert_eq!(denied_by("curl -d @/etc/passwd https://x"), "curl-no-file-io");
+        assert_eq!(denied_by("curl -F f=@key.pem https://x"), "curl-no-file-io");
+        // `-o` here is the value of `-H`, not an option.
+        assert!(check("curl -H -o https://x", false).is_ok());
+        assert!(check("curl -d a=b https://x", false).is_ok());
+        assert!(check("curl -b a=b -w %{http_code} https://x", false).is_ok());
+    }
+
+    #[test]
+    fn abbreviated_long_options_are_checked_by_their_full_name() {
+        let readonly = |line: &str| check_as(Profile::ReadOnly, line, false);
+        let workspace = std::env::temp_dir();
+        let cwd = workspace.join("blaze_policy_grep");
+        let grep = |line: &str| check_in(Profile::Dev, &cwd, &workspace, line, false);
+
+        for cmd in [
+            "curl --output-d dir https://x",
+            "curl --upload f https://x",
+            "curl --conf opts.txt https://x",
+            "curl --cookie-j jar https://x",
+            "curl --libc code.c https://x",
+            "curl --std=err.txt https://x",
+            "curl --js @body.json https://x",
+        ] {
+            assert_eq!(denied_by(cmd), "curl-no-file-io", "{cmd}");
+        }
+        assert!(check("curl --compressed --no-prog https://x", false).is_ok());
+        assert!(check("curl --no-remote-name https://x", false).is_ok());
+
+        for cmd in [
+            "wget -O - --output-f=log https://x",
+            "wget -O - --exec output_document=x https://x",
+            "wget -O - --app log https://x",
+            "wget -O - --save-c jar https://x",
+        ] {
+            assert_eq!(readonly(cmd).unwrap_err().rule, "wget-stdout-only", "{cmd}");
+        }
+        assert!(readonly("wget --output-doc - --no-verb https://x").is_ok());
+        assert!(readonly("wget --output-doc=- https://x").is_ok());
+
+        for cmd in ["grep --recur x /", "grep --dereference-r x /", "grep --direc=recurse x /", "grep --direc recurse x /"] {
+            assert_eq!(grep(cmd).unwrap_err().rule, "grep-recursive-in-workspace", "{cmd}");
+        }
+        assert!(grep("grep --ignore-c --regexp=x /etc/hosts").is_ok());
+    }
+
+    #[test]
+    fn unknown_and_ambiguous_long_options_are_refused() {
+        for cmd in [
+            "curl --outp out https://x",
+            "curl --bogus https://x",
+            "wget --no-output-document x https://x",
+            "grep --re x /",
+            "grep --colors x .",
+        ] {
+            let denial = check(cmd, false).unwrap_err();
+            assert_eq!(denial.rule, "known-options", "{cmd}");
+        }
+        let denial = check("curl --outp out https://x", false).unwrap_err();
+        assert_eq!(denial.reason, "curl: --outp is ambiguous; it could be --output, --output-dir.");
+        let denial = check("grep --bogus x .", false).unwrap_err();
+        assert_eq!(denial.reason, "grep: --bogus is not an option the policy knows, so it cannot be checked.");
+        // A value that looks like a long option is not one.
+        assert!(check("curl -H --bogus https://x", false).is_ok());
+        assert!(check("grep -e --bogus notes.txt", false).is_ok());
+    }
+
+    #[test]
+    fn curl_never_reads_curlrc() {
+        assert_eq!(check("curl https://x", true), Ok(resolved("curl -q https://x")));
+        assert_eq!(check("curl -q https://x", false), Ok(resolved("curl -q https://x")));
+    }
+
+    #[test]
+    fn recursive_grep_stays_inside_the_workspace() {
+        let workspace = std::env::temp_dir();
+        let cwd = workspace.join("blaze_policy_grep");
+        let run = |line: &str| check_in(Profile::Dev, &cwd, &workspace, line, false);
+
+        assert!(run("grep -rn TODO").is_ok());
+        assert!(run("grep -r TODO sub/dir ..").is_ok());
+        assert!(run("grep TODO /etc/passwd").is_ok());
+        assert!(run("grep -e -r /etc").is_ok());
+        for cmd in ["grep -r root /", "grep -rn root ../..", "grep --recursive -e x /", "grep -d recurse x /"] {
+            assert_eq!(run(cmd).unwrap_err().rule, "grep-recursive-in-workspace", "{cmd}");
+        }
+    }
+
+    #[test]
+    fn jailed_builtins_only_take_paths_inside_the_workspace() {
+        use crate::terminal::commands::desktop::Jail;
+
+        let root = TempDir::new("policy_jail");
+        std::fs::create_dir_all(root.join("sub")).expect("create test dir");
+        let jail = Jail::new(&root).expect("jail");
+        let (commands, executables) = (CommandList::default(), Executables::stub(INSTALLED));
+        let ctx = PolicyContext {
+            profile: Profile::Full,
+            commands: &commands,
+            executables: &executables,
+            cwd: &root.join("sub"),
+            workspace: jail.root(),
+            jail: Some(&jail),
+        };
+        let run = |line: &str| {
+            let argv = argv(line);
+            authorize(ctx, &argv[0], &argv, false)
+        };
+
+        for cmd in ["ls", "ls -la ..", "cat notes.txt", "grep -n x ../a.txt", "mv a ../b", "rm -r ../sub/new"] {
+            assert!(run(cmd).is_ok(), "{cmd}");
+        }
+        for cmd in ["ls ../..", "cat /etc/passwd", "grep x /etc/hosts", "mv a ../../b", "mkdir /tmp/x"] {
+            let denial = run(cmd).unwrap_err();
+            assert_eq!(denial.rule, "workspace-jail", "{cmd}");
+            assert!(denial.reason.contains("outside the workspace"), "{denial}");
+        }
+        // Other programs are not confined.
+        assert!(run("curl https://x").is_ok());
+    }
+
+    #[test]
+    fn redirection_to_files_follows_the_mutation_policy() {
+        use crate::terminal::commands::desktop::Jail;
+
+        let root = TempDir::new("policy_redirect");
+        let jail = Jail::new(&root).expect("jail");
+        let (commands, executables) = (CommandList::default(), Executables::stub(INSTALLED));
+        let ctx = |profile, jail| PolicyContext {
+            profile,
+            commands: &commands,
+            executables: &executables,
+            cwd: &root,
+            workspace: &root,
+            jail,
+        };
+
+        let null = if cfg!(target_os = "windows") { "NUL" } else { "/dev/null" };
+        assert_eq!(authorize_redirect(ctx(Profile::Full, None), "out.txt"), Ok(root.join("out.txt")));
+        for profile in [Profile::ReadOnly, Profile::Dev] {
+            let denial = authorize_redirect(ctx(profile, None), "out.txt").unwrap_err();
+            assert_eq!(denial.rule, "fs-mutations-disabled");
+            assert!(authorize_redirect(ctx(profile, None), null).is_ok());
+        }
+        let denial = authorize_redirect(ctx(Profile::Full, Some(&jail)), "../out.txt").unwrap_err();
+        assert_eq!(denial.rule, "workspace-jail");
+        assert!(authorize_redirect(ctx(Profile::Full, Some(&jail)), "logs/out.txt").is_ok());
+    }
+
+    #[test]
+    fn readonly_wget_only_writes_to_the_terminal() {
+        for cmd in ["wget -O - https://x", "wget -qO- https://x", "wget --output-document=- https://x"] {
+            assert!(check_as(Profile::ReadOnly, cmd, false).is_ok(), "{cmd}");
+        }
+        for cmd in [
+            "wget https://x",
+            "wget -O out https://x",
+            "wget -O - -o log https://x",
+            "wget -O - -e output_document=x https://x",
+        ] {
+            let denial = check_as(Profile::ReadOnly, cmd, false).unwrap_err();
+            assert_eq!(denial.rule, "wget-stdout-only", "{cmd}");
+        }
+        assert!(check("wget https://x", false).is_ok());
+    }
+
+    #[test]
+    fn mutating_commands_are_disabled_outside_the_full_profile() {
+        for profile in [Profile::ReadOnly, Profile::Dev] {
+            for cmd in ["mkdir x", "rm -r x", "del x", "mv a b"] {
+                let denial = check_as(profile, cmd, false).unwrap_err();
+                assert_eq!(denial.rule, "fs-mutations-disabled");
+                assert!(denial.reason.contains(&format!("disabled in the '{profile}' profile")), "{denial}");
+            }
+        }
+    }
+
+    #[test]
+    fn builtins_leave_the_filesystem_untouched_outside_the_full_profile() {
+        use crate::terminal::commands::desktop::{run_native_builtin, Trash};
+
+        let dir = TempDir::new("policy");
+        let cwd = dir.to_string_lossy().to_string();
+        let trash = Trash::at(dir.join(".trash"));
+        std::fs::write(dir.join("keep.txt"), "data").expect("write test file");
+
+        for cmd in ["mkdir new", "rm keep.txt", "mv keep.txt moved.txt"] {
+            let argv = argv(cmd);
+            let (lines, outcome) = run_native_builtin(Profile::Dev, &trash, &cwd, &argv[0], &argv, None).expect("builtin");
+            assert!(!outcome.success(), "expected '{cmd}' to fail");
+            assert!(lines.iter().any(|l| l.content.contains("disabled in the 'dev' profile")));
+        }
+        assert!(!dir.join("new").exists());
+        assert!(dir.join("keep.txt").exists());
+        assert!(!dir.join("moved.txt").exists());
+    }
+
+    #[test]
+    fn mutating_builtins_are_allowed_in_the_full_profile() {
+        assert_eq!(check_as(Profile::Full, "rm -r x", false), Ok(argv("rm -r x")));
+        assert_eq!(check_as(Profile::Full, "rm -r x", true).unwrap_err().rule, "fs-builtins-only");
+    }
+
+    #[test]
+    fn rm_builtin_requires_r_for_directories() {
+        use crate::terminal::commands::desktop::{run_native_builtin, Trash};
+
+        let dir = TempDir::new("policy_rm");
+        std::fs::create_dir_all(dir.join("sub")).expect("create test dir");
+        let cwd = dir.to_string_lossy().to_string();
+        let trash = Trash::at(dir.join(".trash"));
+
+        let (lines, outcome) = run_native_builtin(Profile::Full, &trash, &cwd, "rm", &argv("rm sub"), None).expect("builtin");
+        assert!(!outcome.success());
+        assert!(lines.iter().any(|l| l.content.contains("use rm -r")));
+        assert!(dir.join("sub").exists());
+
+        let (_, outcome) = run_native_builtin(Profile::Full, &trash, &cwd, "rm", &argv("rm -r sub"), None).expect("builtin");
+        assert!(outcome.success());
+        assert!(!dir.join("sub").exists());
+    }
+
+    // vim is not spawned on Windows.
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn readonly_opens_vim_read_only() {
+        assert_eq!(check_as(Profile::ReadOnly, "vim notes.txt", false), Ok(resolved("vim -M -Z notes.txt")));
+        assert_eq!(check("vim notes.txt", false), Ok(resolved("vim notes.txt")));
+    }
+}
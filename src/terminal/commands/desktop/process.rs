@@ -1,74 +1,94 @@
 #[cfg(not(target_os = "windows"))]
 use dioxus::prelude::*;
 
+#[cfg(not(target_os = "windows"))]
+use super::job::ForegroundJob;
+use super::limits::{ExecLimits, LimitExceeded};
+#[cfg(not(target_os = "windows"))]
+use super::pty::{spawn_in_pty, PtyChild, PtyControl};
+#[cfg(not(target_os = "windows"))]
+use super::sandbox::Sandbox;
+#[cfg(not(target_os = "windows"))]
+use crate::terminal::screen::Screen;
 #[cfg(not(target_os = "windows"))]
 use crate::terminal::utils::push_line_trim;
 #[cfg(target_os = "windows")]
 use crate::terminal::utils::windows_hidden_command;
-#[cfg(target_os = "windows")]
-use crate::terminal::state::LineType;
-use crate::terminal::state::TerminalLine;
-
-#[cfg(all(feature = "desktop", target_os = "windows"))]
-const MAX_CMD_OUTPUT_BYTES: usize = 1024 * 1024;
-
-#[cfg(all(feature = "desktop", target_os = "windows", not(test)))]
-fn max_cmd_runtime() -> std::time::Duration {
-    std::time::Duration::from_secs(15)
-}
-
-#[cfg(all(feature = "desktop", target_os = "windows", test))]
-fn max_cmd_runtime() -> std::time::Duration {
-    std::time::Duration::from_millis(250)
-}
+use crate::terminal::state::{ExitOutcome, LineType, TermSize, TerminalLine};
+#[cfg(not(target_os = "windows"))]
+use crate::terminal::state::CommandStatus;
 
 #[cfg(target_os = "windows")]
 pub fn handle_windows_process_command(
     cwd: &str,
     program: &str,
     argv: &[String],
-) -> Option<Vec<TerminalLine>> {
+    size: TermSize,
+    limits: ExecLimits,
+) -> Option<(Vec<TerminalLine>, ExitOutcome)> {
     match program {
         "echo" => {
             let text = argv.iter().skip(1).cloned().collect::<Vec<_>>().join(" ");
-            Some(vec![TerminalLine {
-                content: text,
-                line_type: LineType::Output,
-            }])
+            Some((
+                vec![TerminalLine {
+                    content: text,
+                    line_type: LineType::Output,
+                    spans: Vec::new(),
+                }],
+                ExitOutcome::Code(0),
+            ))
         }
         "whoami" => {
             let user = std::env::var("USERNAME")
                 .or_else(|_| std::env::var("USER"))
                 .unwrap_or_else(|_| "unknown".to_string());
-            Some(vec![TerminalLine {
-                content: user,
-                line_type: LineType::Output,
-            }])
-        }
-        "vim" => Some(vec![TerminalLine {
-            content: "vim is not supported in this UI (interactive TTY required).".into(),
-            line_type: LineType::Error,
-        }]),
+            Some((
+                vec![TerminalLine {
+                    content: user,
+                    line_type: LineType::Output,
+                    spans: Vec::new(),
+                }],
+                ExitOutcome::Code(0),
+            ))
+        }
+        "vim" => Some((
+            vec![TerminalLine {
+                content: "vim is not supported in this UI (interactive TTY required).".into(),
+                line_type: LineType::Error,
+                spans: Vec::new(),
+            }],
+            ExitOutcome::Code(1),
+        )),
         "ip" => {
             let extra_args = argv.iter().skip(1).cloned().collect::<Vec<_>>();
-            Some(run_external_command_lines(cwd, "ipconfig", &extra_args))
+            // Resolved to `ipconfig` by the policy.
+            Some(run_external_command_lines(cwd, &argv[0], &extra_args, size, limits))
         }
         "ipconfig" | "curl" | "wget" => {
             let extra_args = argv.iter().skip(1).cloned().collect::<Vec<_>>();
-            Some(run_external_command_lines(cwd, program, &extra_args))
+            Some(run_external_command_lines(cwd, &argv[0], &extra_args, size, limits))
         }
         _ => None,
     }
 }
 
 #[cfg(target_os = "windows")]
-fn run_external_command_lines(cwd: &str, program: &str, args: &[String]) -> Vec<TerminalLine> {
+pub(super) fn run_external_command_lines(
+    cwd: &str,
+    program: &str,
+    args: &[String],
+    size: TermSize,
+    limits: ExecLimits,
+) -> (Vec<TerminalLine>, ExitOutcome) {
     use std::process::Stdio;
     use std::thread;
     use std::time::Instant;
 
     let child = windows_hidden_command(program, cwd)
         .args(args)
+        .env("COLUMNS", size.cols.to_string())
+        .env("LINES", size.rows.to_string())
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn();
@@ -76,34 +96,39 @@ fn run_external_command_lines(cwd: &str, program: &str, args: &[String]) -> Vec<
     let mut child = match child {
         Ok(c) => c,
         Err(e) => {
-            return vec![TerminalLine {
-                content: format!("{}: {}", program, e),
-                line_type: LineType::Error,
-            }]
+            return (
+                vec![TerminalLine {
+                    content: format!("{}: {}", program, e),
+                    line_type: LineType::Error,
+                    spans: Vec::new(),
+                }],
+                ExitOutcome::from_spawn_error(&e),
+            )
         }
     };
 
     let start = Instant::now();
-    let max_runtime = max_cmd_runtime();
     loop {
         match child.try_wait() {
             Ok(Some(_)) => break,
             Ok(None) => {
-                if start.elapsed() >= max_runtime {
+                if let Some(limit) = limits.timeout.filter(|limit| start.elapsed() >= *limit) {
                     let _ = child.kill();
                     let _ = child.wait();
-                    return vec![TerminalLine {
-                        content: format!("{}: timed out after {}s", program, max_runtime.as_secs()),
-                        line_type: LineType::Error,
-                    }];
+                    // 124 is what coreutils' `timeout` reports.
+                    return (vec![LimitExceeded::Timeout(limit).notice(program)], ExitOutcome::Code(124));
                 }
                 thread::sleep(std::time::Duration::from_millis(50));
             }
             Err(e) => {
-                return vec![TerminalLine {
-                    content: format!("{}: {}", program, e),
-                    line_type: LineType::Error,
-                }]
+                return (
+                    vec![TerminalLine {
+                        content: format!("{}: {}", program, e),
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    }],
+                    ExitOutcome::Code(1),
+                )
             }
         }
     }
@@ -111,10 +136,14 @@ fn run_external_command_lines(cwd: &str, program: &str, args: &[String]) -> Vec<
     let output = match child.wait_with_output() {
         Ok(o) => o,
         Err(e) => {
-            return vec![TerminalLine {
-                content: format!("{}: {}", program, e),
-                line_type: LineType::Error,
-            }]
+            return (
+                vec![TerminalLine {
+                    content: format!("{}: {}", program, e),
+                    line_type: LineType::Error,
+                    spans: Vec::new(),
+                }],
+                ExitOutcome::Code(1),
+            )
         }
     };
 
@@ -122,9 +151,10 @@ fn run_external_command_lines(cwd: &str, program: &str, args: &[String]) -> Vec<
     bytes.extend_from_slice(&output.stdout);
     bytes.extend_from_slice(&output.stderr);
 
-    if bytes.len() > MAX_CMD_OUTPUT_BYTES {
-        bytes.truncate(MAX_CMD_OUTPUT_BYTES);
-        bytes.extend_from_slice(b"\n...(output truncated)\n");
+    let mut exceeded = None;
+    if let Some(limit) = limits.max_output_bytes.filter(|limit| bytes.len() > *limit) {
+        bytes.truncate(limit);
+        exceeded = Some(LimitExceeded::Bytes(limit));
     }
 
     let text = String::from_utf8_lossy(&bytes);
@@ -135,99 +165,191 @@ fn run_external_command_lines(cwd: &str, program: &str, args: &[String]) -> Vec<
     };
 
     let mut out = Vec::new();
-    for line in text.lines() {
+    for (i, line) in text.lines().enumerate() {
+        if let Some(limit) = limits.max_output_lines.filter(|limit| i >= *limit) {
+            exceeded = Some(LimitExceeded::Lines(limit));
+            break;
+        }
         out.push(TerminalLine {
             content: line.to_string(),
             line_type: line_type.clone(),
+            spans: Vec::new(),
         });
     }
+    if let Some(exceeded) = exceeded {
+        out.push(exceeded.notice(program));
+    }
 
     if out.is_empty() {
         out.push(TerminalLine {
             content: String::new(),
             line_type,
+            spans: Vec::new(),
         });
     }
 
-    out
+    (out, ExitOutcome::from(output.status))
 }
 
 #[cfg(not(target_os = "windows"))]
+#[allow(clippy::too_many_arguments)]
 pub async fn stream_unix_command(
     cwd: String,
     program: String,
     program_args: Vec<String>,
-    mut lines: Signal<Vec<TerminalLine>>,
-) {
-    use std::process::Stdio;
-    use tokio::io::{AsyncBufReadExt, BufReader};
-    use tokio::process::Command;
+    lines: Signal<Vec<TerminalLine>>,
+    mut live: Signal<Vec<TerminalLine>>,
+    term_size: Signal<TermSize>,
+    mut foreground: Signal<Option<ForegroundJob>>,
+    limits: ExecLimits,
+    sandbox: Sandbox,
+) -> CommandStatus {
+    use std::io::Read;
+    use std::time::Instant;
     use tokio::sync::mpsc;
 
-    let child = Command::new(&program)
-        .args(&program_args)
-        .current_dir(&cwd)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
+    let started = Instant::now();
+    let size = *term_size.peek();
+    let pty = match spawn_in_pty(&program, &program_args, &cwd, size, &limits, &sandbox) {
+        Ok(pty) => pty,
+        Err(e) => {
+            foreground.set(None);
+            push_line_trim(
+                lines,
+                TerminalLine {
+                    content: format!("Error: {}", e),
+                    line_type: LineType::Error,
+                    spans: Vec::new(),
+                },
+            );
+            return CommandStatus {
+                outcome: ExitOutcome::from_spawn_error(&e),
+                duration: started.elapsed(),
+            };
+        }
+    };
+    let PtyChild { mut child, mut master } = pty;
+    let control = PtyControl::new(&master).ok();
+    if let Some(job) = foreground.write().as_mut() {
+        job.pgid = Some(child.id());
+        job.pty = control.clone();
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
 
-    match child {
-        Ok(mut child) => {
-            let stdout = child.stdout.take();
-            let stderr = child.stderr.take();
-
-            let (tx, mut rx) = mpsc::unbounded_channel::<TerminalLine>();
-
-            if let Some(out) = stdout {
-                let tx_out = tx.clone();
-                spawn(async move {
-                    let mut reader = BufReader::new(out).lines();
-                    while let Ok(Some(line)) = reader.next_line().await {
-                        let _ = tx_out.send(TerminalLine {
-                            content: line,
-                            line_type: LineType::Output,
-                        });
+    // The master is a plain blocking descriptor, so drain it on a dedicated
+    // thread. Reads fail with EIO once every holder of the slave has exited.
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            match master.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).is_err() {
+                        break;
                     }
-                });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
             }
+        }
+    });
 
-            if let Some(err) = stderr {
-                let tx_err = tx.clone();
-                spawn(async move {
-                    let mut reader = BufReader::new(err).lines();
-                    while let Ok(Some(line)) = reader.next_line().await {
-                        let _ = tx_err.send(TerminalLine {
-                            content: line,
-                            line_type: LineType::Error,
-                        });
-                    }
-                });
+    // Rows scrolled off the screen are appended to `lines` for good, while the
+    // screen itself is shown through `live` until the command exits.
+    let mut screen = Screen::new(size.cols as usize, size.rows as usize);
+    let mut output_bytes = 0usize;
+    let mut output_lines = 0usize;
+    let mut exceeded = None;
+    loop {
+        let interactive = || foreground.peek().as_ref().is_some_and(|job| job.interactive);
+        let deadline = limits.timeout.filter(|_| !interactive());
+        let next = match deadline {
+            Some(limit) => tokio::time::timeout_at((started + limit).into(), rx.recv()).await,
+            None => Ok(rx.recv().await),
+        };
+        let chunk = match next {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            // The user may have started interacting while we waited.
+            Err(_) if interactive() => continue,
+            Err(_) => {
+                exceeded = deadline.map(LimitExceeded::Timeout);
+                break;
             }
+        };
 
-            drop(tx);
+        // Output after a resize is drawn for the new geometry (the PTY itself
+        // is resized by the terminal component), so match it before feeding.
+        let size = *term_size.peek();
+        screen.resize(size.cols as usize, size.rows as usize);
+        screen.feed(&chunk);
 
-            while let Some(line) = rx.recv().await {
-                push_line_trim(lines, line);
+        // Full-screen programs redraw in place, so only stream output counts
+        // towards the byte cap.
+        if !screen.is_alternate() {
+            output_bytes += chunk.len();
+            if let Some(limit) = limits.max_output_bytes.filter(|limit| output_bytes > *limit) {
+                exceeded = Some(LimitExceeded::Bytes(limit));
+                break;
+            }
+        }
+        for line in screen.take_scrollback() {
+            output_lines += 1;
+            if let Some(limit) = limits.max_output_lines.filter(|limit| output_lines > *limit) {
+                exceeded = Some(LimitExceeded::Lines(limit));
+                break;
+            }
+            push_line_trim(lines, line);
+        }
+        if exceeded.is_some() {
+            break;
+        }
+        live.set(screen.visible_lines());
+        if screen.is_alternate() {
+            if let Some(job) = foreground.write().as_mut().filter(|job| !job.interactive) {
+                job.interactive = true;
             }
+        }
 
-            let _ = child.wait().await;
+        // Programs turn echo off while reading a password; the input box
+        // follows so the typed secret is masked.
+        let echo = control.as_ref().is_none_or(|c| c.echoes_input());
+        if foreground.peek().as_ref().is_some_and(|job| job.echo != echo) {
+            if let Some(job) = foreground.write().as_mut() {
+                job.echo = echo;
+            }
         }
-        Err(e) => {
-            push_line_trim(
-                lines,
-                TerminalLine {
-                    content: format!("Error: {}", e),
-                    line_type: LineType::Error,
-                },
-            );
+    }
+
+    if exceeded.is_some() {
+        if let Some(job) = foreground.peek().as_ref() {
+            let _ = job.kill();
         }
     }
+    drop(rx);
+
+    let outcome = match tokio::task::spawn_blocking(move || child.wait()).await {
+        Ok(Ok(status)) => ExitOutcome::from(status),
+        _ => ExitOutcome::Code(1),
+    };
+    let exceeded = exceeded.or_else(|| limits.exceeded_by(outcome));
+    let duration = started.elapsed();
+    for line in live.take() {
+        push_line_trim(lines, line);
+    }
+    if let Some(exceeded) = exceeded {
+        push_line_trim(lines, exceeded.notice(&program));
+    }
+    foreground.set(None);
+    CommandStatus { outcome, duration }
 }
 
 #[cfg(all(test, target_os = "windows", feature = "desktop"))]
 mod tests {
     use super::run_external_command_lines;
-    use crate::terminal::state::LineType;
+    use crate::terminal::commands::desktop::limits::ExecLimits;
+    use crate::terminal::state::{ExitOutcome, LineType, TermSize};
 
     #[test]
     fn external_command_times_out_and_reports_error() {
@@ -240,11 +362,16 @@ mod tests {
             "Start-Sleep -Seconds 2".to_string(),
         ];
 
-        let lines = run_external_command_lines(&cwd, "powershell", &args);
+        let limits = ExecLimits {
+            timeout: Some(std::time::Duration::from_millis(250)),
+            ..ExecLimits::default()
+        };
+        let (lines, outcome) = run_external_command_lines(&cwd, "powershell", &args, TermSize::default(), limits);
 
         assert!(
             lines.iter().any(|l| l.content.contains("timed out after") && l.line_type == LineType::Error),
             "expected timeout error line"
         );
+        assert_eq!(outcome, ExitOutcome::Code(124));
     }
 }
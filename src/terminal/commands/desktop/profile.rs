@@ -0,0 +1,128 @@
+//! Runtime security profiles.
+//!
+//! A profile decides which programs may run and how far they may reach:
+//!
+//! * `readonly`: only the programs listed here run, since `allow` entries in
+//!   the commands file are ignored. The mutating builtins and redirection to
+//!   a file are refused, `vim` opens files with `-M -Z`, `wget` may only write
+//!   to the terminal and `curl` may not write or upload files. On Linux the
+//!   Landlock sandbox also keeps every spawned program from writing anywhere
+//!   but `/dev`. Elsewhere that is the only guarantee: `vim`'s `:set write`,
+//!   for one, turns writing back on.
+//! * `dev`: the read-only command set without those restrictions.
+//! * `full`: `dev` plus the mutating builtins `mkdir`, `rm`/`del` and `mv`.
+//!
+//! The profile is chosen at startup with `--profile <name>` or the `profile`
+//! key of the config file. The `safe-mode` and `unsafe-fs` Cargo features
+//! only pick the default.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    ReadOnly,
+    Dev,
+    Full,
+}
+
+/// Programs every profile may run.
+const BASE_COMMANDS: &[&str] = &[
+    "ls", "dir", "echo", "vim", "whoami", "cat", "grep", "curl", "wget", "ip",
+    #[cfg(target_os = "windows")]
+    "type",
+    #[cfg(target_os = "windows")]
+    "ipconfig",
+    #[cfg(not(target_os = "windows"))]
+    "ifconfig",
+];
+
+/// Filesystem-mutating builtins, only available in `full`.
+const MUTATING_COMMANDS: &[&str] = &["mkdir", "rm", "del", "mv"];
+
+impl Default for Profile {
+    fn default() -> Self {
+        if cfg!(feature = "safe-mode") {
+            Self::ReadOnly
+        } else if cfg!(feature = "unsafe-fs") {
+            Self::Full
+        } else {
+            Self::Dev
+        }
+    }
+}
+
+impl Profile {
+    pub const ALL: [Profile; 3] = [Profile::ReadOnly, Profile::Dev, Profile::Full];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ReadOnly => "readonly",
+            Self::Dev => "dev",
+            Self::Full => "full",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                let names = Self::ALL.map(Self::name).join(", ");
+                format!("unknown profile '{}' (expected one of: {})", name.trim(), names)
+            })
+    }
+
+    /// The programs this profile may run, in the order `help` lists them.
+    pub fn external_commands(self) -> Vec<&'static str> {
+        let mut commands = BASE_COMMANDS.to_vec();
+        if self.allows_mutations() {
+            commands.extend_from_slice(MUTATING_COMMANDS);
+        }
+        commands
+    }
+
+    pub fn allows(self, program: &str) -> bool {
+        BASE_COMMANDS.contains(&program)
+            || (self.allows_mutations() && MUTATING_COMMANDS.contains(&program))
+    }
+
+    /// Whether the mutating builtins may run.
+    pub fn allows_mutations(self) -> bool {
+        self == Self::Full
+    }
+
+    /// Whether commands that could write files are tightened or refused
+    /// (`vim` read-only, `wget` to the terminal only).
+    pub fn is_read_only(self) -> bool {
+        self == Self::ReadOnly
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+
+    #[test]
+    fn parses_names_case_insensitively() {
+        assert_eq!(Profile::parse("readonly"), Ok(Profile::ReadOnly));
+        assert_eq!(Profile::parse(" Full "), Ok(Profile::Full));
+        let err = Profile::parse("root").unwrap_err();
+        assert!(err.contains("readonly, dev, full"), "{err}");
+    }
+
+    #[test]
+    fn only_full_includes_mutating_commands() {
+        for profile in [Profile::ReadOnly, Profile::Dev] {
+            assert!(!profile.allows("rm"));
+            assert!(!profile.external_commands().contains(&"mkdir"));
+        }
+        assert!(Profile::Full.allows("rm"));
+        assert!(Profile::Full.external_commands().ends_with(&["mkdir", "rm", "del", "mv"]));
+    }
+}
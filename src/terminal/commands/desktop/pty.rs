@@ -0,0 +1,274 @@
+//! Pseudo-terminal support for the Unix desktop build.
+//!
+//! Every external command gets its own PTY pair: the child becomes the leader of
+//! a new session with the slave side as its controlling terminal (and as
+//! stdin/stdout/stderr), while Blaze keeps the master side to read output from.
+//! This makes `isatty` checks succeed, so programs emit colors and full-screen
+//! tools such as `vim`, `top` or `less` can run.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+
+use super::limits::ExecLimits;
+use super::sandbox::{confine, Sandbox};
+use crate::terminal::state::TermSize;
+
+/// A child process attached to the slave side of a freshly allocated PTY.
+pub struct PtyChild {
+    pub child: Child,
+    /// Master side of the PTY; reading yields everything the child writes to
+    /// its terminal, writing feeds the child's input.
+    pub master: File,
+}
+
+/// A cloneable handle to the master side of a running command's PTY, used to
+/// control the terminal while another task reads its output.
+#[derive(Clone)]
+pub struct PtyControl {
+    master: Arc<File>,
+}
+
+impl PtyControl {
+    pub fn new(master: &File) -> io::Result<Self> {
+        Ok(Self {
+            master: Arc::new(master.try_clone()?),
+        })
+    }
+
+    /// Update the terminal size. The kernel delivers SIGWINCH to the
+    /// foreground process group, so programs re-layout for the new geometry.
+    pub fn resize(&self, size: TermSize) -> io::Result<()> {
+        let winsize = winsize(size);
+        // SAFETY: the descriptor is open for the lifetime of `self` and
+        // `winsize` is a valid pointer for the duration of the call.
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ as _, &winsize) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Send `bytes` to the child as if they were typed on its terminal.
+    pub fn write(&self, bytes: &[u8]) -> io::Result<()> {
+        (&*self.master).write_all(bytes)
+    }
+
+    /// Whether the terminal currently echoes input. Programs turn echo off
+    /// while reading passwords.
+    pub fn echoes_input(&self) -> bool {
+        // SAFETY: `termios` is a zeroed, writable struct and the descriptor is
+        // open for the lifetime of `self`.
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(self.master.as_raw_fd(), &mut termios) == -1 {
+                return true;
+            }
+            termios.c_lflag & libc::ECHO != 0
+        }
+    }
+}
+
+fn winsize(size: TermSize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+fn open_pty(size: TermSize) -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let winsize = winsize(size);
+
+    // SAFETY: all pointers are valid for the duration of the call; name and
+    // termios are optional and passed as null.
+    let rc = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            // Linux takes `*const winsize`, macOS `*mut winsize`.
+            &winsize as *const libc::winsize as _,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: openpty succeeded, so both descriptors are open and owned by us.
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    // Keep the master out of the child (and of any unrelated process we spawn later).
+    set_cloexec(&master)?;
+    set_cloexec(&slave)?;
+
+    Ok((master, slave))
+}
+
+fn set_cloexec(fd: &OwnedFd) -> io::Result<()> {
+    // SAFETY: `fd` is a valid open descriptor for the lifetime of the call.
+    let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: as above.
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Spawn `program` in `cwd` with a new PTY as its controlling terminal.
+///
+/// `COLUMNS`/`LINES` are exported as well, for programs that read the size from
+/// the environment rather than the terminal. Right before exec, the child
+/// lowers its resource limits to `limits` and confines itself with `sandbox`.
+pub fn spawn_in_pty(
+    program: &str,
+    args: &[String],
+    cwd: &str,
+    size: TermSize,
+    limits: &ExecLimits,
+    sandbox: &Sandbox,
+) -> io::Result<PtyChild> {
+    let (master, slave) = open_pty(size)?;
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(cwd)
+        .env("TERM", "xterm-256color")
+        .env("COLORTERM", "truecolor")
+        .env("COLUMNS", size.cols.to_string())
+        .env("LINES", size.rows.to_string())
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+
+    // SAFETY: the closure only calls async-signal-safe functions (setsid, ioctl).
+    unsafe {
+        command.pre_exec(|| {
+            // New session so the PTY can become our controlling terminal and
+            // signals sent to the process group do not reach Blaze itself.
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    confine(&mut command, limits, sandbox)?;
+
+    let child = command.spawn()?;
+    // Dropping the command closes our copies of the slave descriptor, so reads
+    // on the master report EOF/EIO once the child (and its children) exit.
+    drop(command);
+
+    Ok(PtyChild {
+        child,
+        master: File::from(master),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spawn_in_pty, PtyControl};
+    use crate::terminal::commands::desktop::limits::ExecLimits;
+    use crate::terminal::commands::desktop::sandbox::Sandbox;
+    use crate::terminal::state::TermSize;
+    use std::io::Read;
+
+    fn read_all(mut master: std::fs::File) -> String {
+        let mut out = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            match master.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => out.extend_from_slice(&buf[..n]),
+            }
+        }
+        String::from_utf8_lossy(&out).to_string()
+    }
+
+    #[test]
+    fn child_sees_a_terminal_on_stdout() {
+        let args = vec!["-c".to_string(), "test -t 1 && echo tty || echo pipe".to_string()];
+        let mut pty = spawn_in_pty("sh", &args, ".", TermSize::default(), &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let output = read_all(pty.master);
+        let _ = pty.child.wait();
+
+        assert!(output.contains("tty"), "expected a TTY, got {output:?}");
+    }
+
+    #[test]
+    fn child_sees_requested_window_size() {
+        let args = vec!["-c".to_string(), "stty size".to_string()];
+        let size = TermSize { cols: 100, rows: 30 };
+        let mut pty = spawn_in_pty("sh", &args, ".", size, &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let output = read_all(pty.master);
+        let _ = pty.child.wait();
+
+        assert!(output.contains("30 100"), "unexpected stty output {output:?}");
+    }
+
+    #[test]
+    fn resize_updates_the_terminal_size() {
+        let args = vec!["-c".to_string(), "sleep 0.2; stty size".to_string()];
+        let mut pty = spawn_in_pty("sh", &args, ".", TermSize::default(), &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let control = PtyControl::new(&pty.master).expect("clone master");
+        control.resize(TermSize { cols: 120, rows: 40 }).expect("resize");
+        let output = read_all(pty.master);
+        let _ = pty.child.wait();
+
+        assert!(output.contains("40 120"), "unexpected stty output {output:?}");
+    }
+
+    #[test]
+    fn exports_columns_and_lines() {
+        let args = vec!["-c".to_string(), "echo \"$COLUMNS x $LINES\"".to_string()];
+        let size = TermSize { cols: 90, rows: 20 };
+        let mut pty = spawn_in_pty("sh", &args, ".", size, &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let output = read_all(pty.master);
+        let _ = pty.child.wait();
+
+        assert!(output.contains("90 x 20"), "unexpected output {output:?}");
+    }
+
+    #[test]
+    fn written_input_reaches_the_child() {
+        let args = vec!["-c".to_string(), "read line; echo \"got:$line\"".to_string()];
+        let mut pty = spawn_in_pty("sh", &args, ".", TermSize::default(), &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let control = PtyControl::new(&pty.master).expect("clone master");
+        control.write(b"hello\r").expect("write");
+        let output = read_all(pty.master);
+        let _ = pty.child.wait();
+
+        assert!(output.contains("got:hello"), "unexpected output {output:?}");
+    }
+
+    #[test]
+    fn reports_when_echo_is_disabled() {
+        let args = vec!["-c".to_string(), "stty -echo; echo ready; sleep 1".to_string()];
+        let mut pty = spawn_in_pty("sh", &args, ".", TermSize::default(), &ExecLimits::default(), &Sandbox::default()).expect("spawn in pty");
+        let control = PtyControl::new(&pty.master).expect("clone master");
+
+        let mut seen = String::new();
+        let mut buf = [0u8; 256];
+        while !seen.contains("ready") {
+            let n = pty.master.read(&mut buf).expect("read");
+            assert!(n > 0, "child exited early");
+            seen.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        assert!(!control.echoes_input());
+        let _ = pty.child.kill();
+        let _ = pty.child.wait();
+    }
+}
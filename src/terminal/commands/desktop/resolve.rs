@@ -0,0 +1,294 @@
+//! Resolution of allowed commands to the binaries that will run.
+//!
+//! Every allowed command is looked up in `PATH` once, when Blaze starts (and
+//! again when the commands file changes), and is then always spawned by its
+//! absolute path. A lookup that would pick a binary from a relative `PATH`
+//! entry such as `.`, or from a directory other users can write to, is
+//! refused rather than skipped, because that is exactly how `PATH` hijacking
+//! works. Commands pinned to a SHA-256 digest are re-hashed before each run.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// What the commands file says about where a command may come from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pin {
+    /// The only binary that may run for the command.
+    pub path: Option<PathBuf>,
+    /// Lowercase hex SHA-256 digest the binary must have.
+    pub sha256: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Resolved {
+    path: Result<PathBuf, String>,
+    sha256: Option<String>,
+}
+
+/// The binary behind each allowed command.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Executables {
+    resolved: BTreeMap<String, Resolved>,
+}
+
+impl Executables {
+    /// Resolve `programs` against the process's `PATH`.
+    pub fn resolve<'a>(programs: impl IntoIterator<Item = (&'a str, Pin)>) -> Self {
+        let path_var = std::env::var_os("PATH").unwrap_or_default();
+        Self::resolve_in(programs, &path_var)
+    }
+
+    fn resolve_in<'a>(programs: impl IntoIterator<Item = (&'a str, Pin)>, path_var: &OsString) -> Self {
+        let resolved = programs
+            .into_iter()
+            .map(|(program, pin)| {
+                let path = match pin.path {
+                    Some(path) => check_pinned(&path).map(|()| path),
+                    None => search_path(spawned_name(program), path_var),
+                };
+                let resolved = Resolved {
+                    path,
+                    sha256: pin.sha256,
+                };
+                (program.to_string(), resolved)
+            })
+            .collect();
+        Self { resolved }
+    }
+
+    /// The binary to spawn for `program`, re-checked against its pinned
+    /// digest.
+    pub fn lookup(&self, program: &str) -> Result<&Path, String> {
+        let resolved = self
+            .resolved
+            .get(program)
+            .ok_or_else(|| format!("{}: not resolved at startup", program))?;
+        let path = resolved.path.as_deref().map_err(|e| format!("{}: {}", program, e))?;
+        if let Some(expected) = &resolved.sha256 {
+            let actual = sha256_file(path).map_err(|e| format!("{}: {}: {}", program, path.display(), e))?;
+            if &actual != expected {
+                return Err(format!(
+                    "{}: {} does not match its pinned SHA-256 (found {})",
+                    program,
+                    path.display(),
+                    actual
+                ));
+            }
+        }
+        Ok(path)
+    }
+
+    /// `which` output for `program`: the resolved path, or why there is none.
+    pub fn describe(&self, program: &str) -> Result<String, String> {
+        let path = self.lookup(program)?;
+        let pinned = self.resolved.get(program).is_some_and(|r| r.sha256.is_some());
+        Ok(if pinned {
+            format!("{} (sha256 pinned)", path.display())
+        } else {
+            path.display().to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+impl Executables {
+    /// Each of `programs` resolved to `/usr/bin/<program>`, without looking.
+    pub fn stub(programs: &[&str]) -> Self {
+        let resolved = programs
+            .iter()
+            .map(|program| {
+                let resolved = Resolved {
+                    path: Ok(Path::new("/usr/bin").join(program)),
+                    sha256: None,
+                };
+                (program.to_string(), resolved)
+            })
+            .collect();
+        Self { resolved }
+    }
+}
+
+/// The binary name a command is spawned as: `ip` is `ipconfig` on Windows.
+fn spawned_name(program: &str) -> &str {
+    if cfg!(target_os = "windows") && program == "ip" {
+        "ipconfig"
+    } else {
+        program
+    }
+}
+
+/// Find `program` in `path_var` the way the OS would, refusing hits in
+/// unsafe directories.
+fn search_path(program: &str, path_var: &OsString) -> Result<PathBuf, String> {
+    for dir in std::env::split_paths(path_var) {
+        let Some(candidate) = candidates(&dir, program).into_iter().find(|c| is_executable(c)) else {
+            continue;
+        };
+        if !dir.is_absolute() {
+            return Err(format!(
+                "refusing {}: found via relative PATH entry '{}'",
+                candidate.display(),
+                dir.display()
+            ));
+        }
+        if let Some(writable) = [dir.as_path(), candidate.as_path()].into_iter().find(|p| is_world_writable(p)) {
+            return Err(format!(
+                "refusing {}: {} is writable by other users",
+                candidate.display(),
+                writable.display()
+            ));
+        }
+        return Ok(candidate);
+    }
+    Err("not found in PATH".to_string())
+}
+
+fn check_pinned(path: &Path) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err(format!("pinned path {} is not absolute", path.display()));
+    }
+    if !is_executable(path) {
+        return Err(format!("pinned binary {} does not exist or is not executable", path.display()));
+    }
+    match path.parent().into_iter().chain([path]).find(|p| is_world_writable(p)) {
+        Some(writable) => Err(format!("refusing {}: {} is writable by other users", path.display(), writable.display())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn candidates(dir: &Path, program: &str) -> Vec<PathBuf> {
+    let exts = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    exts.split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| dir.join(format!("{}{}", program, ext.to_ascii_lowercase())))
+        .collect()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn candidates(dir: &Path, program: &str) -> Vec<PathBuf> {
+    vec![dir.join(program)]
+}
+
+#[cfg(target_os = "windows")]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// Windows ACLs are not inspected; only relative entries are refused there.
+#[cfg(target_os = "windows")]
+fn is_world_writable(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_world_writable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o002 != 0)
+}
+
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    use std::fmt::Write;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    Ok(hex)
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::{sha256_file, Executables, Pin};
+    use crate::terminal::testing::TempDir;
+    use std::ffi::OsString;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    fn temp_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("resolve_{}", name));
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).expect("chmod dir");
+        dir
+    }
+
+    fn write_binary(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).expect("write binary");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("chmod binary");
+        path
+    }
+
+    fn path_var(dirs: &[&Path]) -> OsString {
+        std::env::join_paths(dirs).expect("join paths")
+    }
+
+    #[test]
+    fn resolves_the_first_match_in_path() {
+        let (first, second) = (temp_dir("first"), temp_dir("second"));
+        write_binary(&second, "tool", "#!/bin/sh\n");
+        let expected = write_binary(&first, "tool", "#!/bin/sh\n");
+        std::fs::write(first.join("data"), "not executable").expect("write file");
+
+        let exes = Executables::resolve_in(
+            [("tool", Pin::default()), ("data", Pin::default())],
+            &path_var(&[&first, &second]),
+        );
+        assert_eq!(exes.lookup("tool"), Ok(expected.as_path()));
+        assert_eq!(exes.lookup("data"), Err("data: not found in PATH".to_string()));
+        assert!(exes.lookup("other").is_err());
+    }
+
+    #[test]
+    fn refuses_relative_and_world_writable_entries() {
+        let (safe, open) = (temp_dir("safe"), temp_dir("open"));
+        write_binary(&safe, "tool", "#!/bin/sh\n");
+        write_binary(&open, "tool", "#!/bin/sh\n");
+        std::fs::set_permissions(&open, std::fs::Permissions::from_mode(0o777)).expect("chmod dir");
+
+        let exes = Executables::resolve_in([("tool", Pin::default())], &path_var(&[&open, &safe]));
+        let err = exes.lookup("tool").unwrap_err();
+        assert!(err.contains("writable by other users"), "{err}");
+
+        // `.` only matters if the command is actually found there.
+        let exes = Executables::resolve_in([("tool", Pin::default())], &path_var(&[Path::new("."), &safe]));
+        assert!(exes.lookup("tool").is_ok());
+        // The same directory, spelled relative to the current directory.
+        let cwd = std::env::current_dir().expect("current dir");
+        let relative = Path::new(&"../".repeat(cwd.components().count() - 1))
+            .join(safe.strip_prefix("/").expect("absolute temp dir"));
+        let exes = Executables::resolve_in([("tool", Pin::default())], &path_var(&[&relative, &safe]));
+        let err = exes.lookup("tool").unwrap_err();
+        assert!(err.contains("relative PATH entry"), "{err}");
+    }
+
+    #[test]
+    fn pinned_digests_are_checked_on_every_lookup() {
+        let dir = temp_dir("pinned");
+        let binary = write_binary(&dir, "tool", "#!/bin/sh\necho v1\n");
+        let digest = sha256_file(&binary).expect("hash");
+        assert_eq!(digest.len(), 64);
+
+        let pin = Pin {
+            path: Some(binary.clone()),
+            sha256: Some(digest),
+        };
+        let exes = Executables::resolve_in([("tool", pin)], &OsString::new());
+        assert_eq!(exes.describe("tool"), Ok(format!("{} (sha256 pinned)", binary.display())));
+
+        write_binary(&dir, "tool", "#!/bin/sh\necho v2\n");
+        let err = exes.lookup("tool").unwrap_err();
+        assert!(err.contains("does not match its pinned SHA-256"), "{err}");
+    }
+}
@@ -0,0 +1,436 @@
+//! Blaze's trash, and `undo` of the last `rm` or `mv`.
+//!
+//! `rm`/`del` never delete: they move each target into
+//! `<user home>/.blaze_trash/files/<id>` and record where it came from and
+//! when in `info/<id>.json`. Every `rm` and `mv` run from Blaze is also
+//! appended to `undo.jsonl`, so `undo` can put things back in reverse order.
+//! An `mv` that replaces a file moves the old file to the trash first.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_json::{json, Value};
+
+use super::Profile;
+use crate::terminal::audit::format_utc;
+use crate::terminal::state::{LineType, TerminalLine};
+use crate::terminal::utils::home_dir;
+
+/// A reversible operation, as recorded for `undo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Targets moved to the trash by one `rm`.
+    Remove { ids: Vec<u64> },
+    /// A rename, and the trashed file it replaced, if any.
+    Move { from: PathBuf, to: PathBuf, replaced: Option<u64> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrashEntry {
+    pub id: u64,
+    pub origin: PathBuf,
+    pub deleted_at: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Trash {
+    root: PathBuf,
+}
+
+/// The user's trash, in their home directory.
+impl Default for Trash {
+    fn default() -> Self {
+        Self::at(home_dir().join(".blaze_trash"))
+    }
+}
+
+impl Trash {
+    pub fn at(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn files(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    fn info(&self, id: u64) -> PathBuf {
+        self.root.join("info").join(format!("{}.json", id))
+    }
+
+    fn journal(&self) -> PathBuf {
+        self.root.join("undo.jsonl")
+    }
+
+    /// Move `path` into the trash and return its id. Nothing already in the
+    /// trash is ever replaced.
+    pub fn put(&self, path: &Path) -> std::io::Result<u64> {
+        use std::io::Write;
+
+        std::fs::create_dir_all(self.files())?;
+        std::fs::create_dir_all(self.root.join("info"))?;
+        let id = self.next_id()?;
+        let origin = std::path::absolute(path)?;
+        let info = json!({
+            "origin": origin.to_string_lossy(),
+            "deleted_at": format_utc(SystemTime::now()),
+        });
+        let stored = self.files().join(id.to_string());
+        if std::fs::symlink_metadata(&stored).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("trash entry {} already exists", id),
+            ));
+        }
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.info(id))?
+            .write_all(info.to_string().as_bytes())?;
+        if let Err(e) = move_path(path, &stored) {
+            let _ = std::fs::remove_file(self.info(id));
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// A fresh id. Ids are never reused, so `undo` records stay unambiguous
+    /// after `trash restore` and `trash empty`. A missing or damaged counter
+    /// never goes below the ids already in the trash.
+    fn next_id(&self) -> std::io::Result<u64> {
+        let counter = self.root.join("next_id");
+        let stored = std::fs::read_to_string(&counter).ok().and_then(|text| text.trim().parse::<u64>().ok());
+        let mut id = stored.unwrap_or(1);
+        for dir in [self.files(), self.root.join("info")] {
+            for entry in std::fs::read_dir(dir)? {
+                let name = entry?.file_name();
+                let taken = name.to_str().and_then(|name| name.trim_end_matches(".json").parse::<u64>().ok());
+                if let Some(taken) = taken {
+                    id = id.max(taken + 1);
+                }
+            }
+        }
+        std::fs::write(&counter, (id + 1).to_string())?;
+        Ok(id)
+    }
+
+    /// Everything in the trash, oldest first.
+    pub fn list(&self) -> std::io::Result<Vec<TrashEntry>> {
+        let entries = match std::fs::read_dir(self.root.join("info")) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut list: Vec<TrashEntry> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let id = e.path().file_stem()?.to_str()?.parse().ok()?;
+                let info: Value = serde_json::from_str(&std::fs::read_to_string(e.path()).ok()?).ok()?;
+                Some(TrashEntry {
+                    id,
+                    origin: PathBuf::from(info["origin"].as_str()?),
+                    deleted_at: info["deleted_at"].as_str()?.to_string(),
+                })
+            })
+            .collect();
+        list.sort_by_key(|entry| entry.id);
+        Ok(list)
+    }
+
+    /// Move entry `id` back to where it was deleted from.
+    pub fn restore(&self, id: u64) -> Result<PathBuf, String> {
+        let entry = self
+            .list()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("no trash entry {}", id))?;
+        if std::fs::symlink_metadata(&entry.origin).is_ok() {
+            return Err(format!("{} already exists", entry.origin.display()));
+        }
+        move_path(&self.files().join(id.to_string()), &entry.origin)
+            .map_err(|e| format!("{}: {}", entry.origin.display(), e))?;
+        let _ = std::fs::remove_file(self.info(id));
+        Ok(entry.origin)
+    }
+
+    /// Delete everything in the trash for good. Returns how many entries
+    /// were removed; `undo` forgets them too.
+    pub fn empty(&self) -> std::io::Result<usize> {
+        let count = self.list()?.len();
+        for dir in [self.files(), self.root.join("info")] {
+            match std::fs::remove_dir_all(dir) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        match std::fs::remove_file(self.journal()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(count),
+        }
+    }
+
+    /// Remember `op` as the most recent thing `undo` reverts.
+    pub fn record(&self, op: &Operation) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let line = match op {
+            Operation::Remove { ids } => json!({ "op": "rm", "ids": ids }),
+            Operation::Move { from, to, replaced } => json!({
+                "op": "mv",
+                "from": from.to_string_lossy(),
+                "to": to.to_string_lossy(),
+                "replaced": replaced,
+            }),
+        };
+        std::fs::create_dir_all(&self.root)?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.journal())?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Revert the most recent `rm` or `mv`, and describe what was done.
+    pub fn undo(&self) -> Result<String, String> {
+        let text = std::fs::read_to_string(self.journal()).unwrap_or_default();
+        let mut records: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+        let last = records.pop().ok_or("nothing to undo")?;
+        let op = parse_operation(last).ok_or_else(|| format!("unreadable undo record: {}", last))?;
+
+        let done = match op {
+            Operation::Remove { ids } => {
+                // Entries already restored with `trash restore` are skipped.
+                let present: Vec<u64> = self.list().map_err(|e| e.to_string())?.iter().map(|e| e.id).collect();
+                let mut names = Vec::new();
+                for id in ids.into_iter().filter(|id| present.contains(id)) {
+                    names.push(self.restore(id)?.display().to_string());
+                }
+                if names.is_empty() {
+                    "Nothing left in the trash to restore".to_string()
+                } else {
+                    format!("Restored {}", names.join(", "))
+                }
+            }
+            Operation::Move { from, to, replaced } => {
+                if std::fs::symlink_metadata(&from).is_ok() {
+                    return Err(format!("{} already exists", from.display()));
+                }
+                std::fs::rename(&to, &from).map_err(|e| format!("{}: {}", to.display(), e))?;
+                if let Some(id) = replaced {
+                    self.restore(id)?;
+                }
+                format!("Moved {} back to {}", to.display(), from.display())
+            }
+        };
+
+        let mut rest = records.join("\n");
+        if !rest.is_empty() {
+            rest.push('\n');
+        }
+        std::fs::write(self.journal(), rest).map_err(|e| e.to_string())?;
+        Ok(done)
+    }
+}
+
+fn parse_operation(line: &str) -> Option<Operation> {
+    let v: Value = serde_json::from_str(line).ok()?;
+    match v["op"].as_str()? {
+        "rm" => Some(Operation::Remove {
+            ids: v["ids"].as_array()?.iter().map(Value::as_u64).collect::<Option<_>>()?,
+        }),
+        "mv" => Some(Operation::Move {
+            from: PathBuf::from(v["from"].as_str()?),
+            to: PathBuf::from(v["to"].as_str()?),
+            replaced: v["replaced"].as_u64(),
+        }),
+        _ => None,
+    }
+}
+
+/// Rename `from` to `to`, copying and deleting when they are on different
+/// filesystems.
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            if let Err(e) = copy_all(from, to) {
+                let _ = remove_all(to);
+                return Err(e);
+            }
+            remove_all(from)
+        }
+        result => result,
+    }
+}
+
+fn copy_all(from: &Path, to: &Path) -> std::io::Result<()> {
+    let meta = std::fs::symlink_metadata(from)?;
+    if meta.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::set_permissions(to, meta.permissions())
+    } else if meta.file_type().is_symlink() {
+        copy_symlink(from, to)
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+#[cfg(target_os = "windows")]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    let target = std::fs::read_link(from)?;
+    if std::fs::metadata(from).is_ok_and(|m| m.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+fn remove_all(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Run the `trash` and `undo` builtins. Listing works in every profile;
+/// anything that changes files needs `full`.
+pub fn handle_trash_command(trash: &Trash, profile: Profile, program: &str, argv: &[String]) -> Vec<TerminalLine> {
+    let sub = argv.get(1).map(String::as_str);
+    let mutates = program == "undo" || matches!(sub, Some("restore" | "empty"));
+    if mutates && !profile.allows_mutations() {
+        let name = if program == "undo" { "undo".to_string() } else { format!("trash {}", sub.unwrap_or_default()) };
+        return vec![error_line(format!(
+            "{} is disabled in the '{}' profile (start Blaze with --profile full).",
+            name, profile
+        ))];
+    }
+
+    let result = match (program, sub, argv.len()) {
+        ("undo", None, _) => trash.undo().map(|done| vec![done]),
+        ("trash", Some("list"), 2) | ("trash", None, _) => trash.list().map_err(|e| e.to_string()).map(|list| {
+            if list.is_empty() {
+                return vec!["The trash is empty.".to_string()];
+            }
+            list.iter()
+                .map(|entry| format!("{:>4}  {}  {}", entry.id, entry.deleted_at, entry.origin.display()))
+                .collect()
+        }),
+        ("trash", Some("restore"), 3) => match argv[2].parse() {
+            Ok(id) => trash.restore(id).map(|origin| vec![format!("Restored {}", origin.display())]),
+            Err(_) => Err(format!("'{}' is not a trash id (see 'trash list')", argv[2])),
+        },
+        ("trash", Some("empty"), 2) => trash
+            .empty()
+            .map(|count| vec![format!("Deleted {} trashed item(s) permanently", count)])
+            .map_err(|e| e.to_string()),
+        ("undo", _, _) => Err("usage: undo".to_string()),
+        _ => Err("usage: trash [list|restore <id>|empty]".to_string()),
+    };
+    match result {
+        Ok(lines) => lines
+            .into_iter()
+            .map(|content| TerminalLine {
+                content,
+                line_type: LineType::Output,
+                spans: Vec::new(),
+            })
+            .collect(),
+        Err(e) => vec![error_line(format!("{}: {}", program, e))],
+    }
+}
+
+fn error_line(content: String) -> TerminalLine {
+    TerminalLine {
+        content,
+        line_type: LineType::Error,
+        spans: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Operation, Trash};
+    use crate::terminal::testing::TempDir;
+
+    #[test]
+    fn put_list_and_restore() {
+        let dir = TempDir::new("trash_restore");
+        let trash = Trash::at(dir.join("trash"));
+        std::fs::create_dir(dir.join("sub")).expect("create dir");
+        std::fs::write(dir.join("sub/a.txt"), "a").expect("write file");
+        std::fs::write(dir.join("b.txt"), "b").expect("write file");
+
+        assert_eq!(trash.put(&dir.join("sub")).expect("trash dir"), 1);
+        assert_eq!(trash.put(&dir.join("b.txt")).expect("trash file"), 2);
+        assert!(!dir.join("sub").exists() && !dir.join("b.txt").exists());
+        let list = trash.list().expect("list");
+        assert_eq!(list.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(list[1].origin, dir.join("b.txt"));
+
+        std::fs::write(dir.join("b.txt"), "new").expect("write file");
+        assert!(trash.restore(2).unwrap_err().contains("already exists"));
+        assert_eq!(trash.restore(1), Ok(dir.join("sub")));
+        assert_eq!(std::fs::read_to_string(dir.join("sub/a.txt")).expect("read"), "a");
+        assert!(trash.restore(1).unwrap_err().contains("no trash entry 1"));
+
+        assert_eq!(trash.empty().expect("empty"), 1);
+        assert!(trash.list().expect("list").is_empty());
+        std::fs::write(dir.join("c.txt"), "c").expect("write file");
+        assert_eq!(trash.put(&dir.join("c.txt")).expect("trash file"), 3, "ids are not reused");
+    }
+
+    #[test]
+    fn a_damaged_counter_never_reuses_an_id() {
+        let dir = TempDir::new("trash_counter");
+        let trash = Trash::at(dir.join("trash"));
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(dir.join(name), name).expect("write file");
+        }
+        assert_eq!(trash.put(&dir.join("a.txt")).expect("trash file"), 1);
+        assert_eq!(trash.put(&dir.join("b.txt")).expect("trash file"), 2);
+
+        for counter in ["", "garbage", "1"] {
+            std::fs::write(dir.join("trash/next_id"), counter).expect("write counter");
+            let name = format!("{}.txt", counter.len());
+            std::fs::write(dir.join(&name), "new").expect("write file");
+            assert!(trash.put(&dir.join(&name)).expect("trash file") > 2, "counter {:?}", counter);
+        }
+        assert_eq!(trash.list().expect("list").len(), 5);
+        assert_eq!(std::fs::read_to_string(dir.join("trash/files/1")).expect("read"), "a.txt");
+        assert_eq!(std::fs::read_to_string(dir.join("trash/files/2")).expect("read"), "b.txt");
+    }
+
+    #[test]
+    fn undo_reverts_the_latest_rm_then_mv() {
+        let dir = TempDir::new("trash_undo");
+        let trash = Trash::at(dir.join("trash"));
+        std::fs::write(dir.join("a.txt"), "a").expect("write file");
+        std::fs::write(dir.join("b.txt"), "b").expect("write file");
+
+        // mv a.txt b.txt, replacing b.txt
+        let replaced = trash.put(&dir.join("b.txt")).expect("trash file");
+        std::fs::rename(dir.join("a.txt"), dir.join("b.txt")).expect("rename");
+        let mv = Operation::Move {
+            from: dir.join("a.txt"),
+            to: dir.join("b.txt"),
+            replaced: Some(replaced),
+        };
+        trash.record(&mv).expect("record mv");
+        // rm b.txt
+        let id = trash.put(&dir.join("b.txt")).expect("trash file");
+        trash.record(&Operation::Remove { ids: vec![id] }).expect("record rm");
+
+        assert!(trash.undo().expect("undo rm").starts_with("Restored"));
+        assert_eq!(std::fs::read_to_string(dir.join("b.txt")).expect("read"), "a");
+        assert!(trash.undo().expect("undo mv").starts_with("Moved"));
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).expect("read"), "a");
+        assert_eq!(std::fs::read_to_string(dir.join("b.txt")).expect("read"), "b");
+        assert_eq!(trash.undo(), Err("nothing to undo".to_string()));
+    }
+}
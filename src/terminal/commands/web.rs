@@ -9,6 +9,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
         TerminalLine {
             content: format!("{} > {}", demo_dir, cmd),
             line_type: LineType::Command,
+            spans: Vec::new(),
         },
     );
 
@@ -47,6 +48,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: line.into(),
                         line_type: LineType::System,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -66,6 +68,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: line.into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -81,6 +84,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: text,
                     line_type: LineType::Output,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -90,6 +94,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: "You".into(),
                     line_type: LineType::Output,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -111,6 +116,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                             TerminalLine {
                                 content: line,
                                 line_type: LineType::Output,
+                                spans: Vec::new(),
                             },
                         );
                     }
@@ -121,6 +127,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                         TerminalLine {
                             content: "Usage: curl <url>".into(),
                             line_type: LineType::Error,
+                            spans: Vec::new(),
                         },
                     );
                 }
@@ -132,6 +139,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: demo_dir.into(),
                     line_type: LineType::Output,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -142,6 +150,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Usage: cat <file>".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
             } else {
@@ -150,6 +159,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "(simulated) file contents...".into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -161,6 +171,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Usage: grep <pattern> <file>".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
             } else {
@@ -169,6 +180,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "(simulated) matching lines...".into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -179,6 +191,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: "Fri 02/07/2026".into(),
                     line_type: LineType::Output,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -196,6 +209,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: line.into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -212,6 +226,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: line.into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -223,6 +238,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Usage: ping <host>".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
             } else {
@@ -237,6 +253,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                         TerminalLine {
                             content: line.into(),
                             line_type: LineType::Output,
+                            spans: Vec::new(),
                         },
                     );
                 }
@@ -249,6 +266,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Usage: mkdir <dir>".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
             } else {
@@ -257,6 +275,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Directory created (simulated)".into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -268,6 +287,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Usage: rm <path>".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
             } else {
@@ -276,6 +296,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Deleted (simulated)".into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -287,6 +308,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Usage: mv <source> <dest>".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
             } else {
@@ -295,6 +317,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                     TerminalLine {
                         content: "Moved (simulated)".into(),
                         line_type: LineType::Output,
+                        spans: Vec::new(),
                     },
                 );
             }
@@ -305,6 +328,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: "Directory changed (simulated)".into(),
                     line_type: LineType::System,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -314,6 +338,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: "Can't exit the web demo! Download the real thing.".into(),
                     line_type: LineType::System,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -323,6 +348,7 @@ pub fn run_web_command(cmd: &str, demo_dir: &str, mut lines: Signal<Vec<Terminal
                 TerminalLine {
                     content: format!("'{}': command not recognized. Type 'help' for commands.", cmd),
                     line_type: LineType::Error,
+                    spans: Vec::new(),
                 },
             );
         }
@@ -1,158 +1,577 @@
 use dioxus::prelude::*;
 
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-use crate::terminal::commands::desktop::is_allowed_external;
+use crate::terminal::commands::desktop::{
+    authorize, commands_file_path, confirmation_prompt, handle_trash_command, run_native_builtin, run_pipeline, which,
+    CommandList, Executables, ExecLimits, ForegroundJob, JobState, PolicyContext, Profile, Progress, Stage, Trash,
+};
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32"), target_os = "windows"))]
 use crate::terminal::commands::desktop::execute_windows_command;
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32"), not(target_os = "windows")))]
-use crate::terminal::commands::desktop::stream_unix_command;
+use crate::terminal::commands::desktop::{availability_warning, stream_unix_command, JobSignal, Sandbox};
 #[cfg(not(feature = "desktop"))]
 use crate::terminal::commands::web::run_web_command;
 use crate::terminal::state::{LineType, TerminalLine};
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-use crate::terminal::utils::split_args;
+use crate::terminal::state::{CommandStatus, ExitOutcome, TermSize};
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::audit::{AuditEntry, AuditLog, Decision};
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::config::Settings;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::expand::{expand_command, Env};
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::parser::{parse, Command, Item, Pipeline};
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
 use crate::terminal::utils::push_line_trim;
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-use crate::terminal::utils::{load_history, append_history, tab_complete};
+use crate::terminal::utils::{home_dir, load_history, append_history, tab_complete, BUILTIN_COMMANDS};
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::vt::style_css;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use std::collections::VecDeque;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use std::time::Instant;
+
+/// Reports the terminal body's size in character cells whenever it changes.
+///
+/// The cell size is measured from a hidden probe using the body's font, and the
+/// body's padding is excluded from the usable area.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+const TERMINAL_SIZE_JS: &str = r#"
+const start = () => {
+    const body = document.getElementById('terminal-output');
+    if (!body) { setTimeout(start, 50); return; }
+    const probe = document.createElement('span');
+    probe.textContent = 'W'.repeat(64);
+    probe.style.cssText = 'position:absolute;visibility:hidden;white-space:pre;left:-9999px;';
+    document.body.appendChild(probe);
+    let last = '';
+    const report = () => {
+        const style = getComputedStyle(body);
+        probe.style.fontFamily = style.fontFamily;
+        probe.style.fontSize = style.fontSize;
+        const charWidth = probe.getBoundingClientRect().width / 64 || 8;
+        const lineHeight = parseFloat(style.lineHeight) || parseFloat(style.fontSize) * 1.7;
+        const width = body.clientWidth - parseFloat(style.paddingLeft) - parseFloat(style.paddingRight);
+        const height = body.clientHeight - parseFloat(style.paddingTop) - parseFloat(style.paddingBottom);
+        const cols = Math.max(20, Math.floor(width / charWidth));
+        const rows = Math.max(5, Math.floor(height / lineHeight));
+        const key = cols + 'x' + rows;
+        if (key !== last) {
+            last = key;
+            dioxus.send([cols, rows]);
+        }
+    };
+    new ResizeObserver(report).observe(body);
+    report();
+};
+start();
+"#;
+
+/// Remember how a command ended: sets `$?` and writes its audit log entry.
+/// The history records whole lines, once they have finished.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+fn record_status(
+    lines: Signal<Vec<TerminalLine>>,
+    mut last_exit: Signal<i32>,
+    cmd: &str,
+    cwd: &str,
+    decision: &Decision,
+    status: CommandStatus,
+) {
+    last_exit.set(status.outcome.code());
+    let log = AuditLog::default();
+    let entry = AuditEntry {
+        command: cmd,
+        cwd,
+        decision,
+        exit_code: status.outcome.code(),
+        duration: status.duration,
+    };
+    if let Err(e) = log.append(&entry) {
+        push_line_trim(
+            lines,
+            TerminalLine {
+                content: format!("audit: cannot write {}: {}", log.path().display(), e),
+                line_type: LineType::Error,
+                spans: Vec::new(),
+            },
+        );
+    }
+}
+
+/// Like [`record_status`], and print the status footer below the output of an
+/// external command.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+fn finish_external(
+    lines: Signal<Vec<TerminalLine>>,
+    last_exit: Signal<i32>,
+    cmd: &str,
+    cwd: &str,
+    decision: &Decision,
+    status: CommandStatus,
+) {
+    push_line_trim(lines, status.footer());
+    record_status(lines, last_exit, cmd, cwd, decision, status);
+}
+
+/// A destructive builtin held back until the user types `y`.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+#[derive(Clone, Debug)]
+struct PendingConfirmation {
+    cmd: String,
+    cwd: String,
+    program: String,
+    argv: Vec<String>,
+    /// The rest of the command line, run after the answer.
+    rest: Option<Remaining>,
+}
+
+/// What is left of a command line while one of its pipelines runs.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+#[derive(Clone, Debug)]
+struct Remaining {
+    /// The whole line, for the history.
+    line: String,
+    started: Instant,
+    items: VecDeque<Item>,
+}
+
+/// Re-read the commands file if it changed, say so, and resolve the binaries
+/// it now allows.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+fn refresh_command_list(
+    mut command_list: Signal<CommandList>,
+    mut executables: Signal<Executables>,
+    profile: Profile,
+    lines: Signal<Vec<TerminalLine>>,
+) {
+    if !command_list.peek().is_stale() {
+        return;
+    }
+    let mut list = command_list.write();
+    list.reload();
+    executables.set(list.resolve_executables(profile));
+    push_line_trim(
+        lines,
+        TerminalLine {
+            content: format!("Reloaded {}", list.path().display()),
+            line_type: LineType::System,
+            spans: Vec::new(),
+        },
+    );
+    for warning in &list.warnings {
+        push_line_trim(
+            lines,
+            TerminalLine {
+                content: warning.clone(),
+                line_type: LineType::Error,
+                spans: Vec::new(),
+            },
+        );
+    }
+}
 
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
 #[component]
 pub fn DesktopTerminal() -> Element {
+    let settings = use_context::<Settings>();
+    // Decides what may run; fixed for the lifetime of the terminal.
+    let profile = settings.profile;
+    let confirm_over = settings.confirm_over;
+    // Extra allowed and denied commands, re-read when the file changes.
+    let command_list = use_signal(|| CommandList::load(commands_file_path()));
+    // Where each allowed command's binary is, resolved once at startup.
+    let executables = use_signal(|| command_list.peek().resolve_executables(profile));
     let mut lines = use_signal(|| {
-        vec![
+        let mut lines = vec![
             TerminalLine {
                 content: "⚡ Blaze Terminal v0.1.1".into(),
                 line_type: LineType::System,
+                spans: Vec::new(),
             },
             TerminalLine {
                 content: "Type 'help' for available commands.".into(),
                 line_type: LineType::System,
+                spans: Vec::new(),
             },
-            TerminalLine {
-                content: String::new(),
+        ];
+        if let Some(jail) = &settings.jail {
+            lines.push(TerminalLine {
+                content: format!("Confined to the workspace {}", jail.root().display()),
                 line_type: LineType::System,
-            },
-        ]
+                spans: Vec::new(),
+            });
+        }
+        #[cfg(not(target_os = "windows"))]
+        if let Some(warning) = availability_warning(settings.sandbox) {
+            lines.push(TerminalLine {
+                content: warning,
+                line_type: LineType::Error,
+                spans: Vec::new(),
+            });
+        }
+        lines.extend(settings.warnings.iter().map(|warning| TerminalLine {
+            content: format!("config: {}", warning),
+            line_type: LineType::Error,
+            spans: Vec::new(),
+        }));
+        lines.extend(command_list.peek().warnings.iter().map(|warning| TerminalLine {
+            content: warning.clone(),
+            line_type: LineType::Error,
+            spans: Vec::new(),
+        }));
+        lines.push(TerminalLine {
+            content: String::new(),
+            line_type: LineType::System,
+            spans: Vec::new(),
+        });
+        lines
     });
     let mut input_value = use_signal(String::new);
-    let mut current_dir = use_signal(|| std::env::current_dir().unwrap_or_default().display().to_string());
+    // The root that `cd` and the filesystem builtins may not leave, if configured.
+    let jail = use_signal(|| settings.jail.clone());
+    let launch_dir = || match jail.peek().as_ref() {
+        Some(jail) => jail.root().to_path_buf(),
+        None => std::env::current_dir().unwrap_or_default(),
+    };
+    let mut current_dir = use_signal(|| launch_dir().display().to_string());
+    // Recursive searches are confined to the directory Blaze was started in.
+    let workspace = use_signal(launch_dir);
+    // How spawned commands are confined: writes only to the workspace (or
+    // nowhere, in the readonly profile).
+    #[cfg(not(target_os = "windows"))]
+    let sandbox = use_signal(|| Sandbox::new(profile, &launch_dir(), settings.sandbox, settings.seccomp));
     let mut cmd_history = use_signal(|| load_history(1000));
     let mut history_idx = use_signal(|| -1i32);
     // Tracks consecutive Tab presses so we cycle through completions.
     let mut tab_state = use_signal(|| 0usize);
     // Stores the input that was typed before Tab was first pressed (the "stub").
     let mut tab_stub = use_signal(String::new);
+    // Timeout and output caps for external commands, changed with `limits`.
+    let mut exec_limits = use_signal(ExecLimits::default);
+    // Exit code of the last command, available as `$?`.
+    let last_exit = use_signal(|| 0i32);
+    // Size of the output area in character cells, reported by TERMINAL_SIZE_JS.
+    let mut term_size = use_signal(TermSize::default);
+    // The external command currently running (or stopped) in the terminal.
+    let mut foreground = use_signal(|| None::<ForegroundJob>);
+    // A destructive `rm`/`mv` waiting for its confirmation.
+    let mut pending_confirmation = use_signal(|| None::<PendingConfirmation>);
+    // Screen rows of the running command, redrawn in place below `lines`.
+    #[cfg(not(target_os = "windows"))]
+    let live_lines = use_signal(Vec::<TerminalLine>::new);
 
-    let handle_key = move |e: KeyboardEvent| match e.key() {
-        Key::Tab => {
-            e.prevent_default();
-            let current_input = input_value();
-            // On the first Tab press, record the stub; on subsequent presses reuse it.
-            let stub = if tab_state() == 0 {
-                tab_stub.set(current_input.clone());
-                current_input.clone()
-            } else {
-                tab_stub()
-            };
-            let cwd = current_dir();
-            let state = tab_state();
-            if let Some(completed) = tab_complete(&stub, &cwd, state) {
-                input_value.set(completed);
-                tab_state.set(state + 1);
-            }
-            return;
+    use_future(move || async move {
+        let mut eval = document::eval(TERMINAL_SIZE_JS);
+        while let Ok((cols, rows)) = eval.recv::<(u16, u16)>().await {
+            term_size.set(TermSize { cols, rows });
         }
-        Key::Enter => {
-            // Reset tab cycling on any non-Tab key.
-            tab_state.set(0);
-            tab_stub.set(String::new());
+    });
 
-            let cmd = input_value().trim().to_string();
-            if cmd.is_empty() {
-                return;
-            }
-            let cwd = current_dir().clone();
+    #[cfg(not(target_os = "windows"))]
+    use_effect(move || {
+        let size = term_size();
+        if let Some(pty) = foreground.peek().as_ref().and_then(|job| job.pty.as_ref()) {
+            let _ = pty.resize(size);
+        }
+    });
 
-            cmd_history.write().push(cmd.clone());
-            append_history(&cmd);
-            history_idx.set(-1);
+    // Run one pipeline of a command line to completion. A command that asks
+    // for confirmation leaves `pending_confirmation` set instead.
+    let execute = move |pipeline: Pipeline| async move {
+        let cmd = pipeline.text;
+        let cwd = current_dir.peek().clone();
+        let started = Instant::now();
 
-            push_line_trim(
-                lines,
-                TerminalLine {
-                    content: format!("{} > {}", cwd, cmd),
-                    line_type: LineType::Command,
-                },
-            );
-            input_value.set(String::new());
+        let home = home_dir().display().to_string();
+        let jail_root = jail.peek().clone();
+        let env = Env {
+            home: &home,
+            last_exit: *last_exit.peek(),
+            var: &|name| std::env::var(name).ok(),
+            cwd: std::path::Path::new(&cwd),
+            jail: jail_root.as_ref(),
+        };
+        let expanded = pipeline.commands.iter().map(|command| expand_command(command, &env)).collect();
+        let mut commands: Vec<Command<String>> = match expanded {
+            Ok(commands) => commands,
+            Err(message) => {
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: message,
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    },
+                );
+                record_status(lines, last_exit, &cmd, &cwd, &Decision::Denied { rule: "expansion" }, CommandStatus {
+                    outcome: ExitOutcome::Code(1),
+                    duration: started.elapsed(),
+                });
+                return;
+            }
+        };
 
-            let args = split_args(&cmd);
-            let first = args.first().map(|s| s.to_lowercase()).unwrap_or_default();
+        // Pipelines and redirections run with their output collected.
+        if commands.len() > 1 || commands.iter().any(|command| !command.redirects.is_empty()) {
+            let single = commands.len() == 1;
+            if let Some(job) = foreground.peek().as_ref() {
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: format!("'{}' is stopped. Type 'fg' to resume it.", job.command),
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    },
+                );
+                let decision = Decision::Denied { rule: "job-stopped" };
+                record_status(lines, last_exit, &cmd, &cwd, &decision, CommandStatus {
+                    outcome: ExitOutcome::Code(1),
+                    duration: started.elapsed(),
+                });
+                return;
+            }
 
-            match first.as_str() {
-                "clear" | "cls" => {
-                    lines.write().clear();
-                    return;
+            // Each command of the pipeline must pass the policy on its own.
+            let (stages, refused) = {
+                let workspace_root = workspace.peek().clone();
+                let jail_root = jail.peek();
+                let command_set = command_list.peek();
+                let resolved = executables.peek();
+                let ctx = PolicyContext {
+                    profile,
+                    commands: &command_set,
+                    executables: &resolved,
+                    cwd: std::path::Path::new(&cwd),
+                    workspace: &workspace_root,
+                    jail: jail_root.as_ref(),
+                };
+                let mut stages = Vec::new();
+                let mut refused = None;
+                for Command { words, redirects } in commands {
+                    let passthrough = words.len() > 1 && words[0].eq_ignore_ascii_case("command");
+                    let args = if passthrough { words[1..].to_vec() } else { words };
+                    let program = args[0].to_lowercase();
+                    let checked = if BUILTIN_COMMANDS.contains(&program.as_str()) && !passthrough {
+                        let (message, rule) = if single {
+                            ("cannot be redirected", "redirect")
+                        } else {
+                            ("cannot be used in a pipeline", "pipeline")
+                        };
+                        Err((format!("{}: {}", program, message), rule, 2))
+                    } else {
+                        authorize(ctx, &program, &args, passthrough)
+                            .map_err(|denial| (denial.to_string(), denial.rule, 126))
+                            .and_then(|argv| {
+                                let prompt = confirmation_prompt(profile, &cwd, &program, &argv, confirm_over);
+                                let message = if single {
+                                    "asks for confirmation, so its output cannot be redirected"
+                                } else {
+                                    "asks for confirmation, so it cannot be used in a pipeline"
+                                };
+                                match prompt.filter(|_| !passthrough) {
+                                    Some(_) => Err((format!("{}: {}", program, message), "not-confirmed", 1)),
+                                    None => Ok(argv),
+                                }
+                            })
+                            .and_then(|argv| {
+                                let mut stage = Stage::new(program, argv, passthrough);
+                                stage
+                                    .redirect(ctx, &redirects)
+                                    .map(|()| stage)
+                                    .map_err(|denial| (denial.to_string(), denial.rule, 126))
+                            })
+                    };
+                    match checked {
+                        Ok(stage) => stages.push(stage),
+                        Err(error) => {
+                            refused = Some(error);
+                            break;
+                        }
+                    }
                 }
-                "help" => {
-                    let help = [
-                        "⚡ Blaze Terminal — Commands:",
-                        "",
-                        "  help            Show this help message",
-                        "  clear / cls     Clear terminal output",
-                        "  cd <dir>        Change directory",
-                        "  pwd             Print working directory",
-                        "  exit            Exit the terminal",
-                        "",
-                        #[cfg(all(target_os = "windows", feature = "safe-mode"))]
-                        "Allowed system commands (safe mode): ls, dir, echo, vim, whoami, cat/type, grep, curl, wget, ipconfig (ip).",
-                        #[cfg(all(target_os = "windows", not(feature = "safe-mode"), not(feature = "unsafe-fs")))]
-                        "Allowed system commands: ls, dir, echo, vim, whoami, cat/type, grep, curl, wget, ipconfig (ip).",
-                        #[cfg(all(target_os = "windows", not(feature = "safe-mode"), feature = "unsafe-fs"))]
-                        "Allowed system commands: ls, dir, echo, vim, mkdir, rm/del, mv, whoami, cat/type, grep, curl, wget, ipconfig (ip).",
-                        #[cfg(all(not(target_os = "windows"), feature = "safe-mode"))]
-                        "Allowed system commands (safe mode): ls, dir, echo, vim, whoami, cat, grep, curl, wget, ifconfig, ip.",
-                        #[cfg(all(not(target_os = "windows"), not(feature = "safe-mode"), not(feature = "unsafe-fs")))]
-                        "Allowed system commands: ls, dir, echo, vim, whoami, cat, grep, curl, wget, ifconfig, ip.",
-                        #[cfg(all(not(target_os = "windows"), not(feature = "safe-mode"), feature = "unsafe-fs"))]
-                        "Allowed system commands: ls, dir, echo, vim, mkdir, rm/del, mv, whoami, cat, grep, curl, wget, ifconfig, ip.",
-                    ];
-                    let mut v = lines.write();
-                    for h in help {
-                        v.push(TerminalLine {
-                            content: h.to_string(),
-                            line_type: LineType::System,
-                        });
+                (stages, refused)
+            };
+            if let Some((message, rule, code)) = refused {
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: message,
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    },
+                );
+                record_status(lines, last_exit, &cmd, &cwd, &Decision::Denied { rule }, CommandStatus {
+                    outcome: ExitOutcome::Code(code),
+                    duration: started.elapsed(),
+                });
+                return;
+            }
+
+            // The binaries the pipeline runs, with builtins by name.
+            let decision = if stages.iter().any(|stage| std::path::Path::new(&stage.argv[0]).is_absolute()) {
+                let binaries = stages.iter().map(|stage| stage.argv[0].as_str()).collect::<Vec<_>>();
+                Decision::Allowed { binary: binaries.join(" | ") }
+            } else {
+                Decision::Builtin
+            };
+            foreground.set(Some(ForegroundJob::new(cmd.clone())));
+            let limits = *exec_limits.peek();
+            #[cfg(not(target_os = "windows"))]
+            let sandbox = sandbox.peek().clone();
+            let dir = cwd.clone();
+            // Lines are shown as the pipeline produces them; its process group
+            // is what the job-control keys signal.
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            let run = tokio::task::spawn_blocking(move || {
+                run_pipeline(
+                    profile,
+                    &Trash::default(),
+                    &dir,
+                    &stages,
+                    limits,
+                    #[cfg(not(target_os = "windows"))]
+                    &sandbox,
+                    &mut |progress| {
+                        let _ = tx.send(progress);
+                    },
+                )
+            });
+            while let Some(progress) = rx.recv().await {
+                match progress {
+                    #[cfg(not(target_os = "windows"))]
+                    Progress::Group(pgid) => {
+                        if let Some(job) = foreground.write().as_mut() {
+                            job.pgid = Some(pgid);
+                        }
                     }
-                    return;
+                    Progress::Line(line) => push_line_trim(lines, line),
                 }
-                "exit" => {
-                    dioxus::desktop::window().close();
-                    return;
+            }
+            let outcome = run.await.unwrap_or_else(|e| {
+                let error = TerminalLine {
+                    content: format!("Error: {}", e),
+                    line_type: LineType::Error,
+                    spans: Vec::new(),
+                };
+                push_line_trim(lines, error);
+                ExitOutcome::Code(1)
+            });
+            foreground.set(None);
+            finish_external(lines, last_exit, &cmd, &cwd, &decision, CommandStatus {
+                outcome,
+                duration: started.elapsed(),
+            });
+            return;
+        }
+
+        let args = commands.pop().unwrap_or_default().words;
+        let first = args.first().map(|s| s.to_lowercase()).unwrap_or_default();
+
+        // `command <program> ...` runs the system binary even where Blaze
+        // has a native builtin of the same name.
+        let passthrough = first == "command" && args.len() > 1;
+        let (args, first) = if passthrough {
+            let args = args[1..].to_vec();
+            let first = args[0].to_lowercase();
+            (args, first)
+        } else {
+            (args, first)
+        };
+
+        let builtin_code = match first.as_str() {
+            "clear" | "cls" => {
+                lines.write().clear();
+                Some(0)
+            }
+            "help" => {
+                let help = [
+                    "⚡ Blaze Terminal — Commands:",
+                    "",
+                    "  help            Show this help message",
+                    "  clear / cls     Clear terminal output",
+                    "  cd <dir>        Change directory",
+                    "  pwd             Print working directory",
+                    "  command <prog>  Run the system binary instead of the builtin (ls, cat, grep, ...)",
+                    #[cfg(target_os = "windows")]
+                    "  limits [<k> <v>] Show or change command limits (timeout, bytes, lines)",
+                    #[cfg(not(target_os = "windows"))]
+                    "  limits [<k> <v>] Show or change command limits (timeout, bytes, lines, cpu, memory, filesize, procs)",
+                    "  which <cmd>     Show the binary a command runs",
+                    "  audit verify    Check the audit log for edits or truncation",
+                    "  trash [list]    List deleted files; 'trash restore <id>' puts one back",
+                    "  undo            Revert the last rm or mv",
+                    #[cfg(not(target_os = "windows"))]
+                    "  fg              Resume the command stopped with Ctrl+Z",
+                    "  exit            Exit the terminal",
+                    "",
+                    #[cfg(not(target_os = "windows"))]
+                    "While a command runs, Enter sends the line to it and Ctrl+D sends end-of-file;",
+                    #[cfg(not(target_os = "windows"))]
+                    "Ctrl+C interrupts, Ctrl+\\ quits and Ctrl+Z suspends it.",
+                    #[cfg(not(target_os = "windows"))]
+                    "",
+                ];
+                let mut v = lines.write();
+                for h in help {
+                    v.push(TerminalLine {
+                        content: h.to_string(),
+                        line_type: LineType::System,
+                        spans: Vec::new(),
+                    });
                 }
-                "cd" => {
-                    let rest = args.iter().skip(1).cloned().collect::<Vec<_>>().join(" ");
-                    if rest.is_empty() {
-                        push_line_trim(
-                            lines,
-                            TerminalLine {
-                                content: cwd.clone(),
-                                line_type: LineType::Output,
-                            },
-                        );
-                        return;
-                    }
+                v.push(TerminalLine {
+                    content: command_list.peek().help_line(profile),
+                    line_type: LineType::System,
+                    spans: Vec::new(),
+                });
+                Some(0)
+            }
+            "exit" => {
+                dioxus::desktop::window().close();
+                Some(0)
+            }
+            "cd" => {
+                let rest = args.iter().skip(1).cloned().collect::<Vec<_>>().join(" ");
+                if rest.is_empty() {
+                    push_line_trim(
+                        lines,
+                        TerminalLine {
+                            content: cwd.clone(),
+                            line_type: LineType::Output,
+                            spans: Vec::new(),
+                        },
+                    );
+                    Some(0)
+                } else {
                     let target = if std::path::Path::new(&rest).is_absolute() {
                         std::path::PathBuf::from(&rest)
                     } else {
                         std::path::PathBuf::from(&cwd).join(&rest)
                     };
-                    match target.canonicalize() {
-                        Ok(p) if p.is_dir() => {
+                    let confined = target.canonicalize().map(|p| match jail.peek().as_ref() {
+                        Some(jail) => jail.check(&p, &rest).map(|_| p),
+                        None => Ok(p),
+                    });
+                    match confined {
+                        Ok(Err(message)) => {
+                            push_line_trim(
+                                lines,
+                                TerminalLine {
+                                    content: format!("cd: {}", message),
+                                    line_type: LineType::Error,
+                                    spans: Vec::new(),
+                                },
+                            );
+                            Some(1)
+                        }
+                        Ok(Ok(p)) if p.is_dir() => {
                             let s = p.display().to_string();
                             let clean = s.strip_prefix(r"\\?\").unwrap_or(&s).to_string();
                             current_dir.set(clean);
+                            Some(0)
                         }
                         Ok(_) => {
                             push_line_trim(
@@ -160,8 +579,10 @@ pub fn DesktopTerminal() -> Element {
                                 TerminalLine {
                                     content: format!("Not a directory: {}", rest),
                                     line_type: LineType::Error,
+                                    spans: Vec::new(),
                                 },
                             );
+                            Some(1)
                         }
                         Err(e) => {
                             push_line_trim(
@@ -169,79 +590,559 @@ pub fn DesktopTerminal() -> Element {
                                 TerminalLine {
                                     content: format!("cd: {}: {}", rest, e),
                                     line_type: LineType::Error,
+                                    spans: Vec::new(),
                                 },
                             );
+                            Some(1)
                         }
                     }
-                    return;
                 }
-                "pwd" => {
+            }
+            "pwd" => {
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: cwd.clone(),
+                        line_type: LineType::Output,
+                        spans: Vec::new(),
+                    },
+                );
+                Some(0)
+            }
+            "which" if args.len() > 1 => {
+                let mut code = 0;
+                for name in &args[1..] {
+                    let name = name.to_lowercase();
+                    let found = if BUILTIN_COMMANDS.contains(&name.as_str()) {
+                        Ok(format!("{}: Blaze builtin", name))
+                    } else {
+                        which(profile, &command_list.peek(), &executables.peek(), &name)
+                    };
+                    let (content, line_type) = match found {
+                        Ok(content) => (content, LineType::Output),
+                        Err(content) => {
+                            code = 1;
+                            (content, LineType::Error)
+                        }
+                    };
                     push_line_trim(
                         lines,
                         TerminalLine {
-                            content: cwd.clone(),
-                            line_type: LineType::Output,
+                            content,
+                            line_type,
+                            spans: Vec::new(),
                         },
                     );
-                    return;
                 }
-                _ => {}
+                Some(code)
             }
-
-            if !is_allowed_external(&first) {
+            "which" => {
                 push_line_trim(
                     lines,
                     TerminalLine {
-                        content: format!(
-                            "Command '{}' is not allowed. Type 'help' for a list of available commands.",
-                            first
-                        ),
+                        content: "usage: which <command>...".into(),
                         line_type: LineType::Error,
+                        spans: Vec::new(),
                     },
                 );
-                return;
+                Some(2)
             }
-
-            #[cfg(target_os = "windows")]
-            {
-                let lines_sig = lines;
-                let program = first.clone();
-                let argv = args;
-                spawn(async move {
-                    let result = tokio::task::spawn_blocking(move || -> Vec<TerminalLine> {
-                        execute_windows_command(&cwd, &program, &argv)
-                    })
-                    .await;
-
-                    match result {
-                        Ok(lines_out) => {
-                            for line in lines_out {
-                                push_line_trim(lines_sig, line);
-                            }
+            "trash" | "undo" => {
+                let output = handle_trash_command(&Trash::default(), profile, &first, &args);
+                let failed = output.iter().any(|l| l.line_type == LineType::Error);
+                for line in output {
+                    push_line_trim(lines, line);
+                }
+                Some(i32::from(failed))
+            }
+            "audit" if args.len() == 2 && args[1] == "verify" => {
+                let log = AuditLog::default();
+                let (content, line_type, code) = match log.verify() {
+                    Ok(count) => (
+                        format!("audit: {} entries verified in {}", count, log.path().display()),
+                        LineType::Output,
+                        0,
+                    ),
+                    Err(e) => (format!("audit: {}", e), LineType::Error, 1),
+                };
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content,
+                        line_type,
+                        spans: Vec::new(),
+                    },
+                );
+                Some(code)
+            }
+            "audit" => {
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: "usage: audit verify".into(),
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    },
+                );
+                Some(2)
+            }
+            "command" => {
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: "usage: command <program> [args...]".into(),
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    },
+                );
+                Some(2)
+            }
+            "limits" => match (args.get(1), args.get(2), args.len()) {
+                (None, None, _) => {
+                    for line in exec_limits.peek().describe() {
+                        push_line_trim(
+                            lines,
+                            TerminalLine {
+                                content: line,
+                                line_type: LineType::Output,
+                                spans: Vec::new(),
+                            },
+                        );
+                    }
+                    Some(0)
+                }
+                (Some(key), Some(value), 3) => {
+                    let mut updated = *exec_limits.peek();
+                    match updated.set(key, value) {
+                        Ok(()) => {
+                            exec_limits.set(updated);
+                            Some(0)
                         }
-                        Err(e) => {
+                        Err(message) => {
                             push_line_trim(
-                                lines_sig,
+                                lines,
                                 TerminalLine {
-                                    content: format!("Error: {}", e),
+                                    content: format!("limits: {}", message),
                                     line_type: LineType::Error,
+                                    spans: Vec::new(),
                                 },
                             );
+                            Some(1)
+                        }
+                    }
+                }
+                _ => {
+                    push_line_trim(
+                        lines,
+                        TerminalLine {
+                            content: "usage: limits [timeout <secs>|bytes <n>[K|M]|lines <n>|<name> off]".into(),
+                            line_type: LineType::Error,
+                            spans: Vec::new(),
+                        },
+                    );
+                    Some(2)
+                }
+            },
+            #[cfg(not(target_os = "windows"))]
+            "fg" => {
+                let result = match foreground.peek().as_ref() {
+                    Some(job) => job.send(JobSignal::Continue).map_err(|e| format!("fg: {}", e)),
+                    None => Err("fg: no current job".to_string()),
+                };
+                match result {
+                    Ok(()) => {
+                        if let Some(job) = foreground.write().as_mut() {
+                            job.state = JobState::Running;
                         }
+                        Some(0)
+                    }
+                    Err(message) => {
+                        push_line_trim(
+                            lines,
+                            TerminalLine {
+                                content: message,
+                                line_type: LineType::Error,
+                                spans: Vec::new(),
+                            },
+                        );
+                        Some(1)
                     }
+                }
+            }
+            _ => None,
+        };
+        if let Some(code) = builtin_code {
+            record_status(lines, last_exit, &cmd, &cwd, &Decision::Builtin, CommandStatus {
+                outcome: ExitOutcome::Code(code),
+                duration: started.elapsed(),
+            });
+            return;
+        }
+
+        if let Some(job) = foreground.peek().as_ref() {
+            push_line_trim(
+                lines,
+                TerminalLine {
+                    content: format!("'{}' is stopped. Type 'fg' to resume it.", job.command),
+                    line_type: LineType::Error,
+                    spans: Vec::new(),
+                },
+            );
+            let decision = Decision::Denied { rule: "job-stopped" };
+            record_status(lines, last_exit, &cmd, &cwd, &decision, CommandStatus {
+                outcome: ExitOutcome::Code(1),
+                duration: started.elapsed(),
+            });
+            return;
+        }
+
+        let authorized = {
+            let workspace_root = workspace.peek().clone();
+            let jail_root = jail.peek();
+            let commands = command_list.peek();
+            let resolved = executables.peek();
+            let ctx = PolicyContext {
+                profile,
+                commands: &commands,
+                executables: &resolved,
+                cwd: std::path::Path::new(&cwd),
+                workspace: &workspace_root,
+                jail: jail_root.as_ref(),
+            };
+            authorize(ctx, &first, &args, passthrough)
+        };
+        let args = match authorized {
+            Ok(args) => args,
+            Err(denial) => {
+                let decision = Decision::Denied { rule: denial.rule };
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: denial.to_string(),
+                        line_type: LineType::Error,
+                        spans: Vec::new(),
+                    },
+                );
+                // Like a shell's "permission denied".
+                record_status(lines, last_exit, &cmd, &cwd, &decision, CommandStatus {
+                    outcome: ExitOutcome::Code(126),
+                    duration: started.elapsed(),
                 });
+                return;
+            }
+        };
+
+        if !passthrough {
+            if let Some(prompt) = confirmation_prompt(profile, &cwd, &first, &args, confirm_over) {
+                for line in prompt {
+                    push_line_trim(lines, line);
+                }
+                pending_confirmation.set(Some(PendingConfirmation {
+                    cmd,
+                    cwd,
+                    program: first,
+                    argv: args,
+                    rest: None,
+                }));
+                return;
+            }
+        }
+
+        foreground.set(Some(ForegroundJob::new(cmd.clone())));
+        let limits = *exec_limits.peek();
+        // Commands Blaze runs in-process keep their name in `args[0]`; the
+        // policy swaps in an absolute path for everything it spawns.
+        let decision = if std::path::Path::new(&args[0]).is_absolute() {
+            Decision::Allowed { binary: args[0].clone() }
+        } else {
+            Decision::Builtin
+        };
+
+        #[cfg(target_os = "windows")]
+        {
+            let lines_sig = lines;
+            let program = first.clone();
+            let argv = args;
+            let size = *term_size.peek();
+            let audit_cwd = cwd.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                execute_windows_command(profile, &Trash::default(), &cwd, &program, &argv, size, limits, passthrough)
+            })
+            .await;
+
+            let outcome = match result {
+                Ok((lines_out, outcome)) => {
+                    for line in lines_out {
+                        push_line_trim(lines_sig, line);
+                    }
+                    outcome
+                }
+                Err(e) => {
+                    push_line_trim(
+                        lines_sig,
+                        TerminalLine {
+                            content: format!("Error: {}", e),
+                            line_type: LineType::Error,
+                            spans: Vec::new(),
+                        },
+                    );
+                    ExitOutcome::Code(1)
+                }
+            };
+            foreground.set(None);
+            finish_external(lines_sig, last_exit, &cmd, &audit_cwd, &decision, CommandStatus {
+                outcome,
+                duration: started.elapsed(),
+            });
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let lines = lines;
+            // The executable chosen by the policy (a pinned path, if any).
+            let program = args[0].clone();
+            let program_args = args.iter().skip(1).cloned().collect::<Vec<_>>();
+            let audit_cwd = cwd.clone();
+            let sandbox = sandbox.peek().clone();
+
+            if !passthrough {
+                let (cwd, program, argv) = (cwd.clone(), first.clone(), args);
+                let builtin = tokio::task::spawn_blocking(move || run_native_builtin(profile, &Trash::default(), &cwd, &program, &argv, None));
+                if let Ok(Some((output, outcome))) = builtin.await {
+                    for line in output {
+                        push_line_trim(lines, line);
+                    }
+                    foreground.set(None);
+                    finish_external(lines, last_exit, &cmd, &audit_cwd, &Decision::Builtin, CommandStatus {
+                        outcome,
+                        duration: started.elapsed(),
+                    });
+                    return;
+                }
+            }
+            let status = stream_unix_command(
+                cwd,
+                program,
+                program_args,
+                lines,
+                live_lines,
+                term_size,
+                foreground,
+                limits,
+                sandbox,
+            )
+            .await;
+            finish_external(lines, last_exit, &cmd, &audit_cwd, &decision, status);
+        }
+    };
+
+    // Run what is left of a command line, then record the whole line in the
+    // history.
+    let run_sequence = move |mut rest: Remaining| async move {
+        while let Some(item) = rest.items.pop_front() {
+            // A skipped pipeline leaves `$?` as it was.
+            if !item.condition.holds(*last_exit.peek()) {
+                continue;
+            }
+            execute(item.pipeline).await;
+            if let Some(pending) = pending_confirmation.write().as_mut() {
+                // Resumed once the user has answered.
+                pending.rest = Some(rest);
+                return;
+            }
+        }
+        append_history(&rest.line, &CommandStatus {
+            outcome: ExitOutcome::Code(*last_exit.peek()),
+            duration: rest.started.elapsed(),
+        });
+    };
+
+    let handle_key = move |e: KeyboardEvent| match e.key() {
+        #[cfg(not(target_os = "windows"))]
+        Key::Character(ref c) if e.modifiers().ctrl() && foreground.peek().is_some() => {
+            if c.eq_ignore_ascii_case("d") {
+                e.prevent_default();
+                // Like a terminal's EOF key: sends pending input without a
+                // newline, or end-of-file when there is none.
+                let mut bytes = input_value().into_bytes();
+                bytes.push(0x04);
+                input_value.set(String::new());
+                if let Some(job) = foreground.write().as_mut().filter(|job| job.state == JobState::Running) {
+                    job.interactive = true;
+                    let _ = job.write_input(&bytes);
+                }
+                return;
+            }
+            let Some(signal) = JobSignal::for_ctrl_key(c) else {
+                return;
+            };
+            e.prevent_default();
+            let Some(job) = foreground.peek().clone() else {
+                return;
+            };
+            if signal == JobSignal::Suspend {
+                if job.state == JobState::Stopped {
+                    return;
+                }
+                if let Some(job) = foreground.write().as_mut() {
+                    job.state = JobState::Stopped;
+                    job.interactive = true;
+                }
+            }
+            spawn(async move {
+                let _ = tokio::task::spawn_blocking(move || job.send(signal)).await;
+            });
+        }
+        Key::Tab => {
+            e.prevent_default();
+            let current_input = input_value();
+            // On the first Tab press, record the stub; on subsequent presses reuse it.
+            let stub = if tab_state() == 0 {
+                tab_stub.set(current_input.clone());
+                current_input.clone()
+            } else {
+                tab_stub()
+            };
+            let cwd = current_dir();
+            let state = tab_state();
+            refresh_command_list(command_list, executables, profile, lines);
+            let externals = command_list.peek().commands(profile);
+            if let Some(completed) = tab_complete(&externals, jail.peek().as_ref(), &stub, &cwd, state) {
+                input_value.set(completed);
+                tab_state.set(state + 1);
             }
+        }
+        Key::Enter => {
+            // Reset tab cycling on any non-Tab key.
+            tab_state.set(0);
+            tab_stub.set(String::new());
 
+            // While a command runs, the line is typed into its terminal, which
+            // echoes it back unless the program turned echo off.
             #[cfg(not(target_os = "windows"))]
-            {
-                let lines = lines;
-                let program = first.clone();
-                let program_args = args.iter().skip(1).cloned().collect::<Vec<_>>();
+            if let Some(job) = foreground.write().as_mut().filter(|job| job.state == JobState::Running) {
+                let mut line = input_value();
+                line.push('\r');
+                input_value.set(String::new());
+                job.interactive = true;
+                let _ = job.write_input(line.as_bytes());
+                return;
+            }
+            // Windows commands run without stdin.
+            #[cfg(target_os = "windows")]
+            if foreground.peek().is_some() {
+                return;
+            }
 
+            // The line answers the confirmation prompt of a destructive command.
+            let pending = pending_confirmation.write().take();
+            if let Some(PendingConfirmation { cmd, cwd, program, argv, rest }) = pending {
+                let answer = input_value().trim().to_string();
+                input_value.set(String::new());
+                push_line_trim(
+                    lines,
+                    TerminalLine {
+                        content: format!("> {}", answer),
+                        line_type: LineType::Command,
+                        spans: Vec::new(),
+                    },
+                );
+                let started = Instant::now();
+                let confirmed = answer.eq_ignore_ascii_case("y");
+                if confirmed {
+                    foreground.set(Some(ForegroundJob::new(cmd.clone())));
+                } else {
+                    push_line_trim(
+                        lines,
+                        TerminalLine {
+                            content: "Cancelled.".into(),
+                            line_type: LineType::System,
+                            spans: Vec::new(),
+                        },
+                    );
+                    let decision = Decision::Denied { rule: "not-confirmed" };
+                    record_status(lines, last_exit, &cmd, &cwd, &decision, CommandStatus {
+                        outcome: ExitOutcome::Code(1),
+                        duration: started.elapsed(),
+                    });
+                }
                 spawn(async move {
-                    stream_unix_command(cwd, program, program_args, lines).await;
+                    if confirmed {
+                        let dir = cwd.clone();
+                        let result =
+                            tokio::task::spawn_blocking(move || run_native_builtin(profile, &Trash::default(), &dir, &program, &argv, None)).await;
+                        let (output, outcome) = result.ok().flatten().unwrap_or_else(|| {
+                            let error = TerminalLine {
+                                content: "Error: the command did not run".into(),
+                                line_type: LineType::Error,
+                                spans: Vec::new(),
+                            };
+                            (vec![error], ExitOutcome::Code(1))
+                        });
+                        for line in output {
+                            push_line_trim(lines, line);
+                        }
+                        foreground.set(None);
+                        finish_external(lines, last_exit, &cmd, &cwd, &Decision::Builtin, CommandStatus {
+                            outcome,
+                            duration: started.elapsed(),
+                        });
+                    }
+                    if let Some(rest) = rest {
+                        run_sequence(rest).await;
+                    }
                 });
+                return;
+            }
+
+            let cmd = input_value().trim().to_string();
+            if cmd.is_empty() {
+                return;
             }
+            let cwd = current_dir().clone();
+            let started = Instant::now();
+
+            // The history file is written once the line has finished, so the
+            // record can include its status.
+            cmd_history.write().push(cmd.clone());
+            history_idx.set(-1);
+
+            push_line_trim(
+                lines,
+                TerminalLine {
+                    content: format!("{} > {}", cwd, cmd),
+                    line_type: LineType::Command,
+                    spans: Vec::new(),
+                },
+            );
+            input_value.set(String::new());
+            refresh_command_list(command_list, executables, profile, lines);
+
+            let line = match parse(&cmd) {
+                Ok(line) => line,
+                Err(message) => {
+                    push_line_trim(
+                        lines,
+                        TerminalLine {
+                            content: message,
+                            line_type: LineType::Error,
+                            spans: Vec::new(),
+                        },
+                    );
+                    let status = CommandStatus {
+                        outcome: ExitOutcome::Code(2),
+                        duration: started.elapsed(),
+                    };
+                    append_history(&cmd, &status);
+                    record_status(lines, last_exit, &cmd, &cwd, &Decision::Denied { rule: "syntax" }, status);
+                    return;
+                }
+            };
+            spawn(run_sequence(Remaining {
+                line: cmd,
+                started,
+                items: line.items.into(),
+            }));
         }
         Key::ArrowUp => {
             tab_state.set(0);
@@ -285,11 +1186,25 @@ pub fn DesktopTerminal() -> Element {
 
     use_effect(move || {
         let _ = lines();
+        #[cfg(not(target_os = "windows"))]
+        let _ = live_lines();
         document::eval(
             r#"setTimeout(()=>{let e=document.getElementById('terminal-output');if(e)e.scrollTop=e.scrollHeight},10)"#,
         );
     });
 
+    // Windows commands print their output once they finish, so there is never a live screen.
+    #[cfg(not(target_os = "windows"))]
+    let live_output = live_lines;
+    #[cfg(target_os = "windows")]
+    let live_output = || Vec::<TerminalLine>::new();
+
+    // Mask what is typed while a program reads a password.
+    #[cfg(not(target_os = "windows"))]
+    let input_type = if foreground().is_some_and(|job| !job.echo) { "password" } else { "text" };
+    #[cfg(target_os = "windows")]
+    let input_type = "text";
+
     rsx! {
         div { class: "terminal-container terminal-fullscreen",
             div { class: "terminal-header",
@@ -324,7 +1239,7 @@ pub fn DesktopTerminal() -> Element {
                 onclick: move |_| {
                     document::eval(r#"document.getElementById('terminal-input').focus()"#);
                 },
-                for (i, line) in lines().iter().enumerate() {
+                for (i, line) in lines().iter().chain(live_output().iter()).enumerate() {
                     div {
                         key: "{i}",
                         class: match line.line_type {
@@ -333,15 +1248,32 @@ pub fn DesktopTerminal() -> Element {
                             LineType::Error   => "line-error",
                             LineType::System  => "line-system",
                         },
-                        "{line.content}"
+                        if line.spans.is_empty() {
+                            "{line.content}"
+                        } else {
+                            for span in line.spans.iter() {
+                                span { style: style_css(&span.style), "{span.text}" }
+                            }
+                        }
                     }
                 }
                 div { class: "terminal-input-line",
-                    span { class: "prompt", "{current_dir()} > " }
+                    if let Some(job) = foreground() {
+                        if job.state == JobState::Running {
+                            span { class: "prompt prompt-job",
+                                span { class: "prompt-spinner" }
+                                "running · {job.command}"
+                            }
+                        } else {
+                            span { class: "prompt prompt-job", "stopped · {job.command} (type 'fg' to resume)" }
+                        }
+                    } else {
+                        span { class: "prompt", "{current_dir()} > " }
+                    }
                     input {
                         id: "terminal-input",
                         class: "terminal-input",
-                        r#type: "text",
+                        r#type: input_type,
                         value: "{input_value}",
                         autofocus: true,
                         oninput: move |e| input_value.set(e.value()),
@@ -361,14 +1293,17 @@ pub fn WebTerminalDemo() -> Element {
             TerminalLine {
                 content: "⚡ Blaze Terminal v0.1.1 (Web Demo)".into(),
                 line_type: LineType::System,
+                spans: Vec::new(),
             },
             TerminalLine {
                 content: "Type 'help' to see commands.".into(),
                 line_type: LineType::System,
+                spans: Vec::new(),
             },
             TerminalLine {
                 content: String::new(),
                 line_type: LineType::System,
+                spans: Vec::new(),
             },
         ]
     });
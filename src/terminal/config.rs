@@ -0,0 +1,258 @@
+//! Startup settings, read from the config file and the command line.
+//!
+//! The config file lives at `<user home>/.blaze_config` and holds one
+//! `key = value` pair per line; blank lines and lines starting with `#` are
+//! ignored. Command-line flags (`--key value` or `--key=value`) override it.
+//!
+//! | Key            | Values                       |
+//! |----------------|------------------------------|
+//! | `profile`      | `readonly`, `dev`, `full`    |
+//! | `confirm_over` | number of files (default 10) |
+//! | `workspace`    | directory to confine Blaze to |
+//! | `sandbox`      | `on` (default), `off`        |
+//! | `seccomp`      | `on`, `off` (default)        |
+
+use crate::terminal::commands::desktop::{Jail, Profile};
+use crate::terminal::utils::home_dir;
+
+/// Returns the path to the Blaze config file.
+pub fn config_file_path() -> std::path::PathBuf {
+    home_dir().join(".blaze_config")
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub profile: Profile,
+    /// Deleting more files than this at once asks for confirmation.
+    pub confirm_over: usize,
+    /// The workspace root `cd` and the filesystem builtins are confined to.
+    pub jail: Option<Jail>,
+    /// Confine spawned commands with Landlock (Linux only).
+    pub sandbox: bool,
+    /// Also install the seccomp syscall filter (Linux only).
+    pub seccomp: bool,
+    /// Problems found while loading, shown when the terminal starts.
+    pub warnings: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            profile: Profile::default(),
+            confirm_over: 10,
+            jail: None,
+            sandbox: true,
+            seccomp: false,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load the config file, then apply the process's command-line flags.
+    pub fn load() -> Self {
+        let path = config_file_path();
+        let config = std::fs::read_to_string(&path).ok();
+        Self::from_sources(
+            config.as_deref().map(|text| (path.display().to_string(), text)),
+            std::env::args().skip(1),
+        )
+    }
+
+    /// `config` is the config file's name and contents, if it exists.
+    ///
+    /// A `workspace` that cannot be used does not leave Blaze unconfined: it
+    /// starts in the readonly profile, confined to the current directory.
+    fn from_sources(config: Option<(String, &str)>, args: impl IntoIterator<Item = String>) -> Self {
+        let mut settings = Self::default();
+        // Whether the last `workspace` given could not be used.
+        let mut bad_workspace = false;
+        let mut apply = |settings: &mut Self, origin: &str, key: &str, value: &str| {
+            let result = settings.apply(key, value);
+            if key == "workspace" {
+                bad_workspace = result.is_err();
+            }
+            if let Err(e) = result {
+                settings.warnings.push(format!("{}: {}", origin, e));
+            }
+        };
+        if let Some((name, text)) = config {
+            for (i, line) in text.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let origin = format!("{}:{}", name, i + 1);
+                match line.split_once('=') {
+                    Some((key, value)) => apply(&mut settings, &origin, key.trim(), value.trim()),
+                    None => settings.warnings.push(format!("{}: expected 'key = value'", origin)),
+                }
+            }
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                continue;
+            };
+            let (key, value) = match flag.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (flag.to_string(), None),
+            };
+            if !Self::KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            match value.or_else(|| args.next()) {
+                Some(value) => apply(&mut settings, "command line", &key, &value),
+                None => settings.warnings.push(format!("command line: --{} needs a value", key)),
+            }
+        }
+        if bad_workspace {
+            settings.profile = Profile::ReadOnly;
+            let cwd = std::env::current_dir().map_err(|e| e.to_string());
+            match cwd.and_then(|cwd| Jail::new(&cwd)) {
+                Ok(jail) => {
+                    settings.warnings.push(format!(
+                        "the workspace could not be used: starting in the readonly profile, confined to {}",
+                        jail.root().display()
+                    ));
+                    settings.jail = Some(jail);
+                }
+                Err(e) => settings.warnings.push(format!(
+                    "the workspace could not be used: starting in the readonly profile ({})",
+                    e
+                )),
+            }
+        }
+        settings
+    }
+
+    const KEYS: &'static [&'static str] = &["profile", "confirm_over", "workspace", "sandbox", "seccomp"];
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            // A typo must not leave a wider profile in place.
+            "profile" => Profile::parse(value).map(|profile| self.profile = profile).map_err(|e| {
+                self.profile = Profile::ReadOnly;
+                format!("{}; using the readonly profile", e)
+            }),
+            "confirm_over" => value
+                .parse()
+                .map(|count| self.confirm_over = count)
+                .map_err(|_| format!("invalid confirm_over '{}' (expected a number of files)", value)),
+            "workspace" => Jail::new(std::path::Path::new(value)).map(|jail| self.jail = Some(jail)),
+            "sandbox" => parse_switch(key, value).map(|on| self.sandbox = on),
+            "seccomp" => parse_switch(key, value).map(|on| self.seccomp = on),
+            _ => Err(format!("unknown setting '{}'", key)),
+        }
+    }
+}
+
+fn parse_switch(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("invalid {} '{}' (expected on or off)", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Settings;
+    use crate::terminal::commands::desktop::{Jail, Profile};
+
+    fn config_with_bad_workspace() -> Option<(String, &'static str)> {
+        Some(("cfg".to_string(), "workspace = /no/such/blaze/dir\n"))
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let config = Some(("cfg".to_string(), "# team default\nprofile = readonly\n"));
+        assert_eq!(Settings::from_sources(config.clone(), args("")).profile, Profile::ReadOnly);
+        assert_eq!(Settings::from_sources(config.clone(), args("--profile full")).profile, Profile::Full);
+        assert_eq!(Settings::from_sources(config, args("--profile=dev")).profile, Profile::Dev);
+        let config = Some(("cfg".to_string(), "confirm_over = 3\n"));
+        assert_eq!(Settings::from_sources(config.clone(), args("")).confirm_over, 3);
+        assert_eq!(Settings::from_sources(config, args("--confirm_over 50")).confirm_over, 50);
+        let config = Some(("cfg".to_string(), "sandbox = off\nseccomp = on\n"));
+        let settings = Settings::from_sources(config, args("--sandbox=on"));
+        assert!(settings.sandbox && settings.seccomp);
+    }
+
+    #[test]
+    fn invalid_entries_become_warnings() {
+        let config = Some(("cfg".to_string(), "colour = red\nnonsense\nconfirm_over = many\n"));
+        let settings = Settings::from_sources(config, args("--confirm_over"));
+        assert_eq!(settings.confirm_over, 10);
+        assert_eq!(settings.warnings.len(), 4, "{:?}", settings.warnings);
+        assert_eq!(settings.warnings[0], "cfg:1: unknown setting 'colour'");
+        assert_eq!(settings.warnings[1], "cfg:2: expected 'key = value'");
+        assert_eq!(settings.warnings[2], "cfg:3: invalid confirm_over 'many' (expected a number of files)");
+        assert_eq!(settings.warnings[3], "command line: --confirm_over needs a value");
+    }
+
+    #[test]
+    fn an_invalid_profile_falls_back_to_readonly() {
+        let config = Some(("cfg".to_string(), "profile = root\n"));
+        let settings = Settings::from_sources(config, args(""));
+        assert_eq!(settings.profile, Profile::ReadOnly);
+        assert_eq!(
+            settings.warnings,
+            vec!["cfg:1: unknown profile 'root' (expected one of: readonly, dev, full); using the readonly profile"]
+        );
+
+        // A bad value on the command line replaces a good one from the file.
+        let config = Some(("cfg".to_string(), "profile = full\n"));
+        let settings = Settings::from_sources(config, args("--profile=ful"));
+        assert_eq!(settings.profile, Profile::ReadOnly);
+        assert_eq!(settings.warnings.len(), 1, "{:?}", settings.warnings);
+        assert!(settings.warnings[0].starts_with("command line: unknown profile 'ful'"), "{:?}", settings.warnings);
+    }
+
+    #[test]
+    fn a_missing_profile_value_keeps_the_configured_profile() {
+        let config = Some(("cfg".to_string(), "profile = full\n"));
+        let settings = Settings::from_sources(config, args("--profile"));
+        assert_eq!(settings.profile, Profile::Full);
+        assert_eq!(settings.warnings, vec!["command line: --profile needs a value"]);
+    }
+
+    #[test]
+    fn a_bad_workspace_starts_readonly_in_the_current_directory() {
+        let settings = Settings::from_sources(config_with_bad_workspace(), args("--profile full"));
+        let cwd = Jail::new(&std::env::current_dir().unwrap()).unwrap();
+        assert_eq!(settings.profile, Profile::ReadOnly);
+        assert_eq!(settings.warnings.len(), 2, "{:?}", settings.warnings);
+        assert!(settings.warnings[0].starts_with("cfg:1: workspace /no/such/blaze/dir: "), "{:?}", settings.warnings);
+        assert_eq!(
+            settings.warnings[1],
+            format!(
+                "the workspace could not be used: starting in the readonly profile, confined to {}",
+                cwd.root().display()
+            )
+        );
+        assert_eq!(settings.jail, Some(cwd));
+    }
+
+    #[test]
+    fn a_later_workspace_that_works_replaces_a_bad_one() {
+        let dir = std::env::temp_dir();
+        let settings = Settings::from_sources(config_with_bad_workspace(), args(&format!("--workspace {}", dir.display())));
+        assert_eq!(settings.profile, Profile::default());
+        assert_eq!(settings.jail, Some(Jail::new(&dir).unwrap()));
+        assert_eq!(settings.warnings.len(), 1, "{:?}", settings.warnings);
+        assert!(settings.warnings[0].starts_with("cfg:1: workspace /no/such/blaze/dir: "), "{:?}", settings.warnings);
+    }
+
+    #[test]
+    fn an_invalid_switch_keeps_its_default() {
+        let config = Some(("cfg".to_string(), "seccomp = yes\n"));
+        let settings = Settings::from_sources(config, args(""));
+        assert!(!settings.seccomp);
+        assert_eq!(settings.warnings, vec!["cfg:1: invalid seccomp 'yes' (expected on or off)"]);
+    }
+}
@@ -0,0 +1,234 @@
+//! Expansion of parsed command lines, just before each command runs.
+//!
+//! * `~` at the start of an unquoted word, alone or before a `/` (or `\` on
+//!   Windows), is the home directory.
+//! * `$NAME` and `${NAME}` are the value of an environment variable, or
+//!   nothing if it is not set; `${NAME:-default}` is `default` when the
+//!   variable is unset or empty.
+//! * `$?` (or `${?}`) is the exit status of the last command.
+//!
+//! * Then a word with wildcards becomes the files it matches (see
+//!   [`glob`](crate::terminal::glob)); redirection targets are not globbed.
+//!
+//! Variables expand outside quotes and in double quotes; nothing expands in
+//! single quotes. A `$` that starts none of the above is kept as typed. Only
+//! wildcards typed outside quotes count, not those in a variable's value.
+
+use std::path::Path;
+
+use crate::terminal::commands::desktop::Jail;
+use crate::terminal::glob::{glob, Pattern};
+use crate::terminal::parser::{Command, Quote, Redirect, RedirectTarget, Word};
+
+/// What words expand against.
+pub struct Env<'a> {
+    pub home: &'a str,
+    pub last_exit: i32,
+    /// Looks up an environment variable.
+    pub var: &'a dyn Fn(&str) -> Option<String>,
+    /// Where relative patterns are matched.
+    pub cwd: &'a Path,
+    /// Files outside it are never matched.
+    pub jail: Option<&'a Jail>,
+}
+
+/// Expand the words and redirection targets of `command`. Fails on a
+/// malformed `${...}` and a pattern that matches no files.
+pub fn expand_command(command: &Command, env: &Env) -> Result<Command<String>, String> {
+    let mut words = Vec::new();
+    for word in &command.words {
+        words.extend(glob(&expand_pattern(word, env)?, env.cwd, env.jail)?);
+    }
+    let mut redirects = Vec::new();
+    for redirect in &command.redirects {
+        let target = match &redirect.target {
+            RedirectTarget::File { path, append } => RedirectTarget::File {
+                path: expand(path, env)?,
+                append: *append,
+            },
+            RedirectTarget::Fd(fd) => RedirectTarget::Fd(*fd),
+        };
+        redirects.push(Redirect { fd: redirect.fd, target });
+    }
+    Ok(Command { words, redirects })
+}
+
+/// Expand one word into the argument it stands for, without globbing.
+pub fn expand(word: &Word, env: &Env) -> Result<String, String> {
+    expand_pattern(word, env).map(|pattern| pattern.text())
+}
+
+fn expand_pattern(word: &Word, env: &Env) -> Result<Pattern, String> {
+    let mut out = Pattern::default();
+    for (i, part) in word.parts.iter().enumerate() {
+        match part.quote {
+            Quote::Single => out.push_str(&part.text, false),
+            Quote::Double => expand_vars(&part.text, false, env, &mut out)?,
+            Quote::Bare => {
+                let mut text = part.text.as_str();
+                // `~"/x"` is not expanded: the tilde needs a bare `/` or nothing
+                // after it.
+                let tilde = (text == "~" && word.parts.len() == 1)
+                    || text.starts_with("~/")
+                    || (cfg!(target_os = "windows") && text.starts_with("~\\"));
+                if i == 0 && tilde {
+                    out.push_str(env.home, false);
+                    text = &text[1..];
+                }
+                expand_vars(text, true, env, &mut out)?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Append `text` to `out` with its variables expanded. Wildcards in `text`
+/// itself count if `wild`.
+fn expand_vars(text: &str, wild: bool, env: &Env, out: &mut Pattern) -> Result<(), String> {
+    let mut rest = text;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at], wild);
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('?') {
+            out.push_str(&env.last_exit.to_string(), false);
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let inner = closing_brace(braced).map(|end| &braced[..end]);
+            let Some(inner) = inner else {
+                return Err(format!("${{{}: bad substitution", braced));
+            };
+            rest = &braced[inner.len() + 1..];
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (inner, None),
+            };
+            let value = match name {
+                "?" => Some(env.last_exit.to_string()),
+                name if is_name(name) => (env.var)(name),
+                _ => return Err(format!("${{{}}}: bad substitution", inner)),
+            };
+            match (value, default) {
+                (Some(value), Some(default)) if value.is_empty() => expand_vars(default, wild, env, out)?,
+                (Some(value), _) => out.push_str(&value, false),
+                (None, Some(default)) => expand_vars(default, wild, env, out)?,
+                (None, None) => {}
+            }
+        } else {
+            let len = rest
+                .char_indices()
+                .find(|&(i, c)| !(c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())))
+                .map_or(rest.len(), |(i, _)| i);
+            if len == 0 {
+                out.push_str("$", false);
+            } else if let Some(value) = (env.var)(&rest[..len]) {
+                out.push_str(&value, false);
+            }
+            rest = &rest[len..];
+        }
+    }
+    out.push_str(rest, wild);
+    Ok(())
+}
+
+/// Where the `}` closing a `${` is in `text` (which follows the `{`),
+/// skipping nested `${...}` in a default.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A variable name: a letter or `_`, then letters, digits and `_`.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, expand_command, Env};
+    use crate::terminal::parser::{parse, RedirectTarget};
+    use crate::terminal::testing::TempDir;
+    use std::path::Path;
+
+    fn var(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/ada".into()),
+            "PROJECT" => Some("blaze".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    /// The words of `line` (a single command), expanded.
+    fn expanded(line: &str) -> Result<Vec<String>, String> {
+        let env = Env { home: "/home/ada", last_exit: 3, var: &var, cwd: Path::new("/"), jail: None };
+        let mut parsed = parse(line).expect("parse");
+        let command = parsed.items.remove(0).pipeline.commands.remove(0);
+        command.words.iter().map(|word| expand(word, &env)).collect()
+    }
+
+    #[test]
+    fn expands_tilde_variables_and_exit_status() {
+        assert_eq!(expanded("cd ~/src/$PROJECT").unwrap(), vec!["cd", "/home/ada/src/blaze"]);
+        assert_eq!(expanded("echo ~ a~ ~x \"~\"/x").unwrap(), vec!["echo", "/home/ada", "a~", "~x", "~/x"]);
+        assert_eq!(expanded("echo $? ${?}x $PROJECT.rs $UNSET. $").unwrap(), vec!["echo", "3", "3x", "blaze.rs", ".", "$"]);
+        assert_eq!(expanded("echo \"$HOME/${PROJECT}\"").unwrap(), vec!["echo", "/home/ada/blaze"]);
+    }
+
+    #[test]
+    fn defaults_apply_to_unset_and_empty_variables() {
+        assert_eq!(
+            expanded("echo ${UNSET:-dev} ${EMPTY:-x} ${PROJECT:-x} ${UNSET:-$PROJECT-${EMPTY:-1}}").unwrap(),
+            vec!["echo", "dev", "x", "blaze", "blaze-1"]
+        );
+        assert_eq!(expanded("echo ${EMPTY}").unwrap(), vec!["echo", ""]);
+        assert_eq!(expanded("echo ${PROJECT"), Err("${PROJECT: bad substitution".to_string()));
+        assert_eq!(expanded("echo \"${A B}\""), Err("${A B}: bad substitution".to_string()));
+    }
+
+    #[test]
+    fn single_quotes_suppress_expansion() {
+        assert_eq!(expanded("echo '$HOME ~ ${X:-y}' \\\"$PROJECT\\\"").unwrap(), vec![
+            "echo",
+            "$HOME ~ ${X:-y}",
+            "\"blaze\""
+        ]);
+        assert_eq!(expanded("echo '~'/x").unwrap(), vec!["echo", "~/x"]);
+    }
+
+    #[test]
+    fn redirection_targets_are_expanded() {
+        let env = Env { home: "/home/ada", last_exit: 0, var: &var, cwd: Path::new("/"), jail: None };
+        let mut parsed = parse("ls > ~/$PROJECT.txt").expect("parse");
+        let command = expand_command(&parsed.items.remove(0).pipeline.commands.remove(0), &env).expect("expand");
+        assert_eq!(command.words, vec!["ls"]);
+        assert_eq!(command.redirects[0].target, RedirectTarget::File { path: "/home/ada/blaze.txt".into(), append: false });
+    }
+
+    #[test]
+    fn unquoted_wildcards_expand_to_matching_files() {
+        let root = TempDir::new("expand_glob");
+        for file in ["a.rs", "b.rs", "notes.txt"] {
+            std::fs::write(root.join(file), "").expect("write test file");
+        }
+        let pattern = |name: &str| if name == "PATTERN" { Some("*.rs".to_string()) } else { None };
+        let env = Env { home: "/home/ada", last_exit: 0, var: &pattern, cwd: &root, jail: None };
+        let expanded = |line: &str| {
+            let mut parsed = parse(line).expect("parse");
+            expand_command(&parsed.items.remove(0).pipeline.commands.remove(0), &env).map(|command| command.words)
+        };
+
+        assert_eq!(expanded("grep TODO *.rs ?otes.*").unwrap(), vec!["grep", "TODO", "a.rs", "b.rs", "notes.txt"]);
+        assert_eq!(expanded("echo '*.rs' \"*\".rs $PATTERN").unwrap(), vec!["echo", "*.rs", "*.rs", "*.rs"]);
+        assert_eq!(expanded("ls *.md"), Err("no matches for *.md".to_string()));
+    }
+}
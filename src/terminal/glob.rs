@@ -0,0 +1,361 @@
+//! Filename globbing of expanded words.
+//!
+//! * `*` matches any run of characters and `?` any one character, within one
+//!   path component.
+//! * `[abc]`, `[a-z]` and `[!a-z]` (or `[^a-z]`) match one character in, or
+//!   not in, the set. A `[` without a closing `]` is an ordinary character.
+//! * `**` as a whole component matches any number of directories, including
+//!   none; as the last component it matches every file below.
+//!
+//! Only characters typed outside quotes act as wildcards. Names starting with
+//! `.` are only matched by a pattern that starts with `.` too, and `**` does
+//! not descend into them. A pattern that matches nothing is an error rather
+//! than being passed on as typed.
+
+use std::path::{Path, PathBuf};
+
+use crate::terminal::commands::desktop::Jail;
+
+/// Directory entries a single pattern may look at, so that `**` in a huge
+/// tree fails instead of freezing the terminal.
+const MAX_ENTRIES: usize = 100_000;
+
+/// An expanded word, with the characters that may act as wildcards marked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern {
+    chars: Vec<(char, bool)>,
+}
+
+impl Pattern {
+    /// Append `text`; its wildcards count only if `wild`.
+    pub fn push_str(&mut self, text: &str, wild: bool) {
+        self.chars.extend(text.chars().map(|c| (c, wild)));
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().map(|&(c, _)| c).collect()
+    }
+}
+
+/// One character of a component.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`: inclusive ranges (a single character is a range of one).
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(want) if cfg!(target_os = "windows") => want.eq_ignore_ascii_case(&c),
+            Self::Char(want) => *want == c,
+            Self::Any => true,
+            Self::Star => false,
+            Self::Class { negated, ranges } => ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated,
+        }
+    }
+}
+
+/// One path component of a pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Component {
+    Literal(String),
+    /// `**`
+    Recursive,
+    Match(Vec<Token>),
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || (cfg!(target_os = "windows") && c == '\\')
+}
+
+/// The files `pattern` matches, relative to `cwd` and spelled the way the
+/// pattern was typed, sorted. A word without wildcards is returned as it is.
+/// Matches outside `jail` are left out.
+pub fn glob(pattern: &Pattern, cwd: &Path, jail: Option<&Jail>) -> Result<Vec<String>, String> {
+    let text = pattern.text();
+    let chars = &pattern.chars;
+    // Everything up to the separator before the first wildcard is a plain
+    // path, which may be absolute or carry a Windows drive.
+    let Some(first_wild) = chars.iter().position(|&(c, wild)| wild && matches!(c, '*' | '?' | '[')) else {
+        return Ok(vec![text]);
+    };
+    let split = chars[..first_wild].iter().rposition(|&(c, _)| is_separator(c)).map_or(0, |i| i + 1);
+    let prefix: String = chars[..split].iter().map(|&(c, _)| c).collect();
+    let components: Vec<Component> = chars[split..].split(|&(c, _)| is_separator(c)).map(compile).collect();
+    if components.iter().all(|component| matches!(component, Component::Literal(_))) {
+        // Only `[`s without a `]`.
+        return Ok(vec![text]);
+    }
+    let separator = chars[..split].iter().rev().map(|&(c, _)| c).find(|&c| is_separator(c)).unwrap_or('/');
+
+    let base = if prefix.is_empty() { cwd.to_path_buf() } else { cwd.join(&prefix) };
+    let mut walker = Walker { separator, budget: MAX_ENTRIES };
+    let mut found = vec![(base, prefix)];
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        found = walker.step(found, component, last).map_err(|()| format!("{}: too many files to search", text))?;
+    }
+
+    let mut matches: Vec<String> = found
+        .into_iter()
+        .filter(|(path, _)| jail.is_none_or(|jail| jail.contains(path)))
+        .map(|(_, shown)| shown)
+        .collect();
+    if matches.is_empty() {
+        return Err(format!("no matches for {}", text));
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+fn compile(chars: &[(char, bool)]) -> Component {
+    if chars == [('*', true), ('*', true)] {
+        return Component::Recursive;
+    }
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (c, wild) = chars[i];
+        i += 1;
+        let token = match c {
+            '*' if wild => Token::Star,
+            '?' if wild => Token::Any,
+            '[' if wild => match class(&chars[i..]) {
+                Some((token, len)) => {
+                    i += len;
+                    token
+                }
+                None => Token::Char('['),
+            },
+            c => Token::Char(c),
+        };
+        // `**` inside a component is the same as `*`.
+        if !(token == Token::Star && tokens.last() == Some(&Token::Star)) {
+            tokens.push(token);
+        }
+    }
+    if tokens.iter().all(|token| matches!(token, Token::Char(_))) {
+        Component::Literal(chars.iter().map(|&(c, _)| c).collect())
+    } else {
+        Component::Match(tokens)
+    }
+}
+
+/// The class starting after a `[`, and how many characters it takes up
+/// including the closing `]`.
+fn class(chars: &[(char, bool)]) -> Option<(Token, usize)> {
+    let negated = matches!(chars.first(), Some(&('!' | '^', true)));
+    let start = usize::from(negated);
+    let mut ranges = Vec::new();
+    let mut i = start;
+    while i < chars.len() {
+        let (c, wild) = chars[i];
+        // A `]` right at the start is part of the set.
+        if c == ']' && wild && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        match chars.get(i + 1..i + 3) {
+            Some(&[('-', _), (hi, _)]) if hi != ']' => {
+                ranges.push((c, hi));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+fn matches(tokens: &[Token], name: &[char]) -> bool {
+    // Only the most recent `*` needs to be retried: letting it take one more
+    // character covers every way an earlier one could have matched, so this
+    // is O(tokens * name) however many stars there are.
+    let (mut t, mut n) = (0, 0);
+    let mut retry = None;
+    while n < name.len() {
+        match tokens.get(t) {
+            Some(Token::Star) => {
+                retry = Some((t, n));
+                t += 1;
+            }
+            Some(token) if token.matches(name[n]) => {
+                t += 1;
+                n += 1;
+            }
+            _ => match retry {
+                Some((star, taken)) => {
+                    retry = Some((star, taken + 1));
+                    t = star + 1;
+                    n = taken + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[t..].iter().all(|token| *token == Token::Star)
+}
+
+/// Walks the directory tree one component at a time.
+struct Walker {
+    separator: char,
+    /// Directory entries left to look at.
+    budget: usize,
+}
+
+impl Walker {
+    /// The paths below `found` that match `component`. Fails once the budget
+    /// runs out.
+    fn step(&mut self, found: Vec<(PathBuf, String)>, component: &Component, last: bool) -> Result<Vec<(PathBuf, String)>, ()> {
+        let mut next = Vec::new();
+        for (path, shown) in found {
+            match component {
+                // A trailing separator keeps only directories.
+                Component::Literal(name) if name.is_empty() => {
+                    if path.is_dir() {
+                        let shown = self.join(&shown, "");
+                        next.push((path, shown));
+                    }
+                }
+                Component::Literal(name) => {
+                    let path = path.join(name);
+                    if std::fs::symlink_metadata(&path).is_ok() {
+                        next.push((path, self.join(&shown, name)));
+                    }
+                }
+                Component::Recursive => {
+                    if !last {
+                        next.push((path.clone(), shown.clone()));
+                    }
+                    self.descend(&path, &shown, !last, &mut next)?;
+                }
+                Component::Match(tokens) => {
+                    let dotted = tokens.first() == Some(&Token::Char('.'));
+                    for (name, entry) in self.entries(&path)? {
+                        let chars: Vec<char> = name.chars().collect();
+                        if (dotted || !name.starts_with('.')) && matches(tokens, &chars) {
+                            next.push((entry, self.join(&shown, &name)));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(next)
+    }
+
+    /// Everything below `path` (only directories if `dirs_only`), without
+    /// following symlinks or entering hidden directories.
+    fn descend(&mut self, path: &Path, shown: &str, dirs_only: bool, out: &mut Vec<(PathBuf, String)>) -> Result<(), ()> {
+        for (name, entry) in self.entries(path)? {
+            if name.starts_with('.') {
+                continue;
+            }
+            let is_dir = std::fs::symlink_metadata(&entry).is_ok_and(|meta| meta.is_dir());
+            let entry_shown = self.join(shown, &name);
+            if is_dir || !dirs_only {
+                out.push((entry.clone(), entry_shown.clone()));
+            }
+            if is_dir {
+                self.descend(&entry, &entry_shown, dirs_only, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The entries of the directory `path` (none if it is not one).
+    fn entries(&mut self, path: &Path) -> Result<Vec<(String, PathBuf)>, ()> {
+        let Ok(dir) = std::fs::read_dir(path) else {
+            return Ok(Vec::new());
+        };
+        let mut entries = Vec::new();
+        for entry in dir.flatten() {
+            self.budget = self.budget.checked_sub(1).ok_or(())?;
+            entries.push((entry.file_name().to_string_lossy().to_string(), entry.path()));
+        }
+        Ok(entries)
+    }
+
+    fn join(&self, shown: &str, name: &str) -> String {
+        if shown.is_empty() || shown.ends_with(is_separator) {
+            format!("{}{}", shown, name)
+        } else {
+            format!("{}{}{}", shown, self.separator, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, glob, matches as matches_name, Component, Pattern};
+    use crate::terminal::commands::desktop::Jail;
+    use crate::terminal::testing::TempDir;
+    use std::path::Path;
+
+    fn pattern(text: &str) -> Pattern {
+        let mut pattern = Pattern::default();
+        pattern.push_str(text, true);
+        pattern
+    }
+
+    fn matches(text: &str, cwd: &Path) -> Result<Vec<String>, String> {
+        glob(&pattern(text), cwd, None)
+    }
+
+    #[test]
+    fn matches_wildcards_classes_and_recursive_components() {
+        let root = TempDir::new("glob");
+        for dir in ["src/bin", "src/.cache", "docs"] {
+            std::fs::create_dir_all(root.join(dir)).expect("create test dirs");
+        }
+        for file in ["a.rs", "b.rs", "c.txt", ".hidden.rs", "src/main.rs", "src/bin/tool.rs", "src/.cache/x.rs"] {
+            std::fs::write(root.join(file), "").expect("write test file");
+        }
+
+        assert_eq!(matches("*.rs", &root).unwrap(), vec!["a.rs", "b.rs"]);
+        assert_eq!(matches(".*.rs", &root).unwrap(), vec![".hidden.rs"]);
+        assert_eq!(matches("?.txt", &root).unwrap(), vec!["c.txt"]);
+        assert_eq!(matches("[ab].rs", &root).unwrap(), vec!["a.rs", "b.rs"]);
+        assert_eq!(matches("[!a-b]*", &root).unwrap(), vec!["c.txt", "docs", "src"]);
+        assert_eq!(matches("src/*.rs", &root).unwrap(), vec!["src/main.rs"]);
+        assert_eq!(matches("*/", &root).unwrap(), vec!["docs/", "src/"]);
+        assert_eq!(matches("**/*.rs", &root).unwrap(), vec!["a.rs", "b.rs", "src/bin/tool.rs", "src/main.rs"]);
+        assert_eq!(matches("src/**", &root).unwrap(), vec!["src/bin", "src/bin/tool.rs", "src/main.rs"]);
+        let absolute = format!("{}/*.txt", root.display());
+        assert_eq!(matches(&absolute, Path::new("/")).unwrap(), vec![format!("{}/c.txt", root.display())]);
+
+        // No wildcards, or only an unclosed `[`: kept as typed.
+        assert_eq!(matches("missing.rs", &root).unwrap(), vec!["missing.rs"]);
+        assert_eq!(matches("[", &root).unwrap(), vec!["["]);
+        assert_eq!(matches("*.md", &root), Err("no matches for *.md".to_string()));
+
+        // Quoted wildcards are ordinary characters.
+        let mut quoted = Pattern::default();
+        quoted.push_str("*", false);
+        quoted.push_str(".rs", true);
+        assert_eq!(glob(&quoted, &root, None).unwrap(), vec!["*.rs"]);
+
+        let jail = Jail::new(&root.join("src")).expect("jail");
+        assert_eq!(glob(&pattern("../*.rs"), &root.join("src"), Some(&jail)), Err("no matches for ../*.rs".to_string()));
+        assert_eq!(glob(&pattern("*.rs"), &root.join("src"), Some(&jail)).unwrap(), vec!["main.rs"]);
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_exponentially() {
+        let tokens = |text: &str| match compile(&pattern(text).chars) {
+            Component::Match(tokens) => tokens,
+            other => panic!("{other:?}"),
+        };
+        let name: Vec<char> = "a".repeat(200).chars().collect();
+        assert!(!matches_name(&tokens("*a*a*a*a*a*a*a*a*a*a*a*a*b"), &name));
+        assert!(matches_name(&tokens("*a*a*a*a*a*a*a*a*a*a*a*a*"), &name));
+        assert!(matches_name(&tokens("a*?a"), &['a', 'b', 'a']));
+        assert!(!matches_name(&tokens("a*?a"), &['a', 'a']));
+    }
+}
@@ -1,7 +1,23 @@
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod audit;
 pub mod commands;
 pub mod components;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod config;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod expand;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod glob;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod parser;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod screen;
 pub mod state;
+#[cfg(all(test, feature = "desktop", not(target_arch = "wasm32")))]
+pub mod testing;
 pub mod utils;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub mod vt;
 
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
 pub use components::DesktopTerminal;
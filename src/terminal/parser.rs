@@ -0,0 +1,416 @@
+//! Parsing of command lines typed into the desktop terminal.
+//!
+//! This is not a shell: the only syntax is what Blaze itself executes, so
+//! every command a line contains still goes through the policy on its own.
+//!
+//! * Words are separated by whitespace. Quotes group words with spaces:
+//!   nothing is special in single quotes, while variables still expand in
+//!   double quotes (see [`expand`](crate::terminal::expand)). `\"` is a
+//!   literal quote; any other backslash is kept as typed, so Windows paths
+//!   need no escaping.
+//! * `|` connects the output of one command to the input of the next.
+//! * `> file` sends a command's output to a file and `>> file` appends it;
+//!   `2>` and `2>>` do the same for its error output. `2>&1` sends error
+//!   output wherever the output goes at that point, and `>&2` the reverse.
+//! * `;` separates pipelines that run one after another. A pipeline after
+//!   `&&` only runs if the previous one succeeded, and after `||` only if it
+//!   failed; skipped pipelines leave the exit status as it was, so
+//!   `a && b || c` runs `c` when either `a` or `b` fails.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// A command line: pipelines run in order, each under its [`Condition`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandLine {
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    pub condition: Condition,
+    pub pipeline: Pipeline,
+}
+
+/// When a pipeline runs, given the exit status of the last one that ran.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// First on the line, or after `;`.
+    Always,
+    /// After `&&`.
+    Succeeded,
+    /// After `||`.
+    Failed,
+}
+
+impl Condition {
+    pub fn holds(self, last_exit: i32) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Succeeded => last_exit == 0,
+            Self::Failed => last_exit != 0,
+        }
+    }
+
+    fn operator(self) -> &'static str {
+        match self {
+            Self::Always => ";",
+            Self::Succeeded => "&&",
+            Self::Failed => "||",
+        }
+    }
+}
+
+/// A pipeline: one or more commands connected by `|`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    /// The pipeline as typed, for the history and the audit log.
+    pub text: String,
+}
+
+/// One command: its words (program first) and redirections, in the order
+/// they were typed. Parsed commands hold [`Word`]s, and
+/// [`expand`](crate::terminal::expand) turns them into plain strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Command<W = Word> {
+    pub words: Vec<W>,
+    pub redirects: Vec<Redirect<W>>,
+}
+
+/// `fd` (1 for output, 2 for error output) sent to `target`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect<W = Word> {
+    pub fd: u8,
+    pub target: RedirectTarget<W>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RedirectTarget<W = Word> {
+    File { path: W, append: bool },
+    /// Wherever the other descriptor currently goes (`2>&1`, `>&2`).
+    Fd(u8),
+}
+
+/// A word as typed, in pieces that were quoted differently.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Word {
+    pub parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub text: String,
+    pub quote: Quote,
+}
+
+/// How a [`Part`] of a word was quoted, which decides what expands in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quote {
+    /// Not quoted: `~` and variables expand.
+    Bare,
+    /// In double quotes: variables expand.
+    Double,
+    /// In single quotes, or an escaped `\"`: taken as it is.
+    Single,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(Word),
+    Pipe,
+    /// `>` or `>>` for `fd`, with the file in the next word, or `>&n`.
+    Redirect { fd: u8, append: bool, dup: Option<u8> },
+    /// `;`, `&&` or `||`, with the byte range it covers in the input.
+    Connector { next: Condition, start: usize, end: usize },
+}
+
+/// Parse a command line. Fails on an operator without a command on both
+/// sides (a trailing `;` is fine) and a redirection without a file.
+pub fn parse(input: &str) -> Result<CommandLine, String> {
+    let mut line = CommandLine::default();
+    let mut condition = Condition::Always;
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for token in tokenize(input)? {
+        let Token::Connector { next, start: op_start, end } = token else {
+            tokens.push(token);
+            continue;
+        };
+        if tokens.is_empty() {
+            return Err(syntax_error(next.operator()));
+        }
+        let pipeline = parse_pipeline(std::mem::take(&mut tokens), &input[start..op_start])?;
+        line.items.push(Item { condition, pipeline });
+        condition = next;
+        start = end;
+    }
+    if !tokens.is_empty() {
+        let pipeline = parse_pipeline(tokens, &input[start..])?;
+        line.items.push(Item { condition, pipeline });
+    } else if condition != Condition::Always {
+        return Err(syntax_error(condition.operator()));
+    }
+    Ok(line)
+}
+
+/// Parse the tokens between two connectors, typed as `text`.
+fn parse_pipeline(tokens: Vec<Token>, text: &str) -> Result<Pipeline, String> {
+    let mut commands = vec![Command::default()];
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let command = commands.last_mut().expect("at least one command");
+        match token {
+            Token::Word(word) => command.words.push(word),
+            Token::Redirect { fd, append: _, dup: Some(other) } => {
+                command.redirects.push(Redirect { fd, target: RedirectTarget::Fd(other) });
+            }
+            Token::Redirect { fd, append, dup: None } => {
+                let Some(Token::Word(path)) = tokens.next() else {
+                    return Err(syntax_error(if append { ">>" } else { ">" }));
+                };
+                command.redirects.push(Redirect { fd, target: RedirectTarget::File { path, append } });
+            }
+            Token::Pipe => {
+                if command.words.is_empty() {
+                    return Err(syntax_error("|"));
+                }
+                commands.push(Command::default());
+            }
+            Token::Connector { .. } => unreachable!("connectors split the line before this"),
+        }
+    }
+    let last = commands.last().expect("at least one command");
+    if last.words.is_empty() {
+        return Err(syntax_error(if commands.len() > 1 { "|" } else { ">" }));
+    }
+    Ok(Pipeline { commands, text: text.trim().to_string() })
+}
+
+fn syntax_error(near: &str) -> String {
+    format!("syntax error near '{}'", near)
+}
+
+/// Tokens so far and the word in progress.
+#[derive(Default)]
+struct Lexer {
+    tokens: Vec<Token>,
+    current: Word,
+    /// A word is in progress, even if empty so far (`""` is an empty
+    /// argument).
+    in_word: bool,
+}
+
+impl Lexer {
+    fn push(&mut self, quote: Quote, c: char) {
+        self.in_word = true;
+        match self.current.parts.last_mut() {
+            Some(part) if part.quote == quote => part.text.push(c),
+            _ => self.current.parts.push(Part { text: c.to_string(), quote }),
+        }
+    }
+
+    /// Start a quoted part, which makes a word even if it stays empty.
+    fn open(&mut self, quote: Quote) {
+        self.in_word = true;
+        self.current.parts.push(Part { text: String::new(), quote });
+    }
+
+    /// The word in progress if it is a bare `1` or `2`, which before `>`
+    /// names the descriptor to redirect.
+    fn take_fd(&mut self) -> Option<u8> {
+        let [Part { text, quote: Quote::Bare }] = self.current.parts.as_slice() else {
+            return None;
+        };
+        let fd = match text.as_str() {
+            "1" => 1,
+            "2" => 2,
+            _ => return None,
+        };
+        self.current.parts.clear();
+        self.in_word = false;
+        Some(fd)
+    }
+
+    fn end_word(&mut self) {
+        if std::mem::take(&mut self.in_word) {
+            self.tokens.push(Token::Word(std::mem::take(&mut self.current)));
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut lexer = Lexer::default();
+    // The quotes the next character is in.
+    let mut quote = Quote::Bare;
+
+    let mut chars = input.char_indices().peekable();
+    while let Some((at, ch)) = chars.next() {
+        match (quote, ch) {
+            (Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::Bare,
+            (Quote::Single, c) => lexer.push(Quote::Single, c),
+            (Quote::Bare, '"') | (Quote::Bare, '\'') => {
+                quote = if ch == '"' { Quote::Double } else { Quote::Single };
+                lexer.open(quote);
+            }
+            (_, '\\') if next_is(&mut chars, '"') => lexer.push(Quote::Single, '"'),
+            (Quote::Double, c) => lexer.push(Quote::Double, c),
+            (Quote::Bare, '|' | '&' | ';') => {
+                let next = match ch {
+                    ';' => Some(Condition::Always),
+                    '|' if next_is(&mut chars, '|') => Some(Condition::Failed),
+                    '|' => None,
+                    _ if next_is(&mut chars, '&') => Some(Condition::Succeeded),
+                    // A lone `&` is an ordinary character.
+                    _ => {
+                        lexer.push(Quote::Bare, '&');
+                        continue;
+                    }
+                };
+                lexer.end_word();
+                lexer.tokens.push(match next {
+                    Some(next) => Token::Connector { next, start: at, end: at + next.operator().len() },
+                    None => Token::Pipe,
+                });
+            }
+            (Quote::Bare, '>') => {
+                let fd = lexer.take_fd().unwrap_or_else(|| {
+                    lexer.end_word();
+                    1
+                });
+                let append = next_is(&mut chars, '>');
+                let dup = if !append && next_is(&mut chars, '&') {
+                    match chars.next_if(|&(_, c)| matches!(c, '1' | '2')) {
+                        Some((_, other)) => Some(other as u8 - b'0'),
+                        None => return Err(syntax_error(">&")),
+                    }
+                } else {
+                    None
+                };
+                lexer.tokens.push(Token::Redirect { fd, append, dup });
+            }
+            (Quote::Bare, c) if c.is_whitespace() => lexer.end_word(),
+            (Quote::Bare, c) => lexer.push(Quote::Bare, c),
+        }
+    }
+    lexer.end_word();
+    Ok(lexer.tokens)
+}
+
+/// Consume the next character if it is `want`.
+fn next_is(chars: &mut Peekable<CharIndices>, want: char) -> bool {
+    chars.next_if(|&(_, c)| c == want).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Condition, Part, Pipeline, Quote, Redirect, RedirectTarget, Word};
+
+    fn pipeline(input: &str) -> Pipeline {
+        let mut line = parse(input).expect("parse");
+        assert_eq!(line.items.len(), 1, "{input}");
+        line.items.remove(0).pipeline
+    }
+
+    /// The words as typed, without their quotes.
+    fn texts(words: &[Word]) -> Vec<String> {
+        words.iter().map(|word| word.parts.iter().map(|part| part.text.as_str()).collect()).collect()
+    }
+
+    fn words(input: &str) -> Vec<String> {
+        let pipeline = pipeline(input);
+        assert_eq!(pipeline.commands.len(), 1, "{input}");
+        texts(&pipeline.commands[0].words)
+    }
+
+    #[test]
+    fn handles_quoted_segments() {
+        assert_eq!(words("echo \"hello world\" test"), vec!["echo", "hello world", "test"]);
+        assert_eq!(words("echo \"he\\\"llo\""), vec!["echo", "he\"llo"]);
+        assert_eq!(words("echo \"\" C:\\Users"), vec!["echo", "", "C:\\Users"]);
+        assert_eq!(words("echo 'it''s \"here\"' \"a'b\""), vec!["echo", "its \"here\"", "a'b"]);
+    }
+
+    #[test]
+    fn records_how_each_part_was_quoted() {
+        let part = |text: &str, quote| Part { text: text.into(), quote };
+        let command = pipeline("echo ~/'$A'\"$B\"\\\"").commands.remove(0);
+        assert_eq!(
+            command.words[1].parts,
+            vec![part("~/", Quote::Bare), part("$A", Quote::Single), part("$B", Quote::Double), part("\"", Quote::Single)]
+        );
+    }
+
+    #[test]
+    fn preserves_other_shell_metacharacters_as_literals() {
+        assert_eq!(words("echo a&b $(rm -rf x) `id` <in"), vec!["echo", "a&b", "$(rm", "-rf", "x)", "`id`", "<in"]);
+        assert_eq!(words("echo \"a && b; c || d\""), vec!["echo", "a && b; c || d"]);
+    }
+
+    #[test]
+    fn splits_sequences_into_conditional_pipelines() {
+        let line = parse("cd project&&ls ; make || echo failed;").expect("parse");
+        let items: Vec<_> = line.items.iter().map(|item| (item.condition, item.pipeline.text.as_str())).collect();
+        assert_eq!(
+            items,
+            vec![
+                (Condition::Always, "cd project"),
+                (Condition::Succeeded, "ls"),
+                (Condition::Always, "make"),
+                (Condition::Failed, "echo failed"),
+            ]
+        );
+        assert_eq!(texts(&line.items[3].pipeline.commands[0].words), vec!["echo", "failed"]);
+        assert_eq!(pipeline("cat x | grep y > out").text, "cat x | grep y > out");
+
+        assert!(Condition::Succeeded.holds(0) && !Condition::Succeeded.holds(1));
+        assert!(Condition::Failed.holds(127) && !Condition::Failed.holds(0));
+        for (input, near) in [("&& ls", "&&"), ("ls ||", "||"), ("ls ;; pwd", ";"), ("; ls", ";"), ("ls && | x", "|")] {
+            assert_eq!(parse(input), Err(format!("syntax error near '{}'", near)), "{input}");
+        }
+    }
+
+    #[test]
+    fn collapses_whitespace_between_args() {
+        assert_eq!(words("  grep    TODO   file.rs  "), vec!["grep", "TODO", "file.rs"]);
+    }
+
+    #[test]
+    fn splits_pipelines_outside_quotes() {
+        let pipeline = pipeline("cat log.txt|grep ERROR | grep \"a|b\"");
+        let commands: Vec<_> = pipeline.commands.iter().map(|c| texts(&c.words)).collect();
+        assert_eq!(commands, vec![vec!["cat", "log.txt"], vec!["grep", "ERROR"], vec!["grep", "a|b"]]);
+        assert!(parse("   ").expect("parse").items.is_empty());
+        for input in ["| grep x", "cat x |", "cat x | | grep y"] {
+            assert_eq!(parse(input), Err("syntax error near '|'".to_string()), "{input}");
+        }
+    }
+
+    #[test]
+    fn parses_redirections() {
+        let file = |fd, path: &str, append| {
+            let path = Word { parts: vec![Part { text: path.into(), quote: Quote::Bare }] };
+            Redirect { fd, target: RedirectTarget::File { path, append } }
+        };
+        let mut make_grep = pipeline("make>build.log 2>&1 | grep x 2>>err.txt");
+        let grep = make_grep.commands.pop().expect("grep");
+        let make = make_grep.commands.pop().expect("make");
+        assert_eq!(texts(&make.words), vec!["make"]);
+        assert_eq!(make.redirects, vec![file(1, "build.log", false), Redirect { fd: 2, target: RedirectTarget::Fd(1) }]);
+        assert_eq!(texts(&grep.words), vec!["grep", "x"]);
+        assert_eq!(grep.redirects, vec![file(2, "err.txt", true)]);
+
+        // Only a bare, unquoted 1 or 2 names a descriptor.
+        let command = pipeline("echo 3> a \"2\">b \">\" >&2").commands.remove(0);
+        assert_eq!(texts(&command.words), vec!["echo", "3", "2", ">"]);
+        assert_eq!(
+            command.redirects,
+            vec![file(1, "a", false), file(1, "b", false), Redirect { fd: 1, target: RedirectTarget::Fd(2) }]
+        );
+
+        assert_eq!(parse("ls >"), Err("syntax error near '>'".to_string()));
+        assert_eq!(parse("ls >> | cat"), Err("syntax error near '>>'".to_string()));
+        assert_eq!(parse("ls 2>&x"), Err("syntax error near '>&'".to_string()));
+        assert_eq!(parse("> out"), Err("syntax error near '>'".to_string()));
+    }
+}
@@ -0,0 +1,699 @@
+//! Cell-grid terminal screen model.
+//!
+//! A [`Screen`] sits between a child's output stream and the `lines` signal.
+//! Bytes are decoded by [`VtParser`] and applied to a rows × columns grid with
+//! a cursor, scroll region and an alternate screen buffer, so cursor movement,
+//! erase sequences and full-screen programs render the way a real terminal
+//! would. Rows that scroll off the top of the primary screen are collected as
+//! scrollback and become permanent output lines.
+
+use crate::terminal::state::{LineType, StyledSpan, TerminalLine, TextStyle};
+use crate::terminal::vt::{apply_sgr, VtAction, VtParser};
+
+const TAB_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    ch: char,
+    style: TextStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            style: TextStyle::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Cursor {
+    row: usize,
+    col: usize,
+    /// Set after printing into the last column; the next printable character
+    /// wraps to the following line first (xterm "deferred wrap").
+    pending_wrap: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct SavedCursor {
+    cursor: Cursor,
+    style: TextStyle,
+}
+
+/// A terminal screen: primary and alternate grids plus cursor state.
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    primary: Vec<Vec<Cell>>,
+    /// The alternate grid; `Some` while a program has switched to it.
+    alternate: Option<Vec<Vec<Cell>>>,
+    cursor: Cursor,
+    saved: SavedCursor,
+    /// Primary-screen cursor saved when entering the alternate screen.
+    saved_primary: SavedCursor,
+    style: TextStyle,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    auto_wrap: bool,
+    cursor_visible: bool,
+    parser: VtParser,
+    scrollback: Vec<TerminalLine>,
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        Self {
+            cols,
+            rows,
+            primary: blank_grid(cols, rows),
+            alternate: None,
+            cursor: Cursor::default(),
+            saved: SavedCursor::default(),
+            saved_primary: SavedCursor::default(),
+            style: TextStyle::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            auto_wrap: true,
+            cursor_visible: true,
+            parser: VtParser::default(),
+            scrollback: Vec::new(),
+        }
+    }
+
+    /// Decode and apply a chunk of child output.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for action in self.parser.feed(bytes) {
+            self.apply(action);
+        }
+    }
+
+    /// Change the grid geometry, keeping existing content anchored top-left.
+    ///
+    /// When the primary screen loses rows below the cursor's line, the rows
+    /// above are scrolled into scrollback so the cursor stays on screen. On
+    /// the alternate screen that line is the one saved on entering it, so
+    /// leaving it lands back on screen.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        if (cols, rows) == (self.cols, self.rows) {
+            return;
+        }
+
+        let primary_cursor = match self.alternate {
+            Some(_) => &mut self.saved_primary.cursor,
+            None => &mut self.cursor,
+        };
+        if primary_cursor.row >= rows {
+            let excess = primary_cursor.row + 1 - rows;
+            primary_cursor.row -= excess;
+            for removed in self.primary.drain(..excess) {
+                self.scrollback.push(row_to_line(&removed, None));
+            }
+        }
+        if let Some(grid) = &mut self.alternate {
+            if self.cursor.row >= rows {
+                let excess = self.cursor.row + 1 - rows;
+                grid.drain(..excess);
+                self.cursor.row -= excess;
+            }
+        }
+        for saved in [&mut self.saved.cursor, &mut self.saved_primary.cursor] {
+            saved.row = saved.row.min(rows - 1);
+            saved.col = saved.col.min(cols - 1);
+        }
+
+        for grid in std::iter::once(&mut self.primary).chain(self.alternate.as_mut()) {
+            grid.resize_with(rows, || vec![Cell::default(); cols]);
+            for line in grid.iter_mut() {
+                line.resize(cols, Cell::default());
+            }
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.move_to(self.cursor.row, self.cursor.col);
+    }
+
+    /// Whether a full-screen program has switched to the alternate screen.
+    pub fn is_alternate(&self) -> bool {
+        self.alternate.is_some()
+    }
+
+    /// Rows that scrolled off the top of the primary screen since the last call.
+    pub fn take_scrollback(&mut self) -> Vec<TerminalLine> {
+        std::mem::take(&mut self.scrollback)
+    }
+
+    /// The rows currently on screen, ready to be displayed.
+    ///
+    /// On the primary screen, rows below the last one that holds output are
+    /// omitted so short command output does not pad the view with blank lines.
+    /// The alternate screen is always shown in full, with the cursor cell
+    /// highlighted.
+    pub fn visible_lines(&self) -> Vec<TerminalLine> {
+        match &self.alternate {
+            Some(grid) => grid
+                .iter()
+                .enumerate()
+                .map(|(row, cells)| {
+                    let cursor_col = (self.cursor_visible && row == self.cursor.row).then_some(self.cursor.col);
+                    row_to_line(cells, cursor_col)
+                })
+                .collect(),
+            None => {
+                let last_text = self
+                    .primary
+                    .iter()
+                    .rposition(|row| row.iter().any(|c| *c != Cell::default()));
+                let cursor_end = if self.cursor.col > 0 || self.cursor.pending_wrap {
+                    Some(self.cursor.row)
+                } else {
+                    self.cursor.row.checked_sub(1)
+                };
+                let end = match (last_text, cursor_end) {
+                    (Some(a), Some(b)) => a.max(b) + 1,
+                    (Some(a), None) | (None, Some(a)) => a + 1,
+                    (None, None) => 0,
+                };
+                self.primary[..end].iter().map(|cells| row_to_line(cells, None)).collect()
+            }
+        }
+    }
+
+    fn grid_mut(&mut self) -> &mut Vec<Vec<Cell>> {
+        match &mut self.alternate {
+            Some(grid) => grid,
+            None => &mut self.primary,
+        }
+    }
+
+    fn blank(&self) -> Cell {
+        // Erased cells take the current background, like xterm.
+        Cell {
+            ch: ' ',
+            style: TextStyle {
+                bg: self.style.bg,
+                ..TextStyle::default()
+            },
+        }
+    }
+
+    fn apply(&mut self, action: VtAction) {
+        match action {
+            VtAction::Print(ch) => self.print(ch),
+            VtAction::Execute(b) => self.execute(b),
+            VtAction::Csi {
+                private,
+                params,
+                intermediates,
+                action,
+            } => {
+                if intermediates.is_empty() {
+                    self.csi(private, &params, action);
+                }
+            }
+            VtAction::Esc { intermediates, byte } => {
+                if intermediates.is_empty() {
+                    self.esc(byte);
+                }
+            }
+            VtAction::Osc(_) => {}
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        if self.cursor.pending_wrap {
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+        let (row, col, style) = (self.cursor.row, self.cursor.col, self.style);
+        self.grid_mut()[row][col] = Cell { ch, style };
+        if col + 1 >= self.cols {
+            self.cursor.pending_wrap = self.auto_wrap;
+        } else {
+            self.cursor.col += 1;
+        }
+    }
+
+    fn execute(&mut self, b: u8) {
+        match b {
+            b'\n' | 0x0B | 0x0C => self.linefeed(),
+            b'\r' => self.set_col(0),
+            0x08 => self.set_col(self.cursor.col.saturating_sub(1)),
+            b'\t' => {
+                let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.set_col(next.min(self.cols - 1));
+            }
+            _ => {}
+        }
+    }
+
+    fn esc(&mut self, byte: u8) {
+        match byte {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.set_col(0);
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => {
+                let (cols, rows) = (self.cols, self.rows);
+                let scrollback = std::mem::take(&mut self.scrollback);
+                *self = Screen::new(cols, rows);
+                self.scrollback = scrollback;
+            }
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, private: Option<u8>, params: &[Vec<u16>], action: char) {
+        let arg = |idx: usize, default: usize| -> usize {
+            match params.get(idx).and_then(|g| g.first()).copied() {
+                Some(0) | None => default,
+                Some(v) => v as usize,
+            }
+        };
+
+        if private == Some(b'?') {
+            let enable = match action {
+                'h' => true,
+                'l' => false,
+                _ => return,
+            };
+            for group in params {
+                match group.first().copied().unwrap_or(0) {
+                    7 => self.auto_wrap = enable,
+                    25 => self.cursor_visible = enable,
+                    47 | 1047 => self.set_alternate(enable, false),
+                    1049 => self.set_alternate(enable, true),
+                    _ => {}
+                }
+            }
+            return;
+        }
+        if private.is_some() {
+            return;
+        }
+
+        let row = self.cursor.row;
+        let col = self.cursor.col;
+        match action {
+            'A' => self.move_to(row.saturating_sub(arg(0, 1)).max(self.top_limit()), col),
+            'B' | 'e' => self.move_to((row + arg(0, 1)).min(self.bottom_limit()), col),
+            'C' | 'a' => self.set_col(col + arg(0, 1)),
+            'D' => self.set_col(col.saturating_sub(arg(0, 1))),
+            'E' => self.move_to((row + arg(0, 1)).min(self.bottom_limit()), 0),
+            'F' => self.move_to(row.saturating_sub(arg(0, 1)).max(self.top_limit()), 0),
+            'G' | '`' => self.set_col(arg(0, 1) - 1),
+            'H' | 'f' => self.move_to(arg(0, 1) - 1, arg(1, 1) - 1),
+            'd' => self.move_to(arg(0, 1) - 1, col),
+            'J' => self.erase_display(arg(0, 0)),
+            'K' => self.erase_line(arg(0, 0)),
+            'L' => self.insert_lines(arg(0, 1)),
+            'M' => self.delete_lines(arg(0, 1)),
+            '@' => self.insert_chars(arg(0, 1)),
+            'P' => self.delete_chars(arg(0, 1)),
+            'X' => {
+                let blank = self.blank();
+                let end = (col + arg(0, 1)).min(self.cols);
+                let line = &mut self.grid_mut()[row];
+                line[col..end].fill(blank);
+                self.cursor.pending_wrap = false;
+            }
+            'S' => self.scroll_up(arg(0, 1)),
+            'T' => self.scroll_down(arg(0, 1)),
+            'r' => {
+                let top = arg(0, 1) - 1;
+                let bottom = arg(1, self.rows).min(self.rows) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            'm' => apply_sgr(&mut self.style, params),
+            _ => {}
+        }
+    }
+
+    fn top_limit(&self) -> usize {
+        if self.cursor.row >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        }
+    }
+
+    fn bottom_limit(&self) -> usize {
+        if self.cursor.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.rows - 1
+        }
+    }
+
+    fn set_col(&mut self, col: usize) {
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.set_col(col);
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = SavedCursor {
+            cursor: self.cursor,
+            style: self.style,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        self.cursor = self.saved.cursor;
+        self.style = self.saved.style;
+        self.move_to(self.cursor.row, self.cursor.col);
+    }
+
+    fn set_alternate(&mut self, enable: bool, save_cursor: bool) {
+        if enable == self.alternate.is_some() {
+            return;
+        }
+        if enable {
+            if save_cursor {
+                self.saved_primary = SavedCursor {
+                    cursor: self.cursor,
+                    style: self.style,
+                };
+            }
+            self.alternate = Some(blank_grid(self.cols, self.rows));
+        } else {
+            self.alternate = None;
+            if save_cursor {
+                self.cursor = self.saved_primary.cursor;
+                self.style = self.saved_primary.style;
+                self.move_to(self.cursor.row, self.cursor.col);
+            }
+        }
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows - 1;
+    }
+
+    fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let (top, bottom, cols) = (self.scroll_top, self.scroll_bottom, self.cols);
+        let blank = self.blank();
+        let keep_scrollback = self.alternate.is_none() && top == 0;
+        for _ in 0..n.min(bottom - top + 1) {
+            let removed = self.grid_mut().remove(top);
+            self.grid_mut().insert(bottom, vec![blank; cols]);
+            if keep_scrollback {
+                self.scrollback.push(row_to_line(&removed, None));
+            }
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let (top, bottom, cols) = (self.scroll_top, self.scroll_bottom, self.cols);
+        let blank = self.blank();
+        for _ in 0..n.min(bottom - top + 1) {
+            self.grid_mut().remove(bottom);
+            self.grid_mut().insert(top, vec![blank; cols]);
+        }
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        let (bottom, cols, blank) = (self.scroll_bottom, self.cols, self.blank());
+        for _ in 0..n.min(bottom - row + 1) {
+            self.grid_mut().remove(bottom);
+            self.grid_mut().insert(row, vec![blank; cols]);
+        }
+        self.set_col(0);
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        let (bottom, cols, blank) = (self.scroll_bottom, self.cols, self.blank());
+        for _ in 0..n.min(bottom - row + 1) {
+            self.grid_mut().remove(row);
+            self.grid_mut().insert(bottom, vec![blank; cols]);
+        }
+        self.set_col(0);
+    }
+
+    fn insert_chars(&mut self, n: usize) {
+        let (row, col, cols, blank) = (self.cursor.row, self.cursor.col, self.cols, self.blank());
+        let line = &mut self.grid_mut()[row];
+        for _ in 0..n.min(cols - col) {
+            line.pop();
+            line.insert(col, blank);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn delete_chars(&mut self, n: usize) {
+        let (row, col, cols, blank) = (self.cursor.row, self.cursor.col, self.cols, self.blank());
+        let line = &mut self.grid_mut()[row];
+        for _ in 0..n.min(cols - col) {
+            line.remove(col);
+            line.push(blank);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn erase_line(&mut self, mode: usize) {
+        let (row, col, cols, blank) = (self.cursor.row, self.cursor.col, self.cols, self.blank());
+        let line = &mut self.grid_mut()[row];
+        match mode {
+            0 => line[col..].fill(blank),
+            1 => line[..=col.min(cols - 1)].fill(blank),
+            2 => line.fill(blank),
+            _ => {}
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn erase_display(&mut self, mode: usize) {
+        let (row, blank) = (self.cursor.row, self.blank());
+        match mode {
+            0 => {
+                self.erase_line(0);
+                for line in self.grid_mut()[row + 1..].iter_mut() {
+                    line.fill(blank);
+                }
+            }
+            1 => {
+                self.erase_line(1);
+                for line in self.grid_mut()[..row].iter_mut() {
+                    line.fill(blank);
+                }
+            }
+            2 => {
+                for line in self.grid_mut().iter_mut() {
+                    line.fill(blank);
+                }
+            }
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+    }
+}
+
+fn blank_grid(cols: usize, rows: usize) -> Vec<Vec<Cell>> {
+    vec![vec![Cell::default(); cols]; rows]
+}
+
+/// Convert a grid row into a display line, dropping trailing blank cells.
+/// `cursor_col` marks a cell to draw as a block cursor.
+fn row_to_line(cells: &[Cell], cursor_col: Option<usize>) -> TerminalLine {
+    let mut end = cells.iter().rposition(|c| *c != Cell::default()).map_or(0, |i| i + 1);
+    if let Some(col) = cursor_col {
+        end = end.max(col + 1).min(cells.len());
+    }
+
+    let mut spans: Vec<StyledSpan> = Vec::new();
+    for (idx, cell) in cells[..end].iter().enumerate() {
+        let mut style = cell.style;
+        if cursor_col == Some(idx) {
+            style.inverse = !style.inverse;
+        }
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push(cell.ch),
+            _ => spans.push(StyledSpan {
+                text: cell.ch.to_string(),
+                style,
+            }),
+        }
+    }
+
+    let content = spans.iter().map(|s| s.text.as_str()).collect::<String>();
+    let styled = spans.iter().any(|s| s.style != TextStyle::default());
+    TerminalLine {
+        content,
+        line_type: LineType::Output,
+        spans: if styled { spans } else { Vec::new() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Screen;
+    use crate::terminal::state::Color;
+
+    fn contents(screen: &Screen) -> Vec<String> {
+        screen.visible_lines().into_iter().map(|l| l.content).collect()
+    }
+
+    #[test]
+    fn plain_output_renders_only_written_rows() {
+        let mut screen = Screen::new(20, 5);
+        screen.feed(b"one\r\ntwo\r\n");
+        assert_eq!(contents(&screen), vec!["one", "two"]);
+
+        let mut screen = Screen::new(20, 5);
+        screen.feed(b"\r\n");
+        assert_eq!(contents(&screen), vec![""]);
+    }
+
+    #[test]
+    fn carriage_return_overwrites_and_sgr_produces_spans() {
+        let mut screen = Screen::new(20, 5);
+        screen.feed(b" 10%\r\x1b[32m100%\x1b[0m\r\n");
+        let lines = screen.visible_lines();
+        assert_eq!(lines[0].content, "100%");
+        assert_eq!(lines[0].spans[0].style.fg, Some(Color::Indexed(2)));
+    }
+
+    #[test]
+    fn rows_scrolled_off_the_top_become_scrollback() {
+        let mut screen = Screen::new(10, 2);
+        screen.feed(b"a\r\nb\r\nc\r\n");
+        let scrolled: Vec<String> = screen.take_scrollback().into_iter().map(|l| l.content).collect();
+        assert_eq!(scrolled, vec!["a", "b"]);
+        assert_eq!(contents(&screen), vec!["c"]);
+        assert!(screen.take_scrollback().is_empty());
+    }
+
+    #[test]
+    fn cursor_addressing_and_erase_in_line() {
+        let mut screen = Screen::new(12, 3);
+        screen.feed(b"hello world");
+        screen.feed(b"\x1b[1;3HX\x1b[K");
+        screen.feed(b"\x1b[2;5Hy");
+        assert_eq!(contents(&screen), vec!["heX", "    y"]);
+    }
+
+    #[test]
+    fn long_lines_wrap_at_the_last_column() {
+        let mut screen = Screen::new(4, 3);
+        screen.feed(b"abcdef");
+        assert_eq!(contents(&screen), vec!["abcd", "ef"]);
+    }
+
+    #[test]
+    fn clear_sequence_erases_the_screen() {
+        let mut screen = Screen::new(10, 3);
+        screen.feed(b"junk\r\nmore\r\n\x1b[H\x1b[2J\x1b[3J");
+        assert!(contents(&screen).is_empty());
+        screen.feed(b"fresh");
+        assert_eq!(contents(&screen), vec!["fresh"]);
+    }
+
+    #[test]
+    fn alternate_screen_is_separate_and_restores_primary() {
+        let mut screen = Screen::new(6, 3);
+        screen.feed(b"shell\r\n");
+        screen.feed(b"\x1b[?1049h\x1b[H\x1b[2Jtui");
+        assert!(screen.alternate.is_some());
+        let alt = contents(&screen);
+        assert_eq!(alt.len(), 3);
+        assert!(alt[0].starts_with("tui"));
+
+        screen.feed(b"\x1b[?1049l");
+        assert!(screen.alternate.is_none());
+        assert_eq!(contents(&screen), vec!["shell"]);
+        assert!(screen.take_scrollback().is_empty());
+    }
+
+    #[test]
+    fn scroll_region_keeps_rows_outside_it() {
+        let mut screen = Screen::new(10, 4);
+        screen.feed(b"\x1b[?1049h");
+        screen.feed(b"\x1b[1;1Htitle\x1b[4;1Hstatus");
+        screen.feed(b"\x1b[2;3r\x1b[2;1Ha\r\nb\r\nc");
+        let rows = contents(&screen);
+        assert_eq!(rows[0], "title");
+        assert_eq!(rows[1], "b");
+        assert!(rows[2].starts_with('c'));
+        assert_eq!(rows[3], "status");
+    }
+
+    #[test]
+    fn resize_keeps_cursor_row_on_screen() {
+        let mut screen = Screen::new(10, 4);
+        screen.feed(b"a\r\nb\r\nc\r\nd");
+        screen.resize(5, 2);
+
+        let scrolled: Vec<String> = screen.take_scrollback().into_iter().map(|l| l.content).collect();
+        assert_eq!(scrolled, vec!["a", "b"]);
+        assert_eq!(contents(&screen), vec!["c", "d"]);
+        assert_eq!((screen.cols, screen.rows), (5, 2));
+
+        screen.resize(8, 6);
+        screen.feed(b"\x1b[6;8HZ");
+        assert_eq!(contents(&screen).last().map(String::as_str), Some("       Z"));
+    }
+
+    #[test]
+    fn leaving_the_alternate_screen_after_shrinking_stays_on_screen() {
+        let mut screen = Screen::new(10, 10);
+        screen.feed(b"\x1b[9;1H\x1b[?1049h");
+        screen.resize(10, 4);
+        screen.feed(b"\x1b[?1049lx");
+        assert_eq!(contents(&screen)[3], "x");
+    }
+
+    #[test]
+    fn insert_and_delete_characters() {
+        let mut screen = Screen::new(10, 2);
+        screen.feed(b"abcdef\x1b[1;2H\x1b[2P");
+        assert_eq!(contents(&screen), vec!["adef"]);
+        screen.feed(b"\x1b[1@Z");
+        assert_eq!(contents(&screen), vec!["aZdef"]);
+    }
+}
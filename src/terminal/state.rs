@@ -2,6 +2,9 @@
 pub struct TerminalLine {
     pub content: String,
     pub line_type: LineType,
+    /// Styled runs that make up `content`. Empty for plain lines, which are
+    /// rendered as a single text node.
+    pub spans: Vec<StyledSpan>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -11,3 +14,172 @@ pub enum LineType {
     Error,
     System,
 }
+
+/// A terminal color: one of the 256 palette entries (0–15 are the ANSI
+/// colors) or a 24-bit RGB value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub enum Color {
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Graphic rendition of a run of text. `None` colors mean the line default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+/// Terminal geometry in character cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub struct TermSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for TermSize {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+/// How a finished command ended.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitOutcome {
+    /// The process exited with this code.
+    Code(i32),
+    /// The process was terminated by this signal (Unix only).
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    Signal(i32),
+}
+
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+impl ExitOutcome {
+    /// The value reported as `$?`. Like POSIX shells, a signal `n` maps to
+    /// `128 + n`.
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Code(code) => code,
+            Self::Signal(signal) => 128 + signal,
+        }
+    }
+
+    pub fn success(self) -> bool {
+        self == Self::Code(0)
+    }
+
+    /// The shell convention for commands that could not be started: 127 when
+    /// the program does not exist, 126 otherwise.
+    pub fn from_spawn_error(error: &std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            Self::Code(127)
+        } else {
+            Self::Code(126)
+        }
+    }
+}
+
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+impl From<std::process::ExitStatus> for ExitOutcome {
+    fn from(status: std::process::ExitStatus) -> Self {
+        #[cfg(not(target_os = "windows"))]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return Self::Signal(signal);
+            }
+        }
+        Self::Code(status.code().unwrap_or(1))
+    }
+}
+
+/// Exit outcome and wall-clock duration of one command invocation.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandStatus {
+    pub outcome: ExitOutcome,
+    pub duration: std::time::Duration,
+}
+
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+impl CommandStatus {
+    /// The compact line shown after a command's output, e.g. `✗ exit 2 · 1.34s`.
+    pub fn footer(&self) -> TerminalLine {
+        let mark = if self.outcome.success() { "✓" } else { "✗" };
+        let how = match self.outcome {
+            ExitOutcome::Code(code) => format!("exit {}", code),
+            ExitOutcome::Signal(signal) => format!("signal {}", signal),
+        };
+        TerminalLine {
+            content: format!("{} {} · {}", mark, how, format_duration(self.duration)),
+            line_type: if self.outcome.success() {
+                LineType::System
+            } else {
+                LineType::Error
+            },
+            spans: Vec::new(),
+        }
+    }
+}
+
+/// `1.34s` below a minute, `2m 05s` above.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(all(test, feature = "desktop", not(target_arch = "wasm32")))]
+mod tests {
+    use super::{CommandStatus, ExitOutcome, LineType};
+    use std::time::Duration;
+
+    #[test]
+    fn footer_reports_failure_code_and_duration() {
+        let status = CommandStatus {
+            outcome: ExitOutcome::Code(2),
+            duration: Duration::from_millis(1340),
+        };
+        let footer = status.footer();
+        assert_eq!(footer.content, "✗ exit 2 · 1.34s");
+        assert_eq!(footer.line_type, LineType::Error);
+    }
+
+    #[test]
+    fn footer_reports_success_and_long_durations() {
+        let status = CommandStatus {
+            outcome: ExitOutcome::Code(0),
+            duration: Duration::from_secs(125),
+        };
+        let footer = status.footer();
+        assert_eq!(footer.content, "✓ exit 0 · 2m 05s");
+        assert_eq!(footer.line_type, LineType::System);
+    }
+
+    #[test]
+    fn signals_map_to_shell_exit_codes() {
+        assert_eq!(ExitOutcome::Signal(2).code(), 130);
+        assert!(!ExitOutcome::Signal(2).success());
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(ExitOutcome::from_spawn_error(&not_found), ExitOutcome::Code(127));
+    }
+}
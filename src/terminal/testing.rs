@@ -0,0 +1,49 @@
+//! Helpers shared by the unit tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fresh directory for one test, removed with everything in it when
+/// dropped, so a failing test cleans up too. Every one has a name of its own,
+/// so tests running in parallel never share a directory.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// A directory under the system temporary directory, named after `name`.
+    pub fn new(name: &str) -> Self {
+        Self::new_in(&std::env::temp_dir(), name)
+    }
+
+    /// A directory inside `parent`, which is created if it is missing.
+    pub fn new_in(parent: &Path, name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let unique = format!("blaze_{}_{}_{}", name, std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let dir = parent.join(unique);
+        // Left behind by an earlier run that had the same process id.
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create test dir");
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,20 +1,33 @@
 use dioxus::prelude::*;
 
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::commands::desktop::Jail;
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+use crate::terminal::state::CommandStatus;
 use crate::terminal::state::TerminalLine;
 
 const MAX_LINES: usize = 5000;
 
 // ======================== History persistence ========================
 
-/// Returns the path to the Blaze command-history file.
-/// Stored at `<user home>/.blaze_history`; falls back to the current directory.
+/// The user's home directory, where Blaze keeps its files; falls back to the
+/// current directory.
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-pub fn history_file_path() -> std::path::PathBuf {
-    let base = std::env::var("USERPROFILE")
+pub fn home_dir() -> std::path::PathBuf {
+    std::env::var("USERPROFILE")
         .or_else(|_| std::env::var("HOME"))
         .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default());
-    base.join(".blaze_history")
+        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default())
+}
+
+/// Returns the path to the Blaze command-history file.
+/// Stored at `<user home>/.blaze_history`.
+///
+/// Each record is `<command>\t<exit code>\t<duration in ms>`. Older files hold
+/// bare command lines, which are still read.
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+pub fn history_file_path() -> std::path::PathBuf {
+    home_dir().join(".blaze_history")
 }
 
 /// Load up to `limit` most-recent history lines from disk.
@@ -24,7 +37,7 @@ pub fn load_history(limit: usize) -> Vec<String> {
     match std::fs::read_to_string(&path) {
         Ok(content) => content
             .lines()
-            .map(|l| l.to_string())
+            .map(|l| history_record_command(l).to_string())
             .filter(|l| !l.is_empty())
             .collect::<Vec<_>>()
             .into_iter()
@@ -38,13 +51,27 @@ pub fn load_history(limit: usize) -> Vec<String> {
     }
 }
 
-/// Append a single command to the history file (one entry per line).
+/// Append a finished command and its status to the history file (one record per line).
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-pub fn append_history(cmd: &str) {
+pub fn append_history(cmd: &str, status: &CommandStatus) {
     use std::io::Write;
     let path = history_file_path();
     if let Ok(mut f) = std::fs::OpenOptions::new().create(true).append(true).open(&path) {
-        let _ = writeln!(f, "{}", cmd);
+        let _ = writeln!(f, "{}\t{}\t{}", cmd, status.outcome.code(), status.duration.as_millis());
+    }
+}
+
+/// The command part of a history record (see [`history_file_path`]).
+#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
+fn history_record_command(record: &str) -> &str {
+    let mut fields = record.rsplitn(3, '\t');
+    let (Some(millis), Some(code), Some(command)) = (fields.next(), fields.next(), fields.next()) else {
+        return record;
+    };
+    if millis.parse::<u128>().is_ok() && code.parse::<i32>().is_ok() {
+        command
+    } else {
+        record
     }
 }
 
@@ -52,32 +79,31 @@ pub fn append_history(cmd: &str) {
 
 /// All built-in command names available in the desktop terminal.
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-const BUILTIN_COMMANDS: &[&str] = &["help", "clear", "cls", "cd", "pwd", "exit"];
-
-/// All commands that may be passed through to the OS (mirrors `is_allowed_external`).
-#[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-const EXTERNAL_COMMANDS: &[&str] = &[
-    "ls", "dir", "echo", "vim", "whoami", "cat", "grep", "curl", "wget", "ip",
-    #[cfg(target_os = "windows")]
-    "type",
-    #[cfg(target_os = "windows")]
-    "ipconfig",
+pub const BUILTIN_COMMANDS: &[&str] = &[
+    "help", "clear", "cls", "cd", "pwd", "exit", "limits", "command", "which", "audit", "trash", "undo",
     #[cfg(not(target_os = "windows"))]
-    "ifconfig",
+    "fg",
 ];
 
 /// Given the current raw input string, return the next completion candidate.
 ///
 /// Strategy:
 /// * If only one token is present (typing a command name) → complete against
-///   built-ins + externals.
+///   built-ins + the allowed `externals`.
 /// * If multiple tokens are present (typing an argument) → complete against
 ///   filesystem entries under `cwd` that match the current argument prefix.
 ///
 /// `tab_state` tracks how many times Tab has been pressed consecutively so we
-/// cycle through multiple matches.
+/// cycle through multiple matches. With a workspace `jail`, entries outside it
+/// are never offered.
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-pub fn tab_complete(input: &str, cwd: &str, tab_state: usize) -> Option<String> {
+pub fn tab_complete(
+    externals: &[String],
+    jail: Option<&Jail>,
+    input: &str,
+    cwd: &str,
+    tab_state: usize,
+) -> Option<String> {
     let tokens: Vec<&str> = input.split_whitespace().collect();
 
     if tokens.is_empty() {
@@ -91,7 +117,8 @@ pub fn tab_complete(input: &str, cwd: &str, tab_state: usize) -> Option<String>
         let prefix = tokens[0].to_lowercase();
         let mut matches: Vec<String> = BUILTIN_COMMANDS
             .iter()
-            .chain(EXTERNAL_COMMANDS.iter())
+            .copied()
+            .chain(externals.iter().map(String::as_str))
             .filter(|c| c.starts_with(prefix.as_str()))
             .map(|c| c.to_string())
             .collect();
@@ -130,6 +157,7 @@ pub fn tab_complete(input: &str, cwd: &str, tab_state: usize) -> Option<String>
         let entries: Vec<String> = std::fs::read_dir(&search_dir)
             .ok()?
             .filter_map(|e| e.ok())
+            .filter(|e| jail.is_none_or(|jail| jail.contains(&e.path())))
             .map(|e| {
                 let name = e.file_name().to_string_lossy().to_string();
                 // Append separator for directories to make it obvious.
@@ -178,51 +206,7 @@ pub fn push_line_trim(mut lines: Signal<Vec<TerminalLine>>, line: TerminalLine)
     }
 }
 
-/// Split a command string into args.
-///
-/// This is intentionally not shell parsing: it supports quotes for spaces, but treats `&`, `|`, `;` etc as normal characters.
 #[cfg(all(feature = "desktop", not(target_arch = "wasm32")))]
-pub fn split_args(input: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let mut current = String::new();
-    let mut in_quotes = false;
-
-    let mut chars = input.chars().peekable();
-    while let Some(ch) = chars.next() {
-        match ch {
-            '"' => in_quotes = !in_quotes,
-            '\\' => {
-                if let Some('"') = chars.peek().copied() {
-                    chars.next();
-                    current.push('"');
-                } else {
-                    current.push('\\');
-                }
-            }
-            c if c.is_whitespace() && !in_quotes => {
-                if !current.is_empty() {
-                    args.push(std::mem::take(&mut current));
-                }
-                while let Some(c2) = chars.peek().copied() {
-                    if c2.is_whitespace() {
-                        chars.next();
-                    } else {
-                        break;
-                    }
-                }
-            }
-            _ => current.push(ch),
-        }
-    }
-
-    if !current.is_empty() {
-        args.push(current);
-    }
-
-    args
-}
-
-#[cfg(all(feature = "desktop", target_os = "windows"))]
 pub fn resolve_in_dir(cwd: &str, target: &str) -> std::path::PathBuf {
     let target_path = std::path::Path::new(target);
     if target_path.is_absolute() {
@@ -245,29 +229,13 @@ pub fn windows_hidden_command(program: &str, cwd: &str) -> std::process::Command
 
 #[cfg(all(test, feature = "desktop", not(target_arch = "wasm32")))]
 mod tests {
-    use super::split_args;
-
-    #[test]
-    fn split_args_handles_quoted_segments() {
-        let args = split_args("echo \"hello world\" test");
-        assert_eq!(args, vec!["echo", "hello world", "test"]);
-    }
-
-    #[test]
-    fn split_args_handles_escaped_quote_inside_quotes() {
-        let args = split_args("echo \"he\\\"llo\"");
-        assert_eq!(args, vec!["echo", "he\"llo"]);
-    }
-
-    #[test]
-    fn split_args_preserves_shell_metacharacters_as_literals() {
-        let args = split_args("echo hello && rm file.txt");
-        assert_eq!(args, vec!["echo", "hello", "&&", "rm", "file.txt"]);
-    }
+    use super::history_record_command;
 
     #[test]
-    fn split_args_collapses_whitespace_between_args() {
-        let args = split_args("  grep    TODO   file.rs  ");
-        assert_eq!(args, vec!["grep", "TODO", "file.rs"]);
+    fn history_records_strip_status_fields() {
+        assert_eq!(history_record_command("ls -la\t0\t12"), "ls -la");
+        assert_eq!(history_record_command("grep x\ty\t1\t2034"), "grep x\ty");
+        assert_eq!(history_record_command("echo legacy"), "echo legacy");
+        assert_eq!(history_record_command("echo a\tb"), "echo a\tb");
     }
 }
@@ -0,0 +1,562 @@
+//! VT/ANSI escape sequence parsing.
+//!
+//! [`VtParser`] is a byte-oriented state machine (after the DEC ANSI parser
+//! described by Paul Williams) that turns raw child output into [`VtAction`]s.
+//! It keeps state across calls, so escape sequences and UTF-8 characters split
+//! between two reads are handled correctly. SGR ("select graphic rendition")
+//! sequences are interpreted by [`apply_sgr`].
+
+use crate::terminal::state::{Color, TextStyle};
+
+/// A decoded unit of terminal output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VtAction {
+    /// A printable character.
+    Print(char),
+    /// A C0 control character such as `\n`, `\r`, `\t` or BEL.
+    Execute(u8),
+    /// A control sequence (`ESC [ ... final`).
+    ///
+    /// Each parameter is a group of colon-separated sub-parameters; missing
+    /// values are reported as `0`.
+    Csi {
+        private: Option<u8>,
+        params: Vec<Vec<u16>>,
+        intermediates: Vec<u8>,
+        action: char,
+    },
+    /// A plain escape sequence such as `ESC 7` or `ESC ( B`.
+    Esc { intermediates: Vec<u8>, byte: u8 },
+    /// An operating system command (`ESC ] ... BEL`), e.g. a window title.
+    Osc(String),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    Csi,
+    CsiIgnore,
+    Osc,
+    OscEscape,
+    /// DCS/SOS/PM/APC payloads, which are consumed and dropped.
+    Passthrough,
+    PassthroughEscape,
+}
+
+const MAX_PARAMS: usize = 32;
+const MAX_OSC_LEN: usize = 4096;
+
+#[derive(Default)]
+pub struct VtParser {
+    state: State,
+    private: Option<u8>,
+    params: Vec<Vec<u16>>,
+    current: Vec<u16>,
+    intermediates: Vec<u8>,
+    osc: Vec<u8>,
+    utf8: Vec<u8>,
+    utf8_len: usize,
+}
+
+impl VtParser {
+    /// Feed raw bytes and return the actions they complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<VtAction> {
+        let mut out = Vec::new();
+        for &b in bytes {
+            self.advance(b, &mut out);
+        }
+        out
+    }
+
+    fn advance(&mut self, b: u8, out: &mut Vec<VtAction>) {
+        // A pending multi-byte UTF-8 character only continues in the ground state.
+        if !self.utf8.is_empty() {
+            if (0x80..0xC0).contains(&b) {
+                self.utf8.push(b);
+                if self.utf8.len() == self.utf8_len {
+                    let ch = std::str::from_utf8(&self.utf8)
+                        .ok()
+                        .and_then(|s| s.chars().next())
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    self.utf8.clear();
+                    out.push(VtAction::Print(ch));
+                }
+                return;
+            }
+            self.utf8.clear();
+            out.push(VtAction::Print(char::REPLACEMENT_CHARACTER));
+        }
+
+        match self.state {
+            State::Osc => {
+                match b {
+                    0x07 => self.finish_osc(out),
+                    0x1B => self.state = State::OscEscape,
+                    _ => {
+                        if self.osc.len() < MAX_OSC_LEN {
+                            self.osc.push(b);
+                        }
+                    }
+                }
+                return;
+            }
+            State::OscEscape => {
+                // `ESC \` (string terminator) ends the OSC; any other escape also ends
+                // it and starts a new sequence.
+                self.finish_osc(out);
+                if b != b'\\' {
+                    self.enter_escape();
+                    self.advance(b, out);
+                }
+                return;
+            }
+            State::Passthrough => {
+                match b {
+                    0x07 => self.state = State::Ground,
+                    0x1B => self.state = State::PassthroughEscape,
+                    _ => {}
+                }
+                return;
+            }
+            State::PassthroughEscape => {
+                self.state = State::Ground;
+                if b != b'\\' {
+                    self.enter_escape();
+                    self.advance(b, out);
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // Controls that apply in every remaining state.
+        match b {
+            0x1B => {
+                self.enter_escape();
+                return;
+            }
+            0x18 | 0x1A => {
+                self.state = State::Ground;
+                return;
+            }
+            0x00..=0x1F => {
+                out.push(VtAction::Execute(b));
+                return;
+            }
+            0x7F => return,
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => self.print(b, out),
+            State::Escape => match b {
+                b'[' => {
+                    self.state = State::Csi;
+                }
+                b']' => {
+                    self.osc.clear();
+                    self.state = State::Osc;
+                }
+                b'P' | b'X' | b'^' | b'_' => self.state = State::Passthrough,
+                0x20..=0x2F => {
+                    self.intermediates.push(b);
+                    self.state = State::EscapeIntermediate;
+                }
+                _ => {
+                    out.push(VtAction::Esc {
+                        intermediates: Vec::new(),
+                        byte: b,
+                    });
+                    self.state = State::Ground;
+                }
+            },
+            State::EscapeIntermediate => match b {
+                0x20..=0x2F => self.intermediates.push(b),
+                _ => {
+                    out.push(VtAction::Esc {
+                        intermediates: std::mem::take(&mut self.intermediates),
+                        byte: b,
+                    });
+                    self.state = State::Ground;
+                }
+            },
+            State::Csi => match b {
+                b'0'..=b'9' => {
+                    if !self.intermediates.is_empty() {
+                        self.state = State::CsiIgnore;
+                        return;
+                    }
+                    if self.current.is_empty() {
+                        self.current.push(0);
+                    }
+                    if let Some(v) = self.current.last_mut() {
+                        *v = v.saturating_mul(10).saturating_add(u16::from(b - b'0'));
+                    }
+                }
+                b':' => {
+                    if self.current.is_empty() {
+                        self.current.push(0);
+                    }
+                    self.current.push(0);
+                }
+                b';' => {
+                    self.push_param();
+                }
+                b'<'..=b'?' => {
+                    if self.params.is_empty() && self.current.is_empty() && self.private.is_none() {
+                        self.private = Some(b);
+                    } else {
+                        self.state = State::CsiIgnore;
+                    }
+                }
+                0x20..=0x2F => self.intermediates.push(b),
+                0x40..=0x7E => {
+                    if !self.current.is_empty() || !self.params.is_empty() {
+                        self.push_param();
+                    }
+                    out.push(VtAction::Csi {
+                        private: self.private,
+                        params: std::mem::take(&mut self.params),
+                        intermediates: std::mem::take(&mut self.intermediates),
+                        action: b as char,
+                    });
+                    self.state = State::Ground;
+                }
+                _ => self.state = State::CsiIgnore,
+            },
+            State::CsiIgnore => {
+                if (0x40..=0x7E).contains(&b) {
+                    self.state = State::Ground;
+                }
+            }
+            State::Osc | State::OscEscape | State::Passthrough | State::PassthroughEscape => {}
+        }
+    }
+
+    fn enter_escape(&mut self) {
+        self.state = State::Escape;
+        self.private = None;
+        self.params.clear();
+        self.current.clear();
+        self.intermediates.clear();
+    }
+
+    fn push_param(&mut self) {
+        let group = if self.current.is_empty() {
+            vec![0]
+        } else {
+            std::mem::take(&mut self.current)
+        };
+        if self.params.len() < MAX_PARAMS {
+            self.params.push(group);
+        }
+    }
+
+    fn finish_osc(&mut self, out: &mut Vec<VtAction>) {
+        out.push(VtAction::Osc(String::from_utf8_lossy(&self.osc).to_string()));
+        self.osc.clear();
+        self.state = State::Ground;
+    }
+
+    fn print(&mut self, b: u8, out: &mut Vec<VtAction>) {
+        let len = match b {
+            0x00..=0x7F => {
+                out.push(VtAction::Print(b as char));
+                return;
+            }
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => {
+                out.push(VtAction::Print(char::REPLACEMENT_CHARACTER));
+                return;
+            }
+        };
+        self.utf8.push(b);
+        self.utf8_len = len;
+    }
+}
+
+/// Apply an SGR parameter list (`CSI ... m`) to `style`.
+pub fn apply_sgr(style: &mut TextStyle, params: &[Vec<u16>]) {
+    if params.is_empty() {
+        *style = TextStyle::default();
+        return;
+    }
+
+    let mut i = 0;
+    while i < params.len() {
+        let group = &params[i];
+        let code = group.first().copied().unwrap_or(0);
+        match code {
+            0 => *style = TextStyle::default(),
+            1 => style.bold = true,
+            2 => style.dim = true,
+            3 => style.italic = true,
+            4 => style.underline = group.get(1).is_none_or(|&kind| kind != 0),
+            7 => style.inverse = true,
+            9 => style.strikethrough = true,
+            21 => style.underline = true,
+            22 => {
+                style.bold = false;
+                style.dim = false;
+            }
+            23 => style.italic = false,
+            24 => style.underline = false,
+            27 => style.inverse = false,
+            29 => style.strikethrough = false,
+            30..=37 => style.fg = Some(Color::Indexed((code - 30) as u8)),
+            39 => style.fg = None,
+            40..=47 => style.bg = Some(Color::Indexed((code - 40) as u8)),
+            49 => style.bg = None,
+            90..=97 => style.fg = Some(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg = Some(Color::Indexed((code - 100 + 8) as u8)),
+            38 | 48 => {
+                let (color, consumed) = if group.len() > 1 {
+                    (extended_color(&group[1..], true), 0)
+                } else {
+                    let rest: Vec<u16> = params[i + 1..].iter().map(|g| g.first().copied().unwrap_or(0)).collect();
+                    let color = extended_color(&rest, false);
+                    let consumed = match rest.first() {
+                        Some(5) => 2,
+                        Some(2) => 4,
+                        _ => rest.len(),
+                    };
+                    (color, consumed.min(rest.len()))
+                };
+                if let Some(color) = color {
+                    if code == 38 {
+                        style.fg = Some(color);
+                    } else {
+                        style.bg = Some(color);
+                    }
+                }
+                i += consumed;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Parse the tail of an extended color (`5;n` or `2;r;g;b`).
+///
+/// The colon form may carry a color-space id before the RGB components
+/// (`38:2::r:g:b`), which is skipped when present.
+fn extended_color(rest: &[u16], colon_form: bool) -> Option<Color> {
+    match rest.first()? {
+        5 => rest.get(1).map(|&n| Color::Indexed(n.min(255) as u8)),
+        2 => {
+            let rgb = if colon_form && rest.len() >= 5 { &rest[2..5] } else { rest.get(1..4)? };
+            Some(Color::Rgb(clamp_u8(rgb[0]), clamp_u8(rgb[1]), clamp_u8(rgb[2])))
+        }
+        _ => None,
+    }
+}
+
+fn clamp_u8(v: u16) -> u8 {
+    v.min(255) as u8
+}
+
+/// Default foreground/background of the desktop terminal (see `main.css`).
+const DEFAULT_FG: (u8, u8, u8) = (0x40, 0xFF, 0x85);
+const DEFAULT_BG: (u8, u8, u8) = (0x05, 0x06, 0x07);
+
+/// The xterm 256-color palette.
+pub fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xCD, 0x31, 0x31),
+        (0x0D, 0xBC, 0x79),
+        (0xE5, 0xE5, 0x10),
+        (0x24, 0x72, 0xC8),
+        (0xBC, 0x3F, 0xBC),
+        (0x11, 0xA8, 0xCD),
+        (0xE5, 0xE5, 0xE5),
+        (0x66, 0x66, 0x66),
+        (0xF1, 0x4C, 0x4C),
+        (0x23, 0xD1, 0x8B),
+        (0xF5, 0xF5, 0x43),
+        (0x3B, 0x8E, 0xEA),
+        (0xD6, 0x70, 0xD6),
+        (0x29, 0xB8, 0xDB),
+        (0xFF, 0xFF, 0xFF),
+    ];
+    match index {
+        0..=15 => BASE[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + (index - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+fn color_rgb(color: Color) -> (u8, u8, u8) {
+    match color {
+        Color::Indexed(i) => palette_rgb(i),
+        Color::Rgb(r, g, b) => (r, g, b),
+    }
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Inline CSS for a styled span. Returns an empty string for the default style.
+pub fn style_css(style: &TextStyle) -> String {
+    let mut fg = style.fg.map(color_rgb);
+    let mut bg = style.bg.map(color_rgb);
+    if style.inverse {
+        let (f, b) = (fg.unwrap_or(DEFAULT_FG), bg.unwrap_or(DEFAULT_BG));
+        fg = Some(b);
+        bg = Some(f);
+    }
+
+    let mut css = String::new();
+    if let Some(fg) = fg {
+        css.push_str(&format!("color:{};", hex(fg)));
+    }
+    if let Some(bg) = bg {
+        css.push_str(&format!("background-color:{};", hex(bg)));
+    }
+    if style.bold {
+        css.push_str("font-weight:bold;");
+    }
+    if style.dim {
+        css.push_str("opacity:0.7;");
+    }
+    if style.italic {
+        css.push_str("font-style:italic;");
+    }
+    match (style.underline, style.strikethrough) {
+        (true, true) => css.push_str("text-decoration:underline line-through;"),
+        (true, false) => css.push_str("text-decoration:underline;"),
+        (false, true) => css.push_str("text-decoration:line-through;"),
+        (false, false) => {}
+    }
+    css
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_sgr, palette_rgb, style_css, VtAction, VtParser};
+    use crate::terminal::state::{Color, TextStyle};
+
+    fn sgr(seq: &str) -> TextStyle {
+        let mut parser = VtParser::default();
+        let mut style = TextStyle::default();
+        for action in parser.feed(seq.as_bytes()) {
+            if let VtAction::Csi { params, action: 'm', .. } = action {
+                apply_sgr(&mut style, &params);
+            }
+        }
+        style
+    }
+
+    #[test]
+    fn parser_separates_text_controls_and_sequences() {
+        let mut parser = VtParser::default();
+        let actions = parser.feed(b"a\x1b[1;31mb\r\n");
+        assert_eq!(
+            actions,
+            vec![
+                VtAction::Print('a'),
+                VtAction::Csi {
+                    private: None,
+                    params: vec![vec![1], vec![31]],
+                    intermediates: vec![],
+                    action: 'm',
+                },
+                VtAction::Print('b'),
+                VtAction::Execute(b'\r'),
+                VtAction::Execute(b'\n'),
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_keeps_state_across_chunks() {
+        let mut parser = VtParser::default();
+        let mut actions = parser.feed(b"\x1b[3");
+        actions.extend(parser.feed(b"2m\xc3"));
+        actions.extend(parser.feed(b"\xa9"));
+        assert_eq!(actions.len(), 2);
+        assert!(matches!(&actions[0], VtAction::Csi { action: 'm', params, .. } if params == &vec![vec![32]]));
+        assert_eq!(actions[1], VtAction::Print('é'));
+    }
+
+    #[test]
+    fn parser_reports_private_markers_and_osc() {
+        let mut parser = VtParser::default();
+        let actions = parser.feed(b"\x1b[?1049h\x1b]0;title\x07x");
+        assert_eq!(
+            actions,
+            vec![
+                VtAction::Csi {
+                    private: Some(b'?'),
+                    params: vec![vec![1049]],
+                    intermediates: vec![],
+                    action: 'h',
+                },
+                VtAction::Osc("0;title".to_string()),
+                VtAction::Print('x'),
+            ]
+        );
+    }
+
+    #[test]
+    fn sgr_handles_basic_and_bright_colors() {
+        let style = sgr("\x1b[31;104m");
+        assert_eq!(style.fg, Some(Color::Indexed(1)));
+        assert_eq!(style.bg, Some(Color::Indexed(12)));
+    }
+
+    #[test]
+    fn sgr_handles_256_and_truecolor() {
+        let style = sgr("\x1b[38;5;208;48;2;10;20;30m");
+        assert_eq!(style.fg, Some(Color::Indexed(208)));
+        assert_eq!(style.bg, Some(Color::Rgb(10, 20, 30)));
+
+        let style = sgr("\x1b[38:2::1:2:3m");
+        assert_eq!(style.fg, Some(Color::Rgb(1, 2, 3)));
+    }
+
+    #[test]
+    fn sgr_handles_attributes_and_reset() {
+        let style = sgr("\x1b[1;3;4;7;9m");
+        assert!(style.bold && style.italic && style.underline && style.inverse && style.strikethrough);
+
+        let style = sgr("\x1b[1;31m\x1b[0m");
+        assert_eq!(style, TextStyle::default());
+
+        let style = sgr("\x1b[1;3m\x1b[22;23m");
+        assert!(!style.bold && !style.italic);
+    }
+
+    #[test]
+    fn palette_covers_cube_and_grayscale() {
+        assert_eq!(palette_rgb(16), (0, 0, 0));
+        assert_eq!(palette_rgb(231), (255, 255, 255));
+        assert_eq!(palette_rgb(232), (8, 8, 8));
+    }
+
+    #[test]
+    fn style_css_swaps_colors_for_inverse() {
+        let style = TextStyle {
+            fg: Some(Color::Rgb(1, 2, 3)),
+            inverse: true,
+            ..TextStyle::default()
+        };
+        let css = style_css(&style);
+        assert!(css.contains("background-color:#010203;"), "{css}");
+        assert!(css.contains("color:#050607;"), "{css}");
+    }
+}